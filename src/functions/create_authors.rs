@@ -1,13 +1,30 @@
 use std::collections::HashMap;
 
+use super::error::StatsError;
+use super::filters::*;
+use super::merge_aliases::merge_aliases;
+use super::progress::Stage;
 use super::structs::*;
 
-pub fn create_authors(messages: Vec<Message>) -> HashMap<u64, Author> {
+pub fn create_authors<'a>(
+    messages: impl IntoIterator<Item = &'a Message>,
+    bot_mode: BotMode,
+    stage: Stage,
+) -> Result<HashMap<u64, Author>, StatsError> {
     let mut authors: HashMap<u64, Author> = HashMap::new();
     // Create a "server author" to count everything
     let mut server_author: Author = Author::new(0);
 
     for message in messages {
+        // ID 0 is the server author, so a message claiming it
+        // would be counted into the server totals twice
+        if message.author_id == 0 {
+            return Err(StatsError::Aggregate(format!(
+                "The message sent by \"{}\" at {} in channel {} has author ID 0",
+                message.author_name, message.date, message.channel_id
+            )));
+        }
+
         // Get entry for author in question, or create a new author struct from ID
         let author = authors
             .entry(message.author_id)
@@ -16,11 +33,146 @@ pub fn create_authors(messages: Vec<Message>) -> HashMap<u64, Author> {
         // Update information
         *author = author.clone().process_message(message.clone());
 
-        // Process every message for the server as a server author
-        server_author = server_author.process_message(message);
+        // Process every message for the server as a server author,
+        // unless bots are being kept out of the server totals
+        if !message.is_bot || bot_mode == BotMode::Include {
+            server_author = server_author.process_message(message.clone());
+        }
+
+        stage.inc(1);
     }
 
     authors.insert(0, server_author);
 
-    return authors;
+    return Ok(authors);
+}
+
+// Merges authors counted up on their own, such as by
+// separate threads, into one map
+pub fn merge_author_parts(master: &mut HashMap<u64, Author>, part: HashMap<u64, Author>) {
+    for (key, value) in part {
+        let temp_author = match master.remove(&key) {
+            // If the author is already in the master map,
+            // merge it with the other part of the author
+            Some(existing) => existing.merge(value),
+            // Otherwise we can simply insert it
+            None => value,
+        };
+
+        master.insert(key, temp_author);
+    }
+}
+
+// Takes the server author, which counts every message,
+// out from among the real authors
+pub fn take_server_author(authors: &mut HashMap<u64, Author>) -> Result<Author, StatsError> {
+    return match authors.remove(&0) {
+        Some(server) => Ok(server),
+        None => Err(StatsError::Aggregate(String::from(
+            "No server totals were counted up",
+        ))),
+    };
+}
+
+// Authors by their ID
+pub type AuthorMap = HashMap<u64, Author>;
+
+// Turns the authors counted up from the messages into what
+// gets exported: alt accounts are folded into their main
+// account, and the server totals and, when they are kept
+// separate, the bots are taken out from among the members.
+// Returns the server, the members and the bots
+pub fn consolidate_authors(
+    mut authors: HashMap<u64, Author>,
+    alias_groups: &[Vec<u64>],
+    bot_mode: BotMode,
+) -> Result<(Author, AuthorMap, AuthorMap), StatsError> {
+    merge_aliases(&mut authors, alias_groups);
+
+    let server = take_server_author(&mut authors)?;
+
+    let mut bots: HashMap<u64, Author> = HashMap::new();
+
+    if bot_mode == BotMode::Separate {
+        let bot_ids: Vec<u64> = authors
+            .values()
+            .filter(|author| author.is_bot)
+            .map(|author| author.id)
+            .collect();
+
+        for id in bot_ids {
+            if let Some(bot) = authors.remove(&id) {
+                bots.insert(id, bot);
+            }
+        }
+    }
+
+    return Ok((server, authors, bots));
+}
+
+// Counts up every channel the same way as an author, keyed
+// by the channel ID, so channels can be ranked and graphed
+// just like members. Bots are left out whenever they are
+// left out of the server totals, so the two add up.
+pub fn create_channels<'a>(messages: impl IntoIterator<Item = &'a Message>, bot_mode: BotMode) -> HashMap<u64, Author> {
+    let mut channels: HashMap<u64, Author> = HashMap::new();
+
+    for message in messages {
+        if message.is_bot && bot_mode != BotMode::Include {
+            continue;
+        }
+
+        channels
+            .entry(message.channel_id)
+            .or_insert_with(|| Author::new(message.channel_id))
+            .record_message(message);
+    }
+
+    return channels;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::functions::progress::{LogMode, Reporter};
+    use chrono::NaiveDateTime;
+
+    fn message(author_id: u64, is_bot: bool) -> Message {
+        return Message {
+            author_id: author_id,
+            channel_id: 111,
+            author_name: format!("author{}", author_id),
+            date: NaiveDateTime::parse_from_str("2023-01-01 10:00", "%Y-%m-%d %H:%M").unwrap(),
+            content: String::from("hello"),
+            attachments: Vec::new(),
+            reactions: HashMap::new(),
+            is_bot: is_bot,
+        };
+    }
+
+    fn stage() -> Stage {
+        return Reporter::new(LogMode::Quiet).stage("Processing authors", "messages", 0);
+    }
+
+    #[test]
+    fn author_id_zero_is_refused() {
+        let messages = vec![message(222, false), message(0, false)];
+
+        let error = create_authors(&messages, BotMode::Include, stage()).err().unwrap();
+
+        assert_eq!(error.exit_code(), 5);
+        assert!(error.to_string().contains("author0"));
+    }
+
+    #[test]
+    fn channels_add_up_to_the_server() {
+        let messages = vec![message(222, false), message(333, false), message(444, true)];
+
+        for bot_mode in [BotMode::Include, BotMode::Separate] {
+            let authors = create_authors(&messages, bot_mode, stage()).unwrap();
+            let channels = create_channels(&messages, bot_mode);
+
+            assert_eq!(channels[&111].message_count, authors[&0].message_count);
+        }
+    }
 }
@@ -0,0 +1,417 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+
+use super::error::StatsError;
+use super::query::Table;
+use super::rankings::*;
+use super::snapshot::Snapshot;
+use super::structs::*;
+
+// Compares two snapshots, such as the end of last month and
+// the end of this month, returning each part of the report
+// as a titled table
+pub fn diff_snapshots(old: &Snapshot, new: &Snapshot) -> Vec<(String, Table)> {
+    let mut sections: Vec<(String, Table)> = Vec::new();
+
+    sections.push((
+        String::from("Server Changes"),
+        delta_table("Server", &single(&old.server), &single(&new.server), |_| String::from("Server")),
+    ));
+
+    sections.push((
+        String::from("Author Changes"),
+        delta_table("Author", &old.authors, &new.authors, |author| {
+            author.display_name().to_string()
+        }),
+    ));
+
+    sections.push((
+        String::from("Channel Changes"),
+        delta_table("Channel", &old.channels, &new.channels, |channel| {
+            match new.channel_info.get(&channel.id).or(old.channel_info.get(&channel.id)) {
+                Some(info) => info.name.clone(),
+                None => channel.id.to_string(),
+            }
+        }),
+    ));
+
+    sections.push((String::from("Member Changes"), member_table(old, new)));
+    sections.push((String::from("Rank Changes"), rank_table(old, new)));
+
+    return sections;
+}
+
+// Two snapshots only compare like for like when they were
+// counted up the same way. Bots being treated differently or
+// different alt accounts being merged would show up as changes
+// that never happened, so those are refused. Returns warnings
+// for anything that can't be checked.
+pub fn check_comparable(old: &Snapshot, new: &Snapshot) -> Result<Vec<String>, StatsError> {
+    if old.bot_mode != new.bot_mode {
+        return Err(StatsError::Argument(format!(
+            "The snapshots treat bots differently ({:?} and {:?}), so they can't be compared",
+            old.bot_mode, new.bot_mode
+        )));
+    }
+
+    let mut warnings: Vec<String> = Vec::new();
+
+    match (&old.alias_groups, &new.alias_groups) {
+        (Some(old_groups), Some(new_groups)) => {
+            if normalize_groups(old_groups) != normalize_groups(new_groups) {
+                return Err(StatsError::Argument(String::from(
+                    "The snapshots merge different alt accounts, so they can't be compared",
+                )));
+            }
+        }
+        _ => warnings.push(String::from(
+            "A snapshot was saved before aliases were kept in snapshots, so they may merge different alt accounts",
+        )),
+    }
+
+    return Ok(warnings);
+}
+
+// Alias groups in the same order, with the alt accounts of
+// each one in order behind its main account
+fn normalize_groups(groups: &[Vec<u64>]) -> Vec<Vec<u64>> {
+    let mut normalized: Vec<Vec<u64>> = groups
+        .iter()
+        .filter(|group| !group.is_empty())
+        .map(|group| {
+            let mut alts = group[1..].to_vec();
+            alts.sort();
+
+            let mut group = vec![group[0]];
+            group.extend(alts);
+            group
+        })
+        .collect();
+    normalized.sort();
+
+    return normalized;
+}
+
+fn single(author: &Author) -> HashMap<u64, Author> {
+    let mut map = HashMap::new();
+    map.insert(author.id, author.clone());
+
+    return map;
+}
+
+// The value in the later snapshot, and the change since the
+// earlier one as a number and a percentage, of the messages,
+// words, attachments and vocabulary of everyone in either
+// snapshot, biggest change in messages first
+fn delta_table(
+    label: &str,
+    old: &HashMap<u64, Author>,
+    new: &HashMap<u64, Author>,
+    name: impl Fn(&Author) -> String,
+) -> Table {
+    let ids: HashSet<&u64> = old.keys().chain(new.keys()).collect();
+
+    let mut rows: Vec<(i128, Vec<String>)> = Vec::new();
+
+    for id in ids {
+        let before = old.get(id);
+        let after = new.get(id);
+
+        let mut row = vec![id.to_string(), name(after.or(before).unwrap())];
+
+        for (_, metric) in [RANKINGS[0], RANKINGS[1], RANKINGS[3], RANKINGS[5]] {
+            let before_value = before.map(metric).unwrap_or(0) as i128;
+            let after_value = after.map(metric).unwrap_or(0) as i128;
+
+            row.push(after_value.to_string());
+            row.push((after_value - before_value).to_string());
+            row.push(percent_change(before_value, after_value));
+        }
+
+        let message_change = after.map(|a| a.message_count).unwrap_or(0) as i128
+            - before.map(|a| a.message_count).unwrap_or(0) as i128;
+
+        rows.push((message_change, row));
+    }
+
+    rows.sort_by(|a, b| b.0.cmp(&a.0).then(a.1[1].cmp(&b.1[1])));
+
+    return Table {
+        headers: vec![
+            String::from("ID"),
+            String::from(label),
+            String::from("Messages"),
+            String::from("Messages Change"),
+            String::from("Messages Change %"),
+            String::from("Words"),
+            String::from("Words Change"),
+            String::from("Words Change %"),
+            String::from("Attachments"),
+            String::from("Attachments Change"),
+            String::from("Attachments Change %"),
+            String::from("Vocabulary"),
+            String::from("Vocabulary Change"),
+            String::from("Vocabulary Change %"),
+        ],
+        numeric: vec![false, false, true, true, true, true, true, true, true, true, true, true, true, true],
+        rows: rows.into_iter().map(|(_, row)| row).collect(),
+    };
+}
+
+// The change as a percentage of the earlier value. Growing
+// from nothing has no percentage, so is shown as "-".
+fn percent_change(before: i128, after: i128) -> String {
+    if before == 0 {
+        if after == 0 {
+            return String::from("+0.0%");
+        }
+        return String::from("-");
+    }
+
+    return format!("{:+.1}%", (after - before) as f64 * 100.0 / before as f64);
+}
+
+// Members who are new in the later snapshot, who are gone
+// from it, and who are still there but haven't sent anything
+// since the earlier one
+fn member_table(old: &Snapshot, new: &Snapshot) -> Table {
+    let mut rows: Vec<Vec<String>> = Vec::new();
+
+    let mut push = |status: &str, author: &Author| {
+        rows.push(vec![
+            String::from(status),
+            author.id.to_string(),
+            author.display_name().to_string(),
+            author.message_count.to_string(),
+        ]);
+    };
+
+    let mut new_members: Vec<&Author> = new.authors.values().filter(|a| !old.authors.contains_key(&a.id)).collect();
+    new_members.sort_by_key(|author| Reverse(author.message_count));
+
+    for author in new_members {
+        push("New", author);
+    }
+
+    let mut departed: Vec<&Author> = old.authors.values().filter(|a| !new.authors.contains_key(&a.id)).collect();
+    departed.sort_by_key(|author| Reverse(author.message_count));
+
+    for author in departed {
+        push("Departed", author);
+    }
+
+    let mut silent: Vec<&Author> = new
+        .authors
+        .values()
+        .filter(|a| match old.authors.get(&a.id) {
+            Some(before) => before.message_count == a.message_count,
+            None => false,
+        })
+        .collect();
+    silent.sort_by_key(|author| Reverse(author.message_count));
+
+    for author in silent {
+        push("Silent", author);
+    }
+
+    return Table {
+        headers: vec![
+            String::from("Status"),
+            String::from("ID"),
+            String::from("Author"),
+            String::from("Messages"),
+        ],
+        numeric: vec![false, false, false, true],
+        rows: rows,
+    };
+}
+
+// Everyone whose place on a leaderboard has changed, with
+// how many places they moved up (or down, if negative)
+fn rank_table(old: &Snapshot, new: &Snapshot) -> Table {
+    let mut rows: Vec<Vec<String>> = Vec::new();
+
+    for (board, metric) in RANKINGS {
+        let old_ranks = ranks(&old.authors, metric);
+        let new_ranks = ranks(&new.authors, metric);
+
+        let mut moved: Vec<(&u64, &usize)> = new_ranks
+            .iter()
+            .filter(|(id, rank)| old_ranks.get(id) != Some(rank))
+            .collect();
+        moved.sort_by(|a, b| a.1.cmp(b.1));
+
+        for (id, new_rank) in moved {
+            let (old_rank, movement) = match old_ranks.get(id) {
+                Some(old_rank) => (old_rank.to_string(), (*old_rank as i64 - *new_rank as i64).to_string()),
+                None => (String::from("-"), String::from("new")),
+            };
+
+            rows.push(vec![
+                ranking_title(board),
+                new.authors[id].display_name().to_string(),
+                old_rank,
+                new_rank.to_string(),
+                movement,
+            ]);
+        }
+    }
+
+    return Table {
+        headers: vec![
+            String::from("Leaderboard"),
+            String::from("Author"),
+            String::from("Old Rank"),
+            String::from("New Rank"),
+            String::from("Places Moved"),
+        ],
+        numeric: vec![false, false, true, true, true],
+        rows: rows,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::functions::filters::{BotMode, DateRange};
+    use chrono::NaiveDateTime;
+
+    fn snapshot(bot_mode: BotMode, alias_groups: Option<Vec<Vec<u64>>>) -> Snapshot {
+        return Snapshot {
+            version: 3,
+            created: NaiveDateTime::parse_from_str("2023-01-01 10:00", "%Y-%m-%d %H:%M").unwrap(),
+            date_range: DateRange::all(),
+            bot_mode: bot_mode,
+            alias_groups: alias_groups,
+            server: Author::new(0),
+            authors: HashMap::new(),
+            bots: HashMap::new(),
+            channels: HashMap::new(),
+            channel_info: HashMap::new(),
+            excluded_channels: Vec::new(),
+        };
+    }
+
+    #[test]
+    fn snapshots_counted_up_the_same_way_compare() {
+        let old = snapshot(BotMode::Separate, Some(vec![vec![222, 333, 444], vec![555, 666]]));
+        let new = snapshot(BotMode::Separate, Some(vec![vec![555, 666], vec![222, 444, 333]]));
+
+        assert!(check_comparable(&old, &new).unwrap().is_empty());
+    }
+
+    #[test]
+    fn different_bot_modes_are_refused() {
+        let old = snapshot(BotMode::Include, Some(Vec::new()));
+        let new = snapshot(BotMode::Separate, Some(Vec::new()));
+
+        assert_eq!(check_comparable(&old, &new).err().unwrap().exit_code(), 2);
+    }
+
+    #[test]
+    fn different_aliases_are_refused() {
+        let old = snapshot(BotMode::Include, Some(vec![vec![222, 333]]));
+        let new = snapshot(BotMode::Include, Some(vec![vec![333, 222]]));
+
+        assert!(check_comparable(&old, &new).is_err());
+        assert!(check_comparable(&old, &snapshot(BotMode::Include, Some(Vec::new()))).is_err());
+    }
+
+    #[test]
+    fn snapshots_without_aliases_are_warned_about() {
+        let old = snapshot(BotMode::Include, None);
+        let new = snapshot(BotMode::Include, Some(Vec::new()));
+
+        assert_eq!(check_comparable(&old, &new).unwrap().len(), 1);
+    }
+
+    fn author(id: u64, messages: u128) -> Author {
+        let date = NaiveDateTime::parse_from_str("2023-01-01 10:00", "%Y-%m-%d %H:%M").unwrap();
+
+        let mut author = Author::new(id);
+        author.names.push(NameRecord::new(format!("author{}", id), date));
+        author.message_count = messages;
+        author.word_count = messages * 2;
+
+        return author;
+    }
+
+    fn with_authors(authors: Vec<Author>) -> Snapshot {
+        let mut snapshot = snapshot(BotMode::Include, Some(Vec::new()));
+        snapshot.authors = authors.into_iter().map(|author| (author.id, author)).collect();
+
+        return snapshot;
+    }
+
+    // Before: 222 on 10 messages, 333 and 444 tied on 5 and
+    // 666 on 3. After: 444 has overtaken everyone, 666 has
+    // gone and 555 has joined.
+    fn before_and_after() -> (Snapshot, Snapshot) {
+        let old = with_authors(vec![author(222, 10), author(333, 5), author(444, 5), author(666, 3)]);
+        let new = with_authors(vec![author(222, 10), author(333, 5), author(444, 12), author(555, 1)]);
+
+        return (old, new);
+    }
+
+    fn row<'a>(table: &'a Table, column: usize, value: &str) -> &'a Vec<String> {
+        return table.rows.iter().find(|row| row[column] == value).unwrap();
+    }
+
+    #[test]
+    fn deltas_are_given_as_numbers_and_percentages() {
+        let (old, new) = before_and_after();
+
+        let table = delta_table("Author", &old.authors, &new.authors, |a| a.display_name().to_string());
+
+        assert_eq!(table.headers.len(), table.rows[0].len());
+        assert_eq!(row(&table, 0, "444")[2..8], ["12", "7", "+140.0%", "24", "14", "+140.0%"]);
+        assert_eq!(row(&table, 0, "222")[2..5], ["10", "0", "+0.0%"]);
+        assert_eq!(row(&table, 0, "666")[2..5], ["0", "-3", "-100.0%"]);
+
+        // Nothing to take a percentage of
+        assert_eq!(row(&table, 0, "555")[2..5], ["1", "1", "-"]);
+        assert_eq!(row(&table, 0, "555")[8..11], ["0", "0", "+0.0%"]);
+
+        // Biggest change in messages first
+        let ids: Vec<&str> = table.rows.iter().map(|row| row[0].as_str()).collect();
+        assert_eq!(ids, vec!["444", "555", "222", "333", "666"]);
+    }
+
+    #[test]
+    fn members_are_new_departed_or_silent() {
+        let (old, new) = before_and_after();
+
+        let table = member_table(&old, &new);
+
+        let statuses: Vec<(&str, &str)> = table.rows.iter().map(|row| (row[0].as_str(), row[1].as_str())).collect();
+        assert_eq!(
+            statuses,
+            vec![("New", "555"), ("Departed", "666"), ("Silent", "222"), ("Silent", "333")]
+        );
+    }
+
+    #[test]
+    fn rank_movements_count_places_with_ties_going_to_the_lower_id() {
+        let (old, new) = before_and_after();
+
+        let table = rank_table(&old, &new);
+
+        let moved: Vec<Vec<&str>> = table
+            .rows
+            .iter()
+            .filter(|row| row[0] == "Messages")
+            .map(|row| row[1..].iter().map(|cell| cell.as_str()).collect())
+            .collect();
+
+        // 333 and 444 were tied, so 333 held 2nd place
+        assert_eq!(
+            moved,
+            vec![
+                vec!["author444", "3", "1", "2"],
+                vec!["author222", "1", "2", "-1"],
+                vec!["author333", "2", "3", "-1"],
+                vec!["author555", "-", "4", "new"],
+            ]
+        );
+    }
+}
@@ -0,0 +1,58 @@
+use std::error::Error;
+use std::fmt;
+
+// Everything that can go wrong on the way from the command
+// line to the exported statistics. Each kind of failure
+// exits with its own code, so scripts can tell them apart:
+//
+// 2   the arguments could not be understood
+// 3   a file or folder could not be read or written
+// 4   an export file could not be parsed
+// 5   the messages could not be counted up
+// 6   the statistics could not be exported
+// 7   a settings file, such as the aliases, doesn't make sense
+#[derive(Debug)]
+pub enum StatsError {
+    Argument(String),
+    Io(String),
+    Parse(String),
+    Aggregate(String),
+    Export(String),
+    Config(String),
+}
+
+impl StatsError {
+    pub fn exit_code(&self) -> i32 {
+        return match self {
+            StatsError::Argument(_) => 2,
+            StatsError::Io(_) => 3,
+            StatsError::Parse(_) => 4,
+            StatsError::Aggregate(_) => 5,
+            StatsError::Export(_) => 6,
+            StatsError::Config(_) => 7,
+        };
+    }
+}
+
+impl fmt::Display for StatsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            StatsError::Argument(message) => write!(f, "Error in arguments! {}", message),
+            StatsError::Io(message) => write!(f, "Error reading or writing files! {}", message),
+            StatsError::Parse(message) => write!(f, "Error parsing export! {}", message),
+            StatsError::Aggregate(message) => write!(f, "Error processing messages! {}", message),
+            StatsError::Export(message) => write!(f, "Error exporting statistics! {}", message),
+            StatsError::Config(message) => write!(f, "Error in settings! {}", message),
+        };
+    }
+}
+
+impl Error for StatsError {}
+
+// The csv writer is only ever used for the exported
+// statistics, as imports report the file they failed on
+impl From<csv::Error> for StatsError {
+    fn from(error: csv::Error) -> Self {
+        return StatsError::Export(error.to_string());
+    }
+}
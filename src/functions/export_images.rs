@@ -0,0 +1,275 @@
+use chrono::{Duration, NaiveDate, Timelike};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use plotters::style::{register_font, FontStyle};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::sync::OnceLock;
+
+use super::error::StatsError;
+use super::export_stats::ExportDirs;
+use super::progress::Stage;
+use super::structs::*;
+
+// Fonts differ from machine to machine, and may not be there
+// at all, so the charts are always drawn with DejaVu Sans,
+// under the license in fonts/LICENSE-DejaVu.txt
+pub const FONT: &[u8] = include_bytes!("fonts/DejaVuSans.ttf");
+
+const SIZE: (u32, u32) = (1280, 720);
+
+// The kinds of image the charts can be drawn as
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    Svg,
+    Png,
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        return match self {
+            ImageFormat::Svg => "svg",
+            ImageFormat::Png => "png",
+        };
+    }
+}
+
+// What a chart shows, so it can be drawn onto either backend
+enum Chart {
+    // Messages sent in each minute of the day, one line
+    // for each name given
+    TimeMap(Vec<(String, Vec<u32>)>),
+    // Messages sent in each week, from the first message on
+    Timeline(Vec<(NaiveDate, u32)>),
+}
+
+// Draws the same charts as the html graphs, as images that
+// need no browser to view. Author time maps are written into
+// the Graphs folder and the server charts into the main one,
+// whether or not the html graphs are written too.
+pub fn export_images(
+    formats: &[ImageFormat],
+    dirs: &ExportDirs,
+    server: &Author,
+    author_hashmap: &HashMap<u64, Author>,
+    channel_id_dict: &HashMap<u64, Channel>,
+    only_authors: Option<&HashSet<u64>>,
+    stage: &Stage,
+) -> Result<(), StatsError> {
+    static FONT_REGISTERED: OnceLock<bool> = OnceLock::new();
+
+    if !*FONT_REGISTERED.get_or_init(|| register_font("sans-serif", FontStyle::Normal, FONT).is_ok()) {
+        return Err(StatsError::Export(String::from("Could not load the font for drawing charts")));
+    }
+
+    for (id, author) in author_hashmap {
+        if let Some(only_authors) = only_authors {
+            if !only_authors.contains(id) {
+                continue;
+            }
+        }
+
+        let title = format!("Time Map for {}", author.display_name());
+        let chart = Chart::TimeMap(vec![(String::new(), minute_counts(author, None))]);
+
+        if let Err(error) = save_chart(&chart, &title, &dirs.graphs, "timemap", formats) {
+            stage.warn(&format!(
+                "Could not draw chart for {}! {}",
+                author.display_name(),
+                error
+            ));
+        }
+    }
+
+    let mut channels: Vec<&Channel> = channel_id_dict.values().collect();
+    channels.sort_by(|a, b| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+
+    let server_charts = [
+        ("Server Timeline Graph", "timeline", Chart::Timeline(weekly_counts(server))),
+        (
+            "Server Time Graph",
+            "timemap",
+            Chart::TimeMap(vec![(String::new(), minute_counts(server, None))]),
+        ),
+        (
+            "Channel Time Graph",
+            "timemap",
+            Chart::TimeMap(
+                channels
+                    .iter()
+                    .map(|channel| (channel.name.clone(), minute_counts(server, Some(channel.id))))
+                    .collect(),
+            ),
+        ),
+    ];
+
+    for (title, kind, chart) in &server_charts {
+        if let Err(error) = save_chart(chart, title, &dirs.main, kind, formats) {
+            stage.warn(&format!("Could not draw {}! {}", title, error));
+        }
+    }
+
+    Ok(())
+}
+
+// Counts the messages sent in each minute of the day,
+// optionally only in one channel
+fn minute_counts(author: &Author, channel_id: Option<u64>) -> Vec<u32> {
+    let mut counts = vec![0u32; 24 * 60];
+
+    for (point, id) in &author.time_ledger {
+        if channel_id.is_none() || channel_id == Some(*id) {
+            counts[(point.hour() * 60 + point.minute()) as usize] += 1;
+        }
+    }
+
+    return counts;
+}
+
+// Counts the messages sent in each week, starting from the
+// day of the first message
+fn weekly_counts(author: &Author) -> Vec<(NaiveDate, u32)> {
+    let start = match author.time_ledger.iter().map(|(date, _)| date.date()).min() {
+        Some(start) => start,
+        None => return Vec::new(),
+    };
+
+    let mut counts: Vec<(NaiveDate, u32)> = Vec::new();
+
+    for (point, _) in &author.time_ledger {
+        let week = ((point.date() - start).num_days() / 7) as usize;
+
+        while counts.len() <= week {
+            counts.push((start + Duration::days(7 * counts.len() as i64), 0));
+        }
+
+        counts[week].1 += 1;
+    }
+
+    return counts;
+}
+
+fn save_chart(
+    chart: &Chart,
+    title: &str,
+    directory: &str,
+    kind: &str,
+    formats: &[ImageFormat],
+) -> Result<(), Box<dyn Error>> {
+    for format in formats {
+        let output_path = format!(
+            "{}{}-{}.{}",
+            directory,
+            sanitize_filename::sanitize(title),
+            kind,
+            format.extension()
+        );
+
+        match format {
+            ImageFormat::Svg => draw_chart(chart, title, SVGBackend::new(&output_path, SIZE).into_drawing_area())?,
+            ImageFormat::Png => draw_chart(chart, title, BitMapBackend::new(&output_path, SIZE).into_drawing_area())?,
+        }
+    }
+
+    Ok(())
+}
+
+fn draw_chart<DB: DrawingBackend>(chart: &Chart, title: &str, root: DrawingArea<DB, Shift>) -> Result<(), Box<dyn Error>>
+where
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE)?;
+
+    match chart {
+        Chart::TimeMap(lines) => draw_time_map(&root, title, lines)?,
+        Chart::Timeline(weeks) => draw_timeline(&root, title, weeks)?,
+    }
+
+    root.present()?;
+
+    Ok(())
+}
+
+fn draw_time_map<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    title: &str,
+    lines: &[(String, Vec<u32>)],
+) -> Result<(), DrawingAreaErrorKind<DB::ErrorType>> {
+    let highest = lines.iter().flat_map(|(_, counts)| counts.iter()).cloned().max().unwrap_or(0);
+
+    // A label every two hours
+    let hours: Vec<u32> = (0..=24).step_by(2).map(|hour| hour * 60).collect();
+
+    let mut chart = ChartBuilder::on(root)
+        .caption(title, ("sans-serif", 28))
+        .margin(20)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d((0u32..1440u32).with_key_points(hours), 0u32..(highest + highest / 10 + 1))?;
+
+    chart
+        .configure_mesh()
+        .x_label_formatter(&|minute| format!("{:02}:{:02}", minute / 60, minute % 60))
+        .x_desc("Time of day")
+        .y_desc("Messages")
+        .draw()?;
+
+    for (index, (name, counts)) in lines.iter().enumerate() {
+        let colour = Palette99::pick(index).to_rgba();
+        let points = counts.iter().enumerate().map(|(minute, count)| (minute as u32, *count));
+
+        let series = chart.draw_series(LineSeries::new(points, colour.stroke_width(1)))?;
+
+        if !name.is_empty() {
+            series
+                .label(name.as_str())
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], colour.stroke_width(2)));
+        }
+    }
+
+    if lines.iter().any(|(name, _)| !name.is_empty()) {
+        chart
+            .configure_series_labels()
+            .position(SeriesLabelPosition::UpperLeft)
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+    }
+
+    Ok(())
+}
+
+fn draw_timeline<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    title: &str,
+    weeks: &[(NaiveDate, u32)],
+) -> Result<(), DrawingAreaErrorKind<DB::ErrorType>> {
+    // With no messages there is nothing to put on the axes
+    let (first, last) = match (weeks.first(), weeks.last()) {
+        (Some(first), Some(last)) => (first.0, last.0 + Duration::days(7)),
+        _ => {
+            root.titled(title, ("sans-serif", 28))?;
+            return Ok(());
+        }
+    };
+
+    let highest = weeks.iter().map(|(_, count)| *count).max().unwrap_or(0);
+
+    let mut chart = ChartBuilder::on(root)
+        .caption(title, ("sans-serif", 28))
+        .margin(20)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(first..last, 0u32..(highest + highest / 10 + 1))?;
+
+    chart
+        .configure_mesh()
+        .x_label_formatter(&|date| date.format("%b %Y").to_string())
+        .x_desc("Week")
+        .y_desc("Messages")
+        .draw()?;
+
+    chart.draw_series(LineSeries::new(weeks.iter().cloned(), Palette99::pick(0).stroke_width(2)))?;
+
+    Ok(())
+}
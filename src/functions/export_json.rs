@@ -0,0 +1,311 @@
+use chrono::{Local, NaiveDateTime};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+
+use super::error::StatsError;
+use super::filters::DateRange;
+use super::rankings::*;
+use super::structs::*;
+
+// Bumped whenever a field is removed or changes meaning.
+// The layout is described in docs/statistics.schema.json
+pub const STATISTICS_SCHEMA_VERSION: u32 = 1;
+
+// Writes everything in Server Statistics.csv and the author
+// csvs as a single JSON document, "Statistics.json", laid out
+// so scripts can read it without scraping the csv sections
+pub fn export_json(
+    path: &String,
+    server: &Author,
+    author_hashmap: &HashMap<u64, Author>,
+    bot_hashmap: &HashMap<u64, Author>,
+    channel_id_dict: &HashMap<u64, Channel>,
+    excluded_channels: &[Channel],
+    date_range: DateRange,
+) -> Result<(), StatsError> {
+    let statistics = statistics_json(
+        server,
+        author_hashmap,
+        bot_hashmap,
+        channel_id_dict,
+        excluded_channels,
+        date_range,
+    );
+
+    let path_to_export = format!("{}Statistics.json", path);
+
+    let text = match serde_json::to_string_pretty(&statistics) {
+        Ok(text) => text,
+        Err(error) => return Err(StatsError::Export(error.to_string())),
+    };
+
+    if let Err(error) = fs::write(&path_to_export, text) {
+        return Err(StatsError::Io(format!("Could not write {}! {}", path_to_export, error)));
+    }
+
+    Ok(())
+}
+
+// The contents of Statistics.json. IDs are written as
+// strings, as they are too large for JavaScript numbers.
+pub fn statistics_json(
+    server: &Author,
+    author_hashmap: &HashMap<u64, Author>,
+    bot_hashmap: &HashMap<u64, Author>,
+    channel_id_dict: &HashMap<u64, Channel>,
+    excluded_channels: &[Channel],
+    date_range: DateRange,
+) -> Value {
+    let server_words = sorted_words(server);
+    let server_top_50: Vec<&String> = server_words.iter().take(50).map(|(word, _)| *word).collect();
+
+    let channel_counts = server.messages_per_channel();
+
+    let mut channels: Vec<&Channel> = channel_id_dict.values().collect();
+    channels.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut channel_values: Vec<Value> = channels
+        .iter()
+        .map(|channel| {
+            json!({
+                "id": channel.id.to_string(),
+                "name": channel.name,
+                "category": channel.category,
+                "guild": channel.guild,
+                "included": true,
+                "messages": channel_counts.get(&channel.id).cloned().unwrap_or(0),
+            })
+        })
+        .collect();
+
+    for channel in excluded_channels {
+        channel_values.push(json!({
+            "id": channel.id.to_string(),
+            "name": channel.name,
+            "category": channel.category,
+            "guild": channel.guild,
+            "included": false,
+            "messages": 0,
+        }));
+    }
+
+    let mut rankings = serde_json::Map::new();
+    for (name, metric) in RANKINGS {
+        let ranking: Vec<Value> = ranked(author_hashmap, metric)
+            .iter()
+            .enumerate()
+            .map(|(index, author)| {
+                json!({
+                    "rank": index + 1,
+                    "id": author.id.to_string(),
+                    "name": author.display_name(),
+                    "value": metric(author) as u64,
+                })
+            })
+            .collect();
+
+        rankings.insert(String::from(name), Value::from(ranking));
+    }
+
+    let top_words: Vec<Value> = server_words
+        .iter()
+        .take(1000)
+        .enumerate()
+        .map(|(index, (word, count))| json!({ "rank": index + 1, "word": word, "count": **count as u64 }))
+        .collect();
+
+    let authors: Vec<Value> = ranked(author_hashmap, RANKINGS[0].1)
+        .iter()
+        .map(|author| author_json(author, &server_top_50))
+        .collect();
+
+    let bots: Vec<Value> = ranked(bot_hashmap, RANKINGS[0].1)
+        .iter()
+        .map(|author| author_json(author, &server_top_50))
+        .collect();
+
+    return json!({
+        "schema_version": STATISTICS_SCHEMA_VERSION,
+        "generated": Local::now().naive_local().format("%Y-%m-%dT%H:%M:%S").to_string(),
+        "date_range": {
+            "start": date_value(date_range.start),
+            "end": date_value(date_range.end),
+        },
+        "server": stats_json(server),
+        "channels": channel_values,
+        "rankings": rankings,
+        "top_words": top_words,
+        "authors": authors,
+        "bots": bots,
+    });
+}
+
+pub fn date_value(date: Option<NaiveDateTime>) -> Value {
+    return match date {
+        Some(date) => Value::from(date.format("%Y-%m-%dT%H:%M:%S").to_string()),
+        None => Value::Null,
+    };
+}
+
+// The totals of an author, as given for the server and each
+// author in Statistics.json and by the dashboard
+pub fn stats_json(author: &Author) -> Value {
+    let (first, last) = author.first_and_last();
+
+    return json!({
+        "messages": author.message_count as u64,
+        "words": author.word_count as u64,
+        "characters": author.character_count as u64,
+        "attachments": author.attachments_ledger.len(),
+        "questions": author.question_count as u64,
+        "vocabulary": author.vocab_dict.len(),
+        "first_message": date_value(first),
+        "last_message": date_value(last),
+    });
+}
+
+fn author_json(author: &Author, server_top_50: &[&String]) -> Value {
+    let words = sorted_words(author);
+
+    let word_list = |words: Vec<&(&String, &u128)>| -> Vec<Value> {
+        return words
+            .iter()
+            .map(|(word, count)| json!({ "word": word, "count": **count as u64 }))
+            .collect();
+    };
+
+    let mut channels: Vec<(u64, u64)> = author.messages_per_channel().into_iter().collect();
+    channels.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    return json!({
+        "id": author.id.to_string(),
+        "name": author.display_name(),
+        "names": author.name_history().iter().map(|record| json!({
+            "name": record.name,
+            "first_seen": date_value(Some(record.first_seen)),
+            "last_seen": date_value(Some(record.last_seen)),
+        })).collect::<Vec<Value>>(),
+        "merged_ids": author.merged_ids.iter().map(|id| id.to_string()).collect::<Vec<String>>(),
+        "is_bot": author.is_bot,
+        "stats": stats_json(author),
+        "top_words": word_list(words.iter().take(50).collect()),
+        "distinctive_words": word_list(
+            words
+                .iter()
+                .filter(|(word, _)| !server_top_50.contains(word))
+                .take(50)
+                .collect()
+        ),
+        "channels": channels.iter().map(|(id, count)| json!({
+            "id": id.to_string(),
+            "messages": count,
+        })).collect::<Vec<Value>>(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = include_str!("../../docs/statistics.schema.json");
+
+    fn message(author_id: u64, author_name: &str, content: &str) -> Message {
+        return Message {
+            author_id: author_id,
+            channel_id: 111,
+            author_name: String::from(author_name),
+            date: NaiveDateTime::parse_from_str("2021-03-01 10:00", "%Y-%m-%d %H:%M").unwrap(),
+            content: String::from(content),
+            attachments: Vec::new(),
+            reactions: HashMap::new(),
+            is_bot: false,
+        };
+    }
+
+    // Walks the value alongside the schema, following "$ref"s
+    // into "$defs", and collects every required field missing
+    fn missing_fields(schema: &Value, root: &Value, value: &Value, path: &str, missing: &mut Vec<String>) {
+        if let Some(reference) = schema.get("$ref").and_then(|r| r.as_str()) {
+            let name = reference.trim_start_matches("#/$defs/");
+            return missing_fields(&root["$defs"][name], root, value, path, missing);
+        }
+
+        if let Some(required) = schema.get("required").and_then(|r| r.as_array()) {
+            for field in required {
+                let field = field.as_str().unwrap();
+                if value.get(field).is_none() {
+                    missing.push(format!("{}/{}", path, field));
+                }
+            }
+        }
+
+        if let Some(properties) = schema.get("properties").and_then(|p| p.as_object()) {
+            for (name, property) in properties {
+                if let Some(inner) = value.get(name) {
+                    missing_fields(property, root, inner, &format!("{}/{}", path, name), missing);
+                }
+            }
+        }
+
+        if let (Some(items), Some(values)) = (schema.get("items"), value.as_array()) {
+            for (index, inner) in values.iter().enumerate() {
+                missing_fields(items, root, inner, &format!("{}[{}]", path, index), missing);
+            }
+        }
+    }
+
+    fn statistics() -> Value {
+        let mut server = Author::new(0);
+        let mut authors: HashMap<u64, Author> = HashMap::new();
+
+        for msg in [
+            message(333, "bob#5678", "hello there"),
+            message(222, "alice#1234", "hello again?"),
+            message(444, "carol#9999", "hi"),
+            message(444, "carol#9999", "hi hi"),
+        ] {
+            server = server.process_message(msg.clone());
+            let author = authors.remove(&msg.author_id).unwrap_or(Author::new(msg.author_id));
+            authors.insert(msg.author_id, author.process_message(msg));
+        }
+
+        let channels: HashMap<u64, Channel> = HashMap::from([(
+            111,
+            Channel {
+                id: 111,
+                name: String::from("general"),
+                category: String::from("Text Channels"),
+                guild: String::from("Guild"),
+            },
+        )]);
+
+        return statistics_json(&server, &authors, &HashMap::new(), &channels, &[], DateRange::all());
+    }
+
+    #[test]
+    fn every_required_field_is_written() {
+        let schema: Value = serde_json::from_str(SCHEMA).unwrap();
+        let statistics = statistics();
+
+        let mut missing: Vec<String> = Vec::new();
+        missing_fields(&schema, &schema, &statistics, "", &mut missing);
+
+        assert!(missing.is_empty(), "missing {:?}", missing);
+        assert_eq!(statistics["schema_version"], Value::from(STATISTICS_SCHEMA_VERSION));
+    }
+
+    #[test]
+    fn tied_rankings_are_in_id_order() {
+        let statistics = statistics();
+
+        let ids: Vec<&str> = statistics["rankings"]["messages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["id"].as_str().unwrap())
+            .collect();
+
+        assert_eq!(ids, vec!["444", "222", "333"]);
+    }
+}
@@ -0,0 +1,600 @@
+use chrono::{NaiveDateTime, Timelike};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+
+use super::error::StatsError;
+use super::filters::DateRange;
+use super::query::tokenize;
+use super::rankings::*;
+use super::snapshot::Snapshot;
+use super::structs::*;
+use super::text::thousands;
+
+pub const SUMMARY_HELP: &str = "\
+Terms (all optional):
+  sections=S1,S2..      which sections to write, in order, out of overview, posters, words,
+                        channels, hours and changes (default all of them but changes, which
+                        is added when compare is given)
+  top=N                 how many entries each list has (default 10)
+  limit=N               longest a single message may be (default 2000, Discord's limit)
+  compare=SNAPSHOT      a snapshot saved with --save-snapshot, such as last month's, to list
+                        the notable changes since
+";
+
+// The parts of the summary, each written as one block
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SummarySection {
+    // The dates covered and the server totals
+    Overview,
+    // Who sent the most messages
+    Posters,
+    // The most used words
+    Words,
+    // Which channels were busiest
+    Channels,
+    // Which hours of the day were busiest
+    Hours,
+    // New members, people who went quiet and who moved up
+    // the rankings, compared with an earlier snapshot
+    Changes,
+}
+
+impl SummarySection {
+    pub fn parse(value: &str) -> Option<SummarySection> {
+        return match value.trim().to_lowercase().as_str() {
+            "overview" => Some(SummarySection::Overview),
+            "posters" => Some(SummarySection::Posters),
+            "words" => Some(SummarySection::Words),
+            "channels" => Some(SummarySection::Channels),
+            "hours" => Some(SummarySection::Hours),
+            "changes" => Some(SummarySection::Changes),
+            _ => None,
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct SummaryConfig {
+    pub sections: Vec<SummarySection>,
+    pub top: usize,
+    pub limit: usize,
+    pub compare: Option<String>,
+}
+
+impl SummaryConfig {
+    pub fn new() -> SummaryConfig {
+        return SummaryConfig {
+            sections: vec![
+                SummarySection::Overview,
+                SummarySection::Posters,
+                SummarySection::Words,
+                SummarySection::Channels,
+                SummarySection::Hours,
+            ],
+            top: 10,
+            limit: 2000,
+            compare: None,
+        };
+    }
+
+    pub fn parse(text: &str) -> Result<SummaryConfig, String> {
+        return SummaryConfig::from_terms(&tokenize(text));
+    }
+
+    pub fn from_terms(terms: &[String]) -> Result<SummaryConfig, String> {
+        let mut config = SummaryConfig::new();
+        let mut sections_given = false;
+
+        for term in terms {
+            let (key, value) = match term.find('=') {
+                Some(index) => (&term[..index], &term[index + 1..]),
+                None => return Err(format!("Expected KEY=VALUE, found \"{}\"", term)),
+            };
+
+            match key.to_lowercase().as_str() {
+                "sections" => {
+                    config.sections.clear();
+                    sections_given = true;
+
+                    for section in value.split(',') {
+                        match SummarySection::parse(section) {
+                            Some(section) => config.sections.push(section),
+                            None => return Err(format!("Unknown section \"{}\"", section)),
+                        }
+                    }
+                }
+                "top" => config.top = parse_number(value)?,
+                "limit" => config.limit = parse_number(value)?,
+                "compare" => config.compare = Some(String::from(value)),
+                _ => return Err(format!("Unknown term \"{}\"", key)),
+            }
+        }
+
+        if config.compare.is_some() && !sections_given {
+            config.sections.push(SummarySection::Changes);
+        }
+
+        if config.compare.is_none() && config.sections.contains(&SummarySection::Changes) {
+            return Err(String::from("The changes section needs a snapshot to compare with, given as compare=PATH"));
+        }
+
+        // Room for at least a heading and a line under it
+        if config.limit < 100 {
+            return Err(String::from("The limit must be at least 100 characters"));
+        }
+
+        return Ok(config);
+    }
+}
+
+impl Default for SummaryConfig {
+    fn default() -> SummaryConfig {
+        return SummaryConfig::new();
+    }
+}
+
+fn parse_number(value: &str) -> Result<usize, String> {
+    return value
+        .parse()
+        .map_err(|_| format!("Could not parse number \"{}\"", value));
+}
+
+// Writes a summary for posting back to Discord into the
+// "Summary" folder, split into "Message 1.md", "Message 2.md"
+// and so on, each short enough to paste as one message
+pub fn export_markdown(
+    path: &String,
+    config: &SummaryConfig,
+    server: &Author,
+    author_hashmap: &HashMap<u64, Author>,
+    channel_id_dict: &HashMap<u64, Channel>,
+    date_range: DateRange,
+) -> Result<(), StatsError> {
+    let baseline = match &config.compare {
+        Some(compare_path) => Some(Snapshot::load(compare_path)?),
+        None => None,
+    };
+
+    let mut sections: Vec<String> = Vec::new();
+
+    for section in &config.sections {
+        let lines = match section {
+            SummarySection::Overview => overview(server, author_hashmap, channel_id_dict, date_range),
+            SummarySection::Posters => posters(server, author_hashmap, config.top),
+            SummarySection::Words => words(server, config.top),
+            SummarySection::Channels => channels(server, channel_id_dict, config.top),
+            SummarySection::Hours => hours(server, config.top),
+            SummarySection::Changes => match &baseline {
+                Some(baseline) => changes(server, author_hashmap, baseline, config.top),
+                None => Vec::new(),
+            },
+        };
+
+        if !lines.is_empty() {
+            sections.push(lines.join("\n"));
+        }
+    }
+
+    let messages = split_messages(&sections, config.limit);
+
+    let summary_path = format!("{}Summary{}", path, std::path::MAIN_SEPARATOR);
+
+    if let Err(error) = fs::create_dir_all(&summary_path) {
+        return Err(StatsError::Io(format!("Could not create {}! {}", summary_path, error)));
+    }
+
+    // Clear out the messages of the last summary, which may
+    // have been split into more parts than this one
+    if let Ok(entries) = fs::read_dir(&summary_path) {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+
+            if name.starts_with("Message ") && name.ends_with(".md") {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+
+    for (index, message) in messages.iter().enumerate() {
+        let message_path = format!("{}Message {}.md", summary_path, index + 1);
+
+        if let Err(error) = fs::write(&message_path, message) {
+            return Err(StatsError::Io(format!("Could not write {}! {}", message_path, error)));
+        }
+    }
+
+    Ok(())
+}
+
+// Discord counts message length in UTF-16 code units
+fn message_length(text: &str) -> usize {
+    return text.encode_utf16().count();
+}
+
+// Packs the sections into as few messages as possible without
+// going over the limit. Sections are kept whole where they
+// fit, and otherwise split between lines.
+fn split_messages(sections: &[String], limit: usize) -> Vec<String> {
+    let mut messages: Vec<String> = Vec::new();
+    let mut current = String::new();
+
+    let mut push = |current: &mut String, text: &str, separator: &str| {
+        if current.is_empty() {
+            current.push_str(text);
+        } else if message_length(current) + message_length(separator) + message_length(text) <= limit {
+            current.push_str(separator);
+            current.push_str(text);
+        } else {
+            messages.push(current.clone());
+            *current = String::from(text);
+        }
+    };
+
+    for section in sections {
+        if message_length(section) <= limit {
+            push(&mut current, section, "\n\n");
+            continue;
+        }
+
+        let mut first = true;
+        for line in section.lines() {
+            for part in split_line(line, limit) {
+                push(&mut current, &part, if first { "\n\n" } else { "\n" });
+                first = false;
+            }
+        }
+    }
+
+    if !current.is_empty() {
+        messages.push(current);
+    }
+
+    return messages;
+}
+
+// Cuts a line that is too long for a message on its own
+fn split_line(line: &str, limit: usize) -> Vec<String> {
+    let mut parts: Vec<String> = Vec::new();
+    let mut part = String::new();
+
+    for c in line.chars() {
+        if message_length(&part) + c.len_utf16() > limit {
+            parts.push(part);
+            part = String::new();
+        }
+        part.push(c);
+    }
+
+    parts.push(part);
+
+    return parts;
+}
+
+// Stops names and words from being read as formatting, or
+// from pinging anyone when the summary is posted
+pub fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::new();
+
+    for c in text.chars() {
+        match c {
+            '\\' | '*' | '_' | '~' | '`' | '|' | '>' | '[' | ']' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '@' => escaped.push_str("@\u{200b}"),
+            _ => escaped.push(c),
+        }
+    }
+
+    return escaped;
+}
+
+fn percent(part: u128, whole: u128) -> String {
+    if whole == 0 {
+        return String::from("0%");
+    }
+
+    return format!("{:.1}%", part as f64 * 100.0 / whole as f64);
+}
+
+fn count(count: u128, name: &str) -> String {
+    if count == 1 {
+        return format!("{} {}", count, name);
+    }
+
+    return format!("{} {}s", thousands(count), name);
+}
+
+fn overview(
+    server: &Author,
+    author_hashmap: &HashMap<u64, Author>,
+    channel_id_dict: &HashMap<u64, Channel>,
+    date_range: DateRange,
+) -> Vec<String> {
+    let guilds: BTreeSet<&str> = channel_id_dict.values().map(|channel| channel.guild.as_str()).collect();
+    let guilds: Vec<String> = guilds.iter().map(|guild| escape_markdown(guild)).collect();
+
+    let (first, last) = server.first_and_last();
+
+    // The range asked for, or else the messages themselves.
+    // The end of a range is the moment after the last day.
+    let start = date_range.start.or(first);
+    let end = date_range.end_inclusive().or(last);
+
+    let format_date = |date: Option<NaiveDateTime>| match date {
+        Some(date) => date.format("%-d %B %Y").to_string(),
+        None => String::from("?"),
+    };
+
+    let mut lines = vec![if guilds.is_empty() {
+        String::from("# Server statistics")
+    } else {
+        format!("# Server statistics for {}", guilds.join(", "))
+    }];
+
+    lines.push(format!("*{} to {}*", format_date(start), format_date(end)));
+    lines.push(String::new());
+    lines.push(format!(
+        "**{}** messages from **{}** members in **{}** channels",
+        thousands(server.message_count),
+        thousands(author_hashmap.values().filter(|author| author.message_count > 0).count() as u128),
+        channel_id_dict.len()
+    ));
+    lines.push(format!(
+        "**{}** words, **{}** attachments and **{}** questions",
+        thousands(server.word_count),
+        thousands(server.attachments_ledger.len() as u128),
+        thousands(server.question_count)
+    ));
+
+    return lines;
+}
+
+fn posters(server: &Author, author_hashmap: &HashMap<u64, Author>, top: usize) -> Vec<String> {
+    let mut lines = vec![String::from("## Top posters")];
+
+    for (index, author) in ranked(author_hashmap, RANKINGS[0].1).iter().take(top).enumerate() {
+        lines.push(format!(
+            "{}. **{}** {} ({})",
+            index + 1,
+            escape_markdown(author.display_name()),
+            count(author.message_count, "message"),
+            percent(author.message_count, server.message_count)
+        ));
+    }
+
+    return lines;
+}
+
+fn words(server: &Author, top: usize) -> Vec<String> {
+    let mut words: Vec<(&String, &u128)> = server.vocab_dict.iter().filter(|(word, _)| !word.is_empty()).collect();
+    words.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+
+    let mut lines = vec![String::from("## Top words")];
+
+    for (index, (word, uses)) in words.iter().take(top).enumerate() {
+        lines.push(format!("{}. **{}** {}", index + 1, escape_markdown(word), count(**uses, "use")));
+    }
+
+    return lines;
+}
+
+fn channels(server: &Author, channel_id_dict: &HashMap<u64, Channel>, top: usize) -> Vec<String> {
+    let mut counts: Vec<(u64, u64)> = server.messages_per_channel().into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    let mut lines = vec![String::from("## Busiest channels")];
+
+    for (index, (channel_id, messages)) in counts.iter().take(top).enumerate() {
+        let name = match channel_id_dict.get(channel_id) {
+            Some(channel) => escape_markdown(&channel.name),
+            None => channel_id.to_string(),
+        };
+
+        lines.push(format!(
+            "{}. **#{}** {} ({})",
+            index + 1,
+            name,
+            count(*messages as u128, "message"),
+            percent(*messages as u128, server.message_count)
+        ));
+    }
+
+    return lines;
+}
+
+fn hours(server: &Author, top: usize) -> Vec<String> {
+    let mut counts = [0u128; 24];
+    for (date, _) in &server.time_ledger {
+        counts[date.hour() as usize] += 1;
+    }
+
+    let mut busiest: Vec<(usize, u128)> = counts.iter().cloned().enumerate().filter(|(_, count)| *count > 0).collect();
+    busiest.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    let mut lines = vec![String::from("## Busiest hours")];
+
+    for (index, (hour, messages)) in busiest.iter().take(top).enumerate() {
+        lines.push(format!(
+            "{}. **{:02}:00 to {:02}:00** {} ({})",
+            index + 1,
+            hour,
+            (hour + 1) % 24,
+            count(*messages, "message"),
+            percent(*messages, server.message_count)
+        ));
+    }
+
+    return lines;
+}
+
+fn changes(server: &Author, author_hashmap: &HashMap<u64, Author>, baseline: &Snapshot, top: usize) -> Vec<String> {
+    let mut lines = vec![String::from("## Notable changes")];
+
+    let before = baseline.server.message_count;
+    let change = if before == 0 {
+        String::new()
+    } else {
+        let change = (server.message_count as f64 - before as f64) * 100.0 / before as f64;
+        format!(" ({:+.1}%)", change)
+    };
+
+    lines.push(format!(
+        "Messages: **{}**, against {} last time{}",
+        server.message_count, before, change
+    ));
+
+    let mut new_members: Vec<&Author> = author_hashmap
+        .values()
+        .filter(|author| !baseline.authors.contains_key(&author.id))
+        .collect();
+    new_members.sort_by(|a, b| b.message_count.cmp(&a.message_count).then(a.id.cmp(&b.id)));
+
+    if !new_members.is_empty() {
+        lines.push(String::new());
+        lines.push(format!("**New faces** ({})", new_members.len()));
+
+        for author in new_members.iter().take(top) {
+            lines.push(format!(
+                "- {} {}",
+                escape_markdown(author.display_name()),
+                count(author.message_count, "message")
+            ));
+        }
+    }
+
+    let old_ranks = ranks(&baseline.authors, RANKINGS[0].1);
+    let new_ranks = ranks(author_hashmap, RANKINGS[0].1);
+
+    let mut climbers: Vec<(&Author, usize, usize)> = new_ranks
+        .iter()
+        .filter_map(|(id, new_rank)| match old_ranks.get(id) {
+            Some(old_rank) if old_rank > new_rank => Some((&author_hashmap[id], *old_rank, *new_rank)),
+            _ => None,
+        })
+        .collect();
+    climbers.sort_by(|a, b| (b.1 - b.2).cmp(&(a.1 - a.2)).then(a.2.cmp(&b.2)));
+
+    if !climbers.is_empty() {
+        lines.push(String::new());
+        lines.push(String::from("**Climbing the rankings**"));
+
+        for (author, old_rank, new_rank) in climbers.iter().take(top) {
+            lines.push(format!(
+                "- {} up {} places, from {} to {}",
+                escape_markdown(author.display_name()),
+                old_rank - new_rank,
+                old_rank,
+                new_rank
+            ));
+        }
+    }
+
+    let mut quiet: Vec<&Author> = baseline
+        .authors
+        .values()
+        .filter(|author| !author_hashmap.contains_key(&author.id))
+        .collect();
+    quiet.sort_by(|a, b| b.message_count.cmp(&a.message_count).then(a.id.cmp(&b.id)));
+
+    if !quiet.is_empty() {
+        lines.push(String::new());
+        lines.push(format!("**Gone quiet** ({})", quiet.len()));
+
+        for author in quiet.iter().take(top) {
+            lines.push(format!(
+                "- {}, who sent {} last time",
+                escape_markdown(author.display_name()),
+                count(author.message_count, "message")
+            ));
+        }
+    }
+
+    return lines;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections_are_packed_into_as_few_messages_as_fit() {
+        let sections = vec!["a".repeat(40), "b".repeat(40), "c".repeat(40)];
+
+        let messages = split_messages(&sections, 100);
+
+        assert_eq!(
+            messages,
+            vec![format!("{}\n\n{}", "a".repeat(40), "b".repeat(40)), "c".repeat(40)]
+        );
+        assert!(messages.iter().all(|message| message_length(message) <= 100));
+    }
+
+    #[test]
+    fn sections_too_long_for_a_message_are_split_between_lines() {
+        let lines: Vec<String> = (0..10).map(|i| format!("line {} {}", i, "x".repeat(20))).collect();
+        let sections = vec![lines.join("\n")];
+
+        let messages = split_messages(&sections, 100);
+
+        assert!(messages.len() > 1);
+        assert!(messages.iter().all(|message| message_length(message) <= 100));
+        assert_eq!(messages.join("\n"), lines.join("\n"));
+    }
+
+    #[test]
+    fn lines_too_long_for_a_message_are_cut() {
+        let line = "y".repeat(250);
+
+        let messages = split_messages(std::slice::from_ref(&line), 100);
+
+        assert_eq!(messages.len(), 3);
+        assert!(messages.iter().all(|message| message_length(message) <= 100));
+        assert_eq!(messages.concat(), line);
+    }
+
+    #[test]
+    fn length_is_counted_in_utf16_code_units() {
+        // Each of these emoji takes two code units
+        let line = "\u{1f600}".repeat(60);
+
+        assert_eq!(message_length(&line), 120);
+
+        let parts = split_line(&line, 100);
+
+        assert_eq!(parts.len(), 2);
+        assert_eq!(message_length(&parts[0]), 100);
+        assert_eq!(parts.concat(), line);
+    }
+
+    #[test]
+    fn formatting_and_pings_are_escaped() {
+        assert_eq!(escape_markdown("*bold* @everyone"), "\\*bold\\* @\u{200b}everyone");
+    }
+
+    #[test]
+    fn summary_config_is_parsed_from_terms() {
+        let config = SummaryConfig::parse("sections=posters,words top=3 limit=500").unwrap();
+
+        assert_eq!(config.sections, vec![SummarySection::Posters, SummarySection::Words]);
+        assert_eq!(config.top, 3);
+        assert_eq!(config.limit, 500);
+
+        assert!(SummaryConfig::parse("limit=50").is_err());
+        assert!(SummaryConfig::parse("sections=changes").is_err());
+        assert!(SummaryConfig::parse("sections=nonsense").is_err());
+    }
+
+    #[test]
+    fn overview_groups_the_digits_of_large_counts() {
+        let mut server = Author::new(0);
+        server.message_count = 123456;
+        server.word_count = 1234567;
+        server.question_count = 999;
+
+        let lines = overview(&server, &HashMap::new(), &HashMap::new(), DateRange::all());
+
+        assert!(lines.contains(&String::from("**123,456** messages from **0** members in **0** channels")));
+        assert!(lines.contains(&String::from("**1,234,567** words, **0** attachments and **999** questions")));
+    }
+}
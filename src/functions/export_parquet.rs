@@ -0,0 +1,332 @@
+use arrow::array::{
+    ArrayRef, BooleanArray, ListBuilder, StringArray, TimestampMillisecondArray, UInt32Array, UInt64Array,
+    UInt64Builder,
+};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use chrono::NaiveDateTime;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Arc;
+
+use super::error::StatsError;
+use super::rankings::*;
+use super::structs::*;
+
+// How many messages are turned into columns at a time, to
+// keep memory down on large servers
+const BATCH_SIZE: usize = 65536;
+
+// messages.parquet, one row per message. main_author_id is
+// the account the message is counted under once alt accounts
+// are merged with --aliases. Timestamps have no timezone, as
+// in the exports. Columns are only ever added to the end.
+pub fn message_schema() -> SchemaRef {
+    return Arc::new(Schema::new(vec![
+        Field::new("author_id", DataType::UInt64, false),
+        Field::new("main_author_id", DataType::UInt64, false),
+        Field::new("author_name", DataType::Utf8, false),
+        Field::new("is_bot", DataType::Boolean, false),
+        Field::new("channel_id", DataType::UInt64, false),
+        Field::new("channel_name", DataType::Utf8, false),
+        Field::new("timestamp", DataType::Timestamp(TimeUnit::Millisecond, None), false),
+        Field::new("content", DataType::Utf8, false),
+        Field::new("word_count", DataType::UInt64, false),
+        Field::new("char_count", DataType::UInt64, false),
+        Field::new("attachment_count", DataType::UInt32, false),
+        Field::new("reaction_count", DataType::UInt64, false),
+    ]));
+}
+
+// authors.parquet, one row per author (and bot), with the
+// same totals as the csv reports. ranked is false for bots
+// kept out of the rankings.
+pub fn author_schema() -> SchemaRef {
+    return Arc::new(Schema::new(vec![
+        Field::new("author_id", DataType::UInt64, false),
+        Field::new("name", DataType::Utf8, false),
+        Field::new("is_bot", DataType::Boolean, false),
+        Field::new("ranked", DataType::Boolean, false),
+        Field::new(
+            "merged_ids",
+            DataType::List(Arc::new(Field::new("item", DataType::UInt64, true))),
+            false,
+        ),
+        Field::new("messages", DataType::UInt64, false),
+        Field::new("words", DataType::UInt64, false),
+        Field::new("characters", DataType::UInt64, false),
+        Field::new("attachments", DataType::UInt64, false),
+        Field::new("questions", DataType::UInt64, false),
+        Field::new("vocabulary", DataType::UInt64, false),
+        Field::new("first_message", DataType::Timestamp(TimeUnit::Millisecond, None), true),
+        Field::new("last_message", DataType::Timestamp(TimeUnit::Millisecond, None), true),
+    ]));
+}
+
+// Writes messages.parquet and authors.parquet into the
+// "Parquet" folder, for DuckDB, Polars and the like
+pub fn export_parquet(
+    path: &String,
+    messages: &[Message],
+    author_hashmap: &HashMap<u64, Author>,
+    bot_hashmap: &HashMap<u64, Author>,
+    channel_id_dict: &HashMap<u64, Channel>,
+) -> Result<(), StatsError> {
+    let parquet_path = format!("{}Parquet{}", path, std::path::MAIN_SEPARATOR);
+
+    if let Err(error) = fs::create_dir_all(&parquet_path) {
+        return Err(StatsError::Io(format!("Could not create {}! {}", parquet_path, error)));
+    }
+
+    let messages_path = format!("{}messages.parquet", parquet_path);
+    if let Err(error) = write_messages(&messages_path, messages, author_hashmap, bot_hashmap, channel_id_dict) {
+        return Err(StatsError::Export(format!("Could not write {}! {}", messages_path, error)));
+    }
+
+    let authors_path = format!("{}authors.parquet", parquet_path);
+    if let Err(error) = write_authors(&authors_path, author_hashmap, bot_hashmap) {
+        return Err(StatsError::Export(format!("Could not write {}! {}", authors_path, error)));
+    }
+
+    Ok(())
+}
+
+fn create_writer(path: &str, schema: SchemaRef) -> Result<ArrowWriter<fs::File>, ParquetError> {
+    let file = fs::File::create(path)?;
+
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+
+    return ArrowWriter::try_new(file, schema, Some(properties));
+}
+
+fn timestamp(date: &NaiveDateTime) -> i64 {
+    return date.and_utc().timestamp_millis();
+}
+
+fn write_messages(
+    path: &str,
+    messages: &[Message],
+    author_hashmap: &HashMap<u64, Author>,
+    bot_hashmap: &HashMap<u64, Author>,
+    channel_id_dict: &HashMap<u64, Channel>,
+) -> Result<(), ParquetError> {
+    // The account each alt account was merged into
+    let mut main_ids: HashMap<u64, u64> = HashMap::new();
+    for author in author_hashmap.values().chain(bot_hashmap.values()) {
+        for merged_id in &author.merged_ids {
+            main_ids.insert(*merged_id, author.id);
+        }
+    }
+
+    let schema = message_schema();
+    let mut writer = create_writer(path, schema.clone())?;
+
+    for batch in messages.chunks(BATCH_SIZE) {
+        let channel_name = |message: &Message| match channel_id_dict.get(&message.channel_id) {
+            Some(channel) => channel.name.clone(),
+            None => String::new(),
+        };
+
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(UInt64Array::from_iter_values(batch.iter().map(|m| m.author_id))),
+            Arc::new(UInt64Array::from_iter_values(
+                batch.iter().map(|m| *main_ids.get(&m.author_id).unwrap_or(&m.author_id)),
+            )),
+            Arc::new(StringArray::from_iter_values(batch.iter().map(|m| m.author_name.as_str()))),
+            Arc::new(BooleanArray::from(batch.iter().map(|m| m.is_bot).collect::<Vec<bool>>())),
+            Arc::new(UInt64Array::from_iter_values(batch.iter().map(|m| m.channel_id))),
+            Arc::new(StringArray::from_iter_values(batch.iter().map(channel_name))),
+            Arc::new(TimestampMillisecondArray::from_iter_values(
+                batch.iter().map(|m| timestamp(&m.date)),
+            )),
+            Arc::new(StringArray::from_iter_values(batch.iter().map(|m| m.content.as_str()))),
+            Arc::new(UInt64Array::from_iter_values(
+                batch.iter().map(|m| m.content.split(" ").count() as u64),
+            )),
+            Arc::new(UInt64Array::from_iter_values(batch.iter().map(|m| m.content.len() as u64))),
+            Arc::new(UInt32Array::from_iter_values(batch.iter().map(|m| m.attachments.len() as u32))),
+            Arc::new(UInt64Array::from_iter_values(
+                batch.iter().map(|m| m.reactions.values().sum::<u128>() as u64),
+            )),
+        ];
+
+        writer.write(&RecordBatch::try_new(schema.clone(), columns)?)?;
+    }
+
+    writer.close()?;
+
+    Ok(())
+}
+
+fn write_authors(
+    path: &str,
+    author_hashmap: &HashMap<u64, Author>,
+    bot_hashmap: &HashMap<u64, Author>,
+) -> Result<(), ParquetError> {
+    let everyone = members_then_bots(author_hashmap, bot_hashmap);
+
+    let mut merged_ids = ListBuilder::new(UInt64Builder::new());
+    for (author, _) in &everyone {
+        merged_ids.values().append_slice(&author.merged_ids);
+        merged_ids.append(true);
+    }
+
+    let count = |metric: Metric| -> ArrayRef {
+        return Arc::new(UInt64Array::from_iter_values(
+            everyone.iter().map(|(author, _)| metric(author) as u64),
+        ));
+    };
+
+    let first_message = everyone
+        .iter()
+        .map(|(author, _)| author.first_and_last().0.map(|date| timestamp(&date)));
+    let last_message = everyone
+        .iter()
+        .map(|(author, _)| author.first_and_last().1.map(|date| timestamp(&date)));
+
+    let mut columns: Vec<ArrayRef> = vec![
+        Arc::new(UInt64Array::from_iter_values(everyone.iter().map(|(author, _)| author.id))),
+        Arc::new(StringArray::from_iter_values(
+            everyone.iter().map(|(author, _)| author.display_name()),
+        )),
+        Arc::new(BooleanArray::from(
+            everyone.iter().map(|(author, _)| author.is_bot).collect::<Vec<bool>>(),
+        )),
+        Arc::new(BooleanArray::from(
+            everyone.iter().map(|(_, ranked)| *ranked).collect::<Vec<bool>>(),
+        )),
+        Arc::new(merged_ids.finish()),
+    ];
+
+    for (_, metric) in RANKINGS {
+        columns.push(count(metric));
+    }
+
+    columns.push(Arc::new(TimestampMillisecondArray::from(first_message.collect::<Vec<Option<i64>>>())));
+    columns.push(Arc::new(TimestampMillisecondArray::from(last_message.collect::<Vec<Option<i64>>>())));
+
+    let schema = author_schema();
+    let mut writer = create_writer(path, schema.clone())?;
+
+    writer.write(&RecordBatch::try_new(schema, columns)?)?;
+    writer.close()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::Array;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    fn message(author_id: u64, author_name: &str) -> Message {
+        return Message {
+            author_id: author_id,
+            channel_id: 111,
+            author_name: String::from(author_name),
+            date: NaiveDateTime::parse_from_str("2021-03-01 10:00", "%Y-%m-%d %H:%M").unwrap(),
+            content: String::from("hello there"),
+            attachments: Vec::new(),
+            reactions: HashMap::new(),
+            is_bot: false,
+        };
+    }
+
+    // Reads a file back, returning its columns as names and
+    // types along with the rows
+    fn read_back(path: &str) -> (Vec<(String, DataType)>, RecordBatch) {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(fs::File::open(path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let batch = reader.map(|batch| batch.unwrap()).next().unwrap();
+        let columns = batch
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| (field.name().clone(), field.data_type().clone()))
+            .collect();
+
+        return (columns, batch);
+    }
+
+    fn columns(expected: &[(&str, DataType)]) -> Vec<(String, DataType)> {
+        return expected.iter().map(|(name, data_type)| (String::from(*name), data_type.clone())).collect();
+    }
+
+    #[test]
+    fn files_keep_their_columns_in_order() {
+        let dir = std::env::temp_dir().join(format!("discord-statistics-parquet-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = format!("{}{}", dir.to_string_lossy(), std::path::MAIN_SEPARATOR);
+        let parquet_path = format!("{}Parquet{}", path, std::path::MAIN_SEPARATOR);
+
+        let messages = vec![message(222, "alice#1234"), message(333, "alice_alt#5678")];
+
+        // 333 is an alt account of 222
+        let mut main = Author::new(222);
+        for msg in &messages {
+            main = main.process_message(msg.clone());
+        }
+        main.merged_ids = vec![333];
+
+        let authors: HashMap<u64, Author> = HashMap::from([(222, main)]);
+
+        export_parquet(&path, &messages, &authors, &HashMap::new(), &HashMap::new()).unwrap();
+
+        let timestamp = DataType::Timestamp(TimeUnit::Millisecond, None);
+
+        let (message_columns, batch) = read_back(&format!("{}messages.parquet", parquet_path));
+        assert_eq!(
+            message_columns,
+            columns(&[
+                ("author_id", DataType::UInt64),
+                ("main_author_id", DataType::UInt64),
+                ("author_name", DataType::Utf8),
+                ("is_bot", DataType::Boolean),
+                ("channel_id", DataType::UInt64),
+                ("channel_name", DataType::Utf8),
+                ("timestamp", timestamp.clone()),
+                ("content", DataType::Utf8),
+                ("word_count", DataType::UInt64),
+                ("char_count", DataType::UInt64),
+                ("attachment_count", DataType::UInt32),
+                ("reaction_count", DataType::UInt64),
+            ])
+        );
+
+        let main_ids = batch.column(1).as_any().downcast_ref::<UInt64Array>().unwrap();
+        assert_eq!(main_ids.values().to_vec(), vec![222, 222]);
+
+        let (author_columns, batch) = read_back(&format!("{}authors.parquet", parquet_path));
+        assert_eq!(
+            author_columns,
+            columns(&[
+                ("author_id", DataType::UInt64),
+                ("name", DataType::Utf8),
+                ("is_bot", DataType::Boolean),
+                ("ranked", DataType::Boolean),
+                ("merged_ids", DataType::List(Arc::new(Field::new("item", DataType::UInt64, true)))),
+                ("messages", DataType::UInt64),
+                ("words", DataType::UInt64),
+                ("characters", DataType::UInt64),
+                ("attachments", DataType::UInt64),
+                ("questions", DataType::UInt64),
+                ("vocabulary", DataType::UInt64),
+                ("first_message", timestamp.clone()),
+                ("last_message", timestamp),
+            ])
+        );
+        assert_eq!(batch.num_rows(), 1);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
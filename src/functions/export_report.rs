@@ -0,0 +1,134 @@
+use chrono::Timelike;
+use plotly::Plot;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+
+use super::error::StatsError;
+use super::export_json::statistics_json;
+use super::export_stats::{channel_graph, server_timeline, time_graph};
+use super::filters::DateRange;
+use super::structs::*;
+use super::text::escape_html;
+
+const REPORT_HTML: &str = include_str!("report.html");
+
+// The copy of plotly.js that the plotly crate bundles, so
+// pages work without reaching out to a CDN. plotly.js is
+// MIT licensed, see scripts/LICENSE-plotly.txt
+pub const PLOTLY_JS: &str = include_str!("scripts/plotly-1.54.6.min.js");
+
+// Writes "Report.html", a single page holding everything in
+// Statistics.json along with the server time map, timeline
+// and channel graphs. plotly.js is written into the page too,
+// so it can be opened offline or sent on as one file.
+pub fn export_report(
+    path: &String,
+    server: &Author,
+    author_hashmap: &HashMap<u64, Author>,
+    bot_hashmap: &HashMap<u64, Author>,
+    channel_id_dict: &HashMap<u64, Channel>,
+    excluded_channels: &[Channel],
+    date_range: DateRange,
+) -> Result<(), StatsError> {
+    let statistics = statistics_json(
+        server,
+        author_hashmap,
+        bot_hashmap,
+        channel_id_dict,
+        excluded_channels,
+        date_range,
+    );
+
+    // A time map for every author would repeat each minute of
+    // the day over and over, so only the minutes they sent
+    // messages in are kept, and the page draws the rest
+    let mut minutes = serde_json::Map::new();
+    for author in author_hashmap.values().chain(bot_hashmap.values()) {
+        let mut counts: BTreeMap<u32, u64> = BTreeMap::new();
+        for (point, _) in &author.time_ledger {
+            *counts.entry(point.hour() * 60 + point.minute()).or_insert(0) += 1;
+        }
+
+        let counts: Vec<Value> = counts.iter().map(|(minute, count)| json!([minute, count])).collect();
+        minutes.insert(author.id.to_string(), Value::from(counts));
+    }
+
+    let report = json!({
+        "statistics": statistics,
+        "graphs": {
+            "server_timeline": plot_value(server_timeline("Server Timeline Graph", server))?,
+            "server_timemap": plot_value(time_graph("Server Time Graph", server))?,
+            "channels": plot_value(channel_graph("Channel Time Graph", server, channel_id_dict))?,
+        },
+        "minutes": minutes,
+    });
+
+    let title = report_title(channel_id_dict);
+
+    // "</" can't appear inside a script tag, and "<\/" means
+    // the same thing in JSON
+    let report_json = report.to_string().replace("</", "<\\/");
+
+    let html = fill_template(
+        REPORT_HTML,
+        &[
+            ("{{TITLE}}", &escape_html(&title)),
+            ("{{PLOTLY}}", PLOTLY_JS),
+            ("{{REPORT}}", &report_json),
+        ],
+    );
+
+    let path_to_export = format!("{}Report.html", path);
+
+    if let Err(error) = fs::write(&path_to_export, html) {
+        return Err(StatsError::Io(format!("Could not write {}! {}", path_to_export, error)));
+    }
+
+    Ok(())
+}
+
+// "Discord Statistics for" the guilds the channels are in
+pub fn report_title(channel_id_dict: &HashMap<u64, Channel>) -> String {
+    let guilds: BTreeSet<&str> = channel_id_dict.values().map(|channel| channel.guild.as_str()).collect();
+
+    if guilds.is_empty() {
+        return String::from("Discord Statistics");
+    }
+
+    return format!("Discord Statistics for {}", guilds.into_iter().collect::<Vec<&str>>().join(", "));
+}
+
+fn plot_value(plot: Plot) -> Result<Value, StatsError> {
+    return match serde_json::from_str(&plot.to_json()) {
+        Ok(value) => Ok(value),
+        Err(error) => Err(StatsError::Export(format!("Could not read a graph! {}", error))),
+    };
+}
+
+// Swaps each marker in the template for its value. Values
+// are never searched for markers themselves, so the text of
+// the messages can't change the page.
+fn fill_template(template: &str, values: &[(&str, &str)]) -> String {
+    let mut filled = String::with_capacity(template.len() + values.iter().map(|(_, v)| v.len()).sum::<usize>());
+    let mut rest = template;
+
+    loop {
+        let next = values
+            .iter()
+            .filter_map(|(marker, value)| rest.find(marker).map(|index| (index, *marker, *value)))
+            .min_by_key(|(index, _, _)| *index);
+
+        match next {
+            Some((index, marker, value)) => {
+                filled.push_str(&rest[..index]);
+                filled.push_str(value);
+                rest = &rest[index + marker.len()..];
+            }
+            None => {
+                filled.push_str(rest);
+                return filled;
+            }
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use chrono::NaiveDateTime;
+use rusqlite::{params, Connection};
+use std::collections::HashMap;
+use std::fs;
+
+use super::error::StatsError;
+use super::structs::*;
+
+// Dates are stored the way SQLite's own date functions
+// write them, so date(), strftime() and so on work on them
+const SQLITE_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+// Every account that sent a message is in authors. Alt
+// accounts merged with --aliases point at the main account
+// through merged_into, and only accounts with ranked set
+// count towards the rankings, which leaves out bots when
+// they are kept separate.
+//
+// words, characters and is_question are counted the same
+// way as in the csv reports, so the views agree with them.
+const SCHEMA: &str = "
+CREATE TABLE channels (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    category TEXT NOT NULL,
+    guild TEXT NOT NULL,
+    included INTEGER NOT NULL
+);
+
+CREATE TABLE authors (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    is_bot INTEGER NOT NULL,
+    ranked INTEGER NOT NULL,
+    merged_into INTEGER REFERENCES authors(id),
+    vocabulary INTEGER
+);
+
+CREATE TABLE author_names (
+    author_id INTEGER NOT NULL REFERENCES authors(id),
+    name TEXT NOT NULL,
+    first_seen TEXT NOT NULL,
+    last_seen TEXT NOT NULL,
+    PRIMARY KEY (author_id, name)
+);
+
+CREATE TABLE messages (
+    id INTEGER PRIMARY KEY,
+    author_id INTEGER NOT NULL REFERENCES authors(id),
+    channel_id INTEGER NOT NULL REFERENCES channels(id),
+    date TEXT NOT NULL,
+    content TEXT NOT NULL,
+    words INTEGER NOT NULL,
+    characters INTEGER NOT NULL,
+    is_question INTEGER NOT NULL
+);
+
+CREATE TABLE attachments (
+    message_id INTEGER NOT NULL REFERENCES messages(id),
+    url TEXT NOT NULL
+);
+
+CREATE TABLE reactions (
+    message_id INTEGER NOT NULL REFERENCES messages(id),
+    emoji TEXT NOT NULL,
+    count INTEGER NOT NULL,
+    PRIMARY KEY (message_id, emoji)
+);
+
+CREATE INDEX messages_author ON messages(author_id);
+CREATE INDEX messages_channel ON messages(channel_id);
+CREATE INDEX messages_date ON messages(date);
+CREATE INDEX attachments_message ON attachments(message_id);
+
+CREATE VIEW author_totals AS
+SELECT
+    a.id AS author_id,
+    a.name AS name,
+    COUNT(m.id) AS messages,
+    COALESCE(SUM(m.words), 0) AS words,
+    COALESCE(SUM(m.characters), 0) AS characters,
+    COALESCE(SUM((SELECT COUNT(*) FROM attachments t WHERE t.message_id = m.id)), 0) AS attachments,
+    COALESCE(SUM(m.is_question), 0) AS questions,
+    COALESCE(a.vocabulary, 0) AS vocabulary
+FROM authors a
+JOIN authors account ON COALESCE(account.merged_into, account.id) = a.id
+LEFT JOIN messages m ON m.author_id = account.id
+WHERE a.merged_into IS NULL AND a.ranked = 1
+GROUP BY a.id;
+";
+
+// One view per ranking in Server Statistics.csv, highest
+// first with ties going to the lower ID
+const RANKING_VIEWS: [&str; 6] = ["messages", "words", "characters", "attachments", "questions", "vocabulary"];
+
+// When each name an account went by was first and last seen
+type NamesSeen<'a> = HashMap<&'a str, (NaiveDateTime, NaiveDateTime)>;
+
+// Writes the imported messages into "Statistics.sqlite",
+// replacing any database already there
+pub fn export_sqlite(
+    path: &String,
+    messages: &[Message],
+    author_hashmap: &HashMap<u64, Author>,
+    bot_hashmap: &HashMap<u64, Author>,
+    channel_id_dict: &HashMap<u64, Channel>,
+    excluded_channels: &[Channel],
+) -> Result<(), StatsError> {
+    let path_to_export = format!("{}Statistics.sqlite", path);
+
+    if fs::metadata(&path_to_export).is_ok() {
+        if let Err(error) = fs::remove_file(&path_to_export) {
+            return Err(StatsError::Io(format!("Could not replace {}! {}", path_to_export, error)));
+        }
+    }
+
+    let written = write_database(
+        &path_to_export,
+        messages,
+        author_hashmap,
+        bot_hashmap,
+        channel_id_dict,
+        excluded_channels,
+    );
+
+    if let Err(error) = written {
+        return Err(StatsError::Export(format!("Could not write {}! {}", path_to_export, error)));
+    }
+
+    Ok(())
+}
+
+fn write_database(
+    path: &str,
+    messages: &[Message],
+    author_hashmap: &HashMap<u64, Author>,
+    bot_hashmap: &HashMap<u64, Author>,
+    channel_id_dict: &HashMap<u64, Channel>,
+    excluded_channels: &[Channel],
+) -> rusqlite::Result<()> {
+    let mut connection = Connection::open(path)?;
+
+    connection.execute_batch(SCHEMA)?;
+
+    for ranking in RANKING_VIEWS {
+        connection.execute_batch(&format!(
+            "CREATE VIEW ranking_{0} AS
+            SELECT ROW_NUMBER() OVER (ORDER BY {0} DESC, author_id) AS rank, author_id, name, {0} AS value
+            FROM author_totals;",
+            ranking
+        ))?;
+    }
+
+    // Which main account each account was merged into,
+    // and whether it is ranked
+    let mut main_accounts: HashMap<u64, (u64, bool)> = HashMap::new();
+    for (hashmap, ranked) in [(author_hashmap, true), (bot_hashmap, false)] {
+        for author in hashmap.values() {
+            main_accounts.insert(author.id, (author.id, ranked));
+
+            for merged_id in &author.merged_ids {
+                main_accounts.insert(*merged_id, (author.id, ranked));
+            }
+        }
+    }
+
+    // The names each account went by, and whether it was
+    // ever marked as a bot
+    let mut accounts: HashMap<u64, (NamesSeen, bool)> = HashMap::new();
+    for message in messages {
+        let (names, is_bot) = accounts.entry(message.author_id).or_insert((HashMap::new(), false));
+
+        let seen = names
+            .entry(message.author_name.as_str())
+            .or_insert((message.date, message.date));
+        if message.date < seen.0 {
+            seen.0 = message.date;
+        }
+        if message.date > seen.1 {
+            seen.1 = message.date;
+        }
+
+        *is_bot = *is_bot || message.is_bot;
+    }
+
+    let transaction = connection.transaction()?;
+
+    {
+        let mut insert_channel = transaction.prepare(
+            "INSERT OR REPLACE INTO channels (id, name, category, guild, included) VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+
+        for channel in channel_id_dict.values() {
+            insert_channel.execute(params![
+                channel.id as i64,
+                channel.name,
+                channel.category,
+                channel.guild,
+                true
+            ])?;
+        }
+
+        for channel in excluded_channels {
+            insert_channel.execute(params![
+                channel.id as i64,
+                channel.name,
+                channel.category,
+                channel.guild,
+                false
+            ])?;
+        }
+
+        let mut insert_author = transaction.prepare(
+            "INSERT INTO authors (id, name, is_bot, ranked, merged_into, vocabulary) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )?;
+        let mut insert_name = transaction
+            .prepare("INSERT INTO author_names (author_id, name, first_seen, last_seen) VALUES (?1, ?2, ?3, ?4)")?;
+
+        // Main accounts go in before the alt accounts that
+        // point at them
+        let mut account_ids: Vec<&u64> = accounts.keys().collect();
+        account_ids.sort_by_key(|id| (main_accounts.get(id).is_some_and(|(main_id, _)| main_id != *id), **id));
+
+        for id in account_ids {
+            let (names, is_bot) = &accounts[id];
+            let (main_id, ranked) = main_accounts.get(id).cloned().unwrap_or((*id, false));
+
+            let main_author = author_hashmap.get(&main_id).or(bot_hashmap.get(&main_id));
+
+            // The most recently used name, as in the reports
+            let name = names
+                .iter()
+                .max_by_key(|(_, (first_seen, last_seen))| (*last_seen, *first_seen))
+                .map(|(name, _)| *name)
+                .unwrap_or("");
+
+            let merged_into = if main_id == *id { None } else { Some(main_id as i64) };
+
+            let vocabulary = match (merged_into, main_author) {
+                (None, Some(author)) => Some(author.vocab_dict.len() as i64),
+                _ => None,
+            };
+
+            insert_author.execute(params![*id as i64, name, is_bot, ranked, merged_into, vocabulary])?;
+
+            for (name, (first_seen, last_seen)) in names {
+                insert_name.execute(params![
+                    *id as i64,
+                    name,
+                    first_seen.format(SQLITE_DATE_FORMAT).to_string(),
+                    last_seen.format(SQLITE_DATE_FORMAT).to_string()
+                ])?;
+            }
+        }
+
+        let mut insert_message = transaction.prepare(
+            "INSERT INTO messages (author_id, channel_id, date, content, words, characters, is_question)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        )?;
+        let mut insert_attachment = transaction.prepare("INSERT INTO attachments (message_id, url) VALUES (?1, ?2)")?;
+        let mut insert_reaction =
+            transaction.prepare("INSERT INTO reactions (message_id, emoji, count) VALUES (?1, ?2, ?3)")?;
+
+        for message in messages {
+            insert_message.execute(params![
+                message.author_id as i64,
+                message.channel_id as i64,
+                message.date.format(SQLITE_DATE_FORMAT).to_string(),
+                message.content,
+                message.content.split(" ").count() as i64,
+                message.content.len() as i64,
+                message.content.contains("?")
+            ])?;
+
+            let message_id = transaction.last_insert_rowid();
+
+            for attachment in &message.attachments {
+                insert_attachment.execute(params![message_id, attachment])?;
+            }
+
+            for (emoji, count) in &message.reactions {
+                insert_reaction.execute(params![message_id, emoji, *count as i64])?;
+            }
+        }
+    }
+
+    transaction.commit()?;
+
+    Ok(())
+}
@@ -1,22 +1,161 @@
 use chrono::*;
 use csv;
 use csv::Writer;
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
 use std::error::*;
+use std::fs;
 
 use plotly::common::{TickFormatStop, Title};
 use plotly::layout::{Axis, RangeSelector, RangeSlider, SelectorButton, SelectorStep, StepMode};
-use plotly::{Candlestick, ImageFormat, Layout, Ohlc, Plot, Scatter};
+use plotly::{Candlestick, Layout, Ohlc, Plot, Scatter};
 use sanitize_filename;
 
+use super::error::StatsError;
+use super::export_images::{export_images, ImageFormat};
+use super::export_json::{export_json, statistics_json};
+use super::export_markdown::{export_markdown, SummaryConfig};
+use super::export_parquet::export_parquet;
+use super::export_report::export_report;
+use super::export_sqlite::export_sqlite;
+use super::export_template::{export_templates, ReportTemplate};
+use super::export_tidy::export_tidy;
+use super::export_wrapped::export_wrapped;
+use super::export_xlsx::export_xlsx;
+use super::filters::DateRange;
+use super::progress::Stage;
+use super::rankings::{ranked, sorted_words, RANKINGS};
 use super::structs::*;
 
+// The kinds of report that can be written out
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    // Server Statistics.csv, the author csvs and the graphs
+    Csv,
+    // Everything in a single Statistics.json
+    Json,
+    // One rectangular table per statistic, in the Tidy folder
+    Tidy,
+    // Every message in a Statistics.sqlite database
+    Sqlite,
+    // Every message and the author totals as Parquet files,
+    // in the Parquet folder
+    Parquet,
+    // A single Report.html page that works offline
+    Html,
+    // A short summary split into messages that can be pasted
+    // into Discord, in the Summary folder
+    Markdown,
+    // A Statistics.xlsx workbook, with a sheet per ranking
+    // and per channel
+    Xlsx,
+    // The graphs drawn as svg images, next to the html ones
+    Svg,
+    // The graphs drawn as png images, next to the html ones
+    Png,
+}
+
+impl OutputFormat {
+    pub fn parse(value: &str) -> Option<OutputFormat> {
+        return match value.trim().to_lowercase().as_str() {
+            "csv" => Some(OutputFormat::Csv),
+            "json" => Some(OutputFormat::Json),
+            "tidy" => Some(OutputFormat::Tidy),
+            "sqlite" => Some(OutputFormat::Sqlite),
+            "parquet" => Some(OutputFormat::Parquet),
+            "html" => Some(OutputFormat::Html),
+            "markdown" => Some(OutputFormat::Markdown),
+            "xlsx" => Some(OutputFormat::Xlsx),
+            "svg" => Some(OutputFormat::Svg),
+            "png" => Some(OutputFormat::Png),
+            _ => None,
+        };
+    }
+
+    // Whether the format is written from the messages
+    // themselves, rather than just the counted up totals
+    pub fn needs_messages(&self) -> bool {
+        return *self == OutputFormat::Sqlite || *self == OutputFormat::Parquet;
+    }
+
+    // The image the format draws the graphs as, if it does
+    pub fn image_format(&self) -> Option<ImageFormat> {
+        return match self {
+            OutputFormat::Svg => Some(ImageFormat::Svg),
+            OutputFormat::Png => Some(ImageFormat::Png),
+            _ => None,
+        };
+    }
+
+    // Parses a comma separated list of formats, such as "csv,json"
+    pub fn parse_list(value: &str) -> Option<Vec<OutputFormat>> {
+        let mut formats: Vec<OutputFormat> = Vec::new();
+
+        for part in value.split(',') {
+            let format = OutputFormat::parse(part)?;
+
+            if !formats.contains(&format) {
+                formats.push(format);
+            }
+        }
+
+        return Some(formats);
+    }
+}
+
+// Where the reports are written to
+pub struct ExportDirs {
+    pub main: String,
+    pub authors: String,
+    pub graphs: String,
+}
+
+// Everything the reports are made from, once the messages
+// have been counted up
+pub struct Statistics {
+    pub server: Author,
+    pub authors: HashMap<u64, Author>,
+    pub bots: HashMap<u64, Author>,
+    pub channels: HashMap<u64, Channel>,
+    pub excluded_channels: Vec<Channel>,
+    pub date_range: DateRange,
+}
+
+// Which reports to write, along with the settings for
+// those that have any
+#[derive(Clone)]
+pub struct ExportOptions {
+    pub formats: Vec<OutputFormat>,
+    pub summary: SummaryConfig,
+    // The year to write the Wrapped cards for, if any
+    pub wrapped: Option<i32>,
+    // The templates to write reports from, if any
+    pub templates: Vec<ReportTemplate>,
+}
+
+impl ExportOptions {
+    pub fn new() -> ExportOptions {
+        return ExportOptions {
+            formats: vec![OutputFormat::Csv],
+            summary: SummaryConfig::new(),
+            wrapped: None,
+            templates: Vec::new(),
+        };
+    }
+}
+
+impl Default for ExportOptions {
+    fn default() -> ExportOptions {
+        return ExportOptions::new();
+    }
+}
+
 pub fn export_author(
     path: &String,
     sorted_server_words: Vec<(&String, &u128)>,
     author: Author,
 ) -> Result<(), csv::Error> {
-    let author_name = &author.names[0];
+    let author_name = author.display_name();
 
     let path_to_export = format!("{}{}.csv", path, sanitize_filename::sanitize(author_name));
 
@@ -24,7 +163,18 @@ pub fn export_author(
 
     // Start all of the tedious data labeling and exporting...
 
-    wtr.write_record(&["Statistics for:", author_name.as_str(), "", "", "", ""])?;
+    wtr.write_record(&["Statistics for:", author_name, "", "", "", ""])?;
+    if !author.merged_ids.is_empty() {
+        let merged_ids: Vec<String> = author.merged_ids.iter().map(|id| id.to_string()).collect();
+        wtr.write_record(&[
+            "Merged IDs:",
+            merged_ids.join(", ").as_str(),
+            "",
+            "",
+            "",
+            "",
+        ])?;
+    }
     wtr.write_record(&["-----------------------------", "", "", "", "", ""])?;
     wtr.write_record(&[
         "Total Messages:",
@@ -65,7 +215,9 @@ pub fn export_author(
                 found = true;
                 word_found = temp_word_found.unwrap();
 
-                for j in 0..50 {
+                // Servers with fewer than 50 words have fewer to
+                // leave out
+                for j in 0..sorted_server_words.len().min(50) {
                     if word_found.0 == sorted_server_words[j].0 {
                         found = false;
                     }
@@ -82,42 +234,93 @@ pub fn export_author(
             &"".to_string(),
         ])?;
     }
+
+    wtr.write_record(&["-----------------------------", "", "", "", "", ""])?;
+    wtr.write_record(&["Name History", "", "", "", "", ""])?;
+    wtr.write_record(&["-----------------------------", "", "", "", "", ""])?;
+    wtr.write_record(&["Name:", "First Seen:", "Last Seen:", "", "", ""])?;
+
+    for record in author.name_history() {
+        wtr.write_record(&[
+            record.name.clone(),
+            record.first_seen.to_string(),
+            record.last_seen.to_string(),
+            "".to_string(),
+            "".to_string(),
+            "".to_string(),
+        ])?;
+    }
+
     wtr.flush()?;
 
     Ok(())
 }
 
-pub fn export_time_graph(
-    title: &String,
+// Writes every name change in the server as a timeline,
+// dated by the first message sent under the new name
+pub fn export_name_changes(
     path: &String,
-    author: Author,
-) -> Result<(), Box<dyn Error>> {
+    author_hashmap: &HashMap<u64, Author>,
+) -> Result<(), csv::Error> {
+    let path_to_export = format!("{}Name Changes.csv", path);
+
+    let mut wtr = Writer::from_path(path_to_export)?;
+
+    wtr.write_record(&["Date:", "Author ID:", "Previous Name:", "New Name:"])?;
+
+    let mut changes: Vec<(NaiveDateTime, u64, String, String)> = Vec::new();
+
+    for author in author_hashmap.values() {
+        for change in author.name_changes() {
+            changes.push((change.date, author.id, change.previous_name, change.new_name));
+        }
+    }
+
+    changes.sort_by_key(|change| change.0);
+
+    for (date, id, previous_name, new_name) in changes {
+        wtr.write_record(&[date.to_string(), id.to_string(), previous_name, new_name])?;
+    }
+
+    wtr.flush()?;
+
+    Ok(())
+}
+
+// Removes the csv and graph exported for an author under
+// the given name, such as after they have been renamed
+pub fn remove_author_files(author_path: &String, graph_path: &String, name: &str) {
+    let csv_path = format!("{}{}.csv", author_path, sanitize_filename::sanitize(name));
+    let _ = fs::remove_file(csv_path);
+
+    for extension in ["html", "svg", "png"] {
+        let _ = fs::remove_file(format!(
+            "{}{}-timemap.{}",
+            graph_path,
+            sanitize_filename::sanitize(format!("Time Map for {}", name)),
+            extension
+        ));
+    }
+}
+
+// Builds the time map of an author: how many messages they
+// have sent in each minute of the day
+pub fn time_graph(title: &str, author: &Author) -> Plot {
     let mut time_range: Vec<NaiveTime> = Vec::new();
     let mut num_messages: Vec<u128> = Vec::new();
 
     for hr in 0..24 {
         for mn in 0..60 {
-            time_range.push(NaiveTime::from_hms(hr, mn, 0));
+            time_range.push(NaiveTime::from_hms_opt(hr, mn, 0).unwrap());
             num_messages.push(0);
         }
     }
 
-    let mut max: u128 = 0;
-
-    for (point, _) in author.time_ledger {
+    for (point, _) in &author.time_ledger {
         let index: usize = (point.minute() + (point.hour() * 60)) as usize;
         num_messages[index] += 1;
-
-        if num_messages[index] > max {
-            max = num_messages[index];
-        }
     }
 
-    let output_path = format!(
-        "{}{}-timemap.html",
-        path.clone(),
-        sanitize_filename::sanitize(title.clone())
-    );
     let mut plot = Plot::new();
     let trace = Scatter::new(time_range, num_messages);
     plot.add_trace(trace);
@@ -127,51 +330,55 @@ pub fn export_time_graph(
         .title(Title::new(title));
     plot.set_layout(layout);
 
-    // Uncomment line below to show plot when exporting
-    //plot.show();
-
-    plot.to_html(output_path);
-
-    Ok(())
+    return plot;
 }
 
-pub fn export_channel_graph(
+pub fn export_time_graph(
     title: &String,
     path: &String,
     author: Author,
-    channel_id_dict: HashMap<u64, String>,
 ) -> Result<(), Box<dyn Error>> {
     let output_path = format!(
         "{}{}-timemap.html",
         path.clone(),
         sanitize_filename::sanitize(title.clone())
     );
+    let plot = time_graph(title, &author);
+
+    // Uncomment line below to show plot when exporting
+    //plot.show();
+
+    plot.to_html(output_path);
+
+    Ok(())
+}
+
+// Builds a time map with one line for each channel
+pub fn channel_graph(
+    title: &str,
+    author: &Author,
+    channel_id_dict: &HashMap<u64, Channel>,
+) -> Plot {
     let mut plot = Plot::new();
 
-    for (channel_id, channel_name) in channel_id_dict {
+    for (channel_id, channel) in channel_id_dict {
         let mut time_range: Vec<NaiveTime> = Vec::new();
         let mut num_messages: Vec<u128> = Vec::new();
 
         for hr in 0..24 {
             for mn in 0..60 {
-                time_range.push(NaiveTime::from_hms(hr, mn, 0));
+                time_range.push(NaiveTime::from_hms_opt(hr, mn, 0).unwrap());
                 num_messages.push(0);
             }
         }
 
-        let mut max: u128 = 0;
-
-        for (point, id) in author.time_ledger.clone() {
+        for (point, id) in &author.time_ledger {
             if id == channel_id {
                 let index: usize = (point.minute() + (point.hour() * 60)) as usize;
                 num_messages[index] += 1;
-
-                if num_messages[index] > max {
-                    max = num_messages[index];
-                }
             }
         }
-        let trace = Scatter::new(time_range, num_messages).name(&channel_name);
+        let trace = Scatter::new(time_range, num_messages).name(&channel.name);
         plot.add_trace(trace);
     }
 
@@ -180,6 +387,22 @@ pub fn export_channel_graph(
         .title(Title::new(title));
     plot.set_layout(layout);
 
+    return plot;
+}
+
+pub fn export_channel_graph(
+    title: &String,
+    path: &String,
+    author: Author,
+    channel_id_dict: HashMap<u64, Channel>,
+) -> Result<(), Box<dyn Error>> {
+    let output_path = format!(
+        "{}{}-timemap.html",
+        path.clone(),
+        sanitize_filename::sanitize(title.clone())
+    );
+    let plot = channel_graph(title, &author, &channel_id_dict);
+
     // Uncomment line below to show plot when exporting
     //plot.show();
 
@@ -192,7 +415,7 @@ pub fn export_channel_timemap_graph(
     title: &String,
     path: &String,
     author: Author,
-    channel_id_dict: HashMap<u64, String>,
+    channel_id_dict: HashMap<u64, Channel>,
 ) -> Result<(), Box<dyn Error>> {
     let output_path = format!(
         "{}{}-timemap.html",
@@ -212,29 +435,25 @@ pub fn export_channel_timemap_graph(
     Ok(())
 }
 
-// Function to show the total messages sent each week
+// Builds the timeline of the total messages sent each week
+pub fn server_timeline(title: &str, server: &Author) -> Plot {
+    let mut plot = Plot::new();
 
-pub fn export_server_timeline(
-    title: &String,
-    path: &String,
-    server: Author,
-    channel_id_dict: HashMap<u64, String>,
-) -> Result<(), Box<dyn Error>> {
-    let output_path = format!(
-        "{}{}-timeline.html",
-        path.clone(),
-        sanitize_filename::sanitize(title.clone())
-    );
+    let layout = Layout::new()
+        .x_axis(Axis::new().range_slider(RangeSlider::new().visible(true)))
+        .title(Title::new(title));
+    plot.set_layout(layout);
 
     // Find the first and last message in the server
-    let mut sorted_time_ledger = server.time_ledger.clone().into_iter().collect::<Vec<_>>();
+    let mut sorted_time_ledger = server.time_ledger.clone();
     sorted_time_ledger.sort_by(|a, b| a.0.cmp(&b.0));
 
-    let end_time = server.time_ledger.first().unwrap().0;
-    let mut start_time = server.time_ledger.last().unwrap().0;
+    if sorted_time_ledger.is_empty() {
+        return plot;
+    }
 
-    println!("Start time: {:?}", start_time);
-    println!("End time: {:?}", end_time);
+    let mut start_time = sorted_time_ledger.first().unwrap().0;
+    let end_time = sorted_time_ledger.last().unwrap().0;
 
     // Sum the total of all messages in the server each 7 days
     let mut message_count: Vec<u128> = Vec::new();
@@ -245,31 +464,43 @@ pub fn export_server_timeline(
     }
 
     // Add the total messages in each week
-    for (point, channel_id) in server.time_ledger {
-        let index: usize = ((point - start_time).num_days() / 7) as usize;
-        if index >= message_count.len() {
-            println!("Index out of bounds: {}", index);
-        } else {
+    for (point, _) in &server.time_ledger {
+        let index: usize = ((*point - start_time).num_days() / 7) as usize;
+        if index < message_count.len() {
             message_count[index] += 1;
         }
     }
 
-    let mut plot = Plot::new();
-
     // Create time range
     let mut time_range: Vec<NaiveDateTime> = Vec::new();
-    
-    while (start_time <= end_time) {
-        time_range.push(start_time.clone());
+
+    while start_time <= end_time {
+        time_range.push(start_time);
         start_time = start_time + Duration::days(7);
     }
 
     let trace = Scatter::new(time_range, message_count);
     plot.add_trace(trace);
-    let layout = Layout::new()
-        .x_axis(Axis::new().range_slider(RangeSlider::new().visible(true)))
-        .title(Title::new(title));
-    plot.set_layout(layout);
+
+    return plot;
+}
+
+// Function to show the total messages sent each week
+
+pub fn export_server_timeline(
+    title: &String,
+    path: &String,
+    server: Author,
+    channel_id_dict: HashMap<u64, Channel>,
+) -> Result<(), Box<dyn Error>> {
+    let output_path = format!(
+        "{}{}-timeline.html",
+        path.clone(),
+        sanitize_filename::sanitize(title.clone())
+    );
+
+    let plot = server_timeline(title, &server);
+
     // Uncomment line below to show plot when exporting
     //plot.show();
     plot.to_html(output_path);
@@ -277,13 +508,24 @@ pub fn export_server_timeline(
 }
 
 pub fn export_all(
-    path: &String,
-    author_path: &String,
-    graph_path: &String,
-    server: Author,
-    author_hashmap: HashMap<u64, Author>,
-    channel_id_dict: HashMap<u64, String>,
-) -> Result<(), csv::Error> {
+    dirs: &ExportDirs,
+    statistics: Statistics,
+    only_authors: Option<&HashSet<u64>>,
+    stage: &Stage,
+) -> Result<(), StatsError> {
+    let path = &dirs.main;
+    let author_path = &dirs.authors;
+    let graph_path = &dirs.graphs;
+
+    let Statistics {
+        server,
+        authors: author_hashmap,
+        bots: bot_hashmap,
+        channels: channel_id_dict,
+        excluded_channels,
+        ..
+    } = statistics;
+
     let path_to_export = format!("{}Server Statistics.csv", path);
 
     let mut wtr = Writer::from_path(path_to_export)?;
@@ -304,127 +546,109 @@ pub fn export_all(
     // Write basic stats
     wtr.write_record(server.clone().return_stats())?;
 
-    // Write out list of everyone
-    wtr.write_record(&["Members of server:", "", "", "", "", ""])?;
-    for (_, author) in &author_hashmap {
-        let record_to_write = format!("{:?}", author.names);
+    // Write out which channels went into the statistics,
+    // and which were left out by the channel filters
+    wtr.write_record(&["Channels included:", "", "", "", "", ""])?;
+    let mut included_channels: Vec<&Channel> = channel_id_dict.values().collect();
+    included_channels.sort_by(|a, b| a.name.cmp(&b.name));
+
+    for channel in included_channels {
         wtr.write_record(&[
-            author.id.to_string(),
-            record_to_write,
+            channel.id.to_string(),
+            channel.name.clone(),
+            channel.category.clone(),
+            channel.guild.clone(),
             "".to_string(),
             "".to_string(),
-            "".to_string(),
-            "".to_string(),
-        ])?;
-    }
-
-    // Write out ranking lists
-    wtr.write_record(&["-----------------------------", "", "", "", "", ""])?;
-    wtr.write_record(&["Message Count Ranking:", "", "", "", "", ""])?;
-    wtr.write_record(&["-----------------------------", "", "", "", "", ""])?;
-    let mut message_count: Vec<(&u64, &Author)> = author_hashmap.iter().collect();
-    message_count.sort_by(|a, b| b.1.message_count.cmp(&a.1.message_count));
-
-    for item in message_count {
-        wtr.write_record(&[
-            &item.1.names[0],
-            &item.1.message_count.to_string(),
-            "",
-            "",
-            "",
-            "",
         ])?;
     }
-    wtr.write_record(&["-----------------------------", "", "", "", "", ""])?;
-    wtr.write_record(&["Word Count Ranking:", "", "", "", "", ""])?;
-    wtr.write_record(&["-----------------------------", "", "", "", "", ""])?;
-    let mut word_count: Vec<(&u64, &Author)> = author_hashmap.iter().collect();
-    word_count.sort_by(|a, b| b.1.word_count.cmp(&a.1.word_count));
 
-    for item in word_count {
+    wtr.write_record(&["Channels excluded:", "", "", "", "", ""])?;
+    for channel in &excluded_channels {
         wtr.write_record(&[
-            &item.1.names[0],
-            &item.1.word_count.to_string(),
-            "",
-            "",
-            "",
-            "",
+            channel.id.to_string(),
+            channel.name.clone(),
+            channel.category.clone(),
+            channel.guild.clone(),
+            "".to_string(),
+            "".to_string(),
         ])?;
     }
-    wtr.write_record(&["-----------------------------", "", "", "", "", ""])?;
-    wtr.write_record(&["Character Count Ranking:", "", "", "", "", ""])?;
-    wtr.write_record(&["-----------------------------", "", "", "", "", ""])?;
-    let mut character_count: Vec<(&u64, &Author)> = author_hashmap.iter().collect();
-    character_count.sort_by(|a, b| b.1.character_count.cmp(&a.1.character_count));
 
-    for item in character_count {
-        wtr.write_record(&[
-            &item.1.names[0],
-            &item.1.character_count.to_string(),
-            "",
-            "",
-            "",
-            "",
-        ])?;
-    }
-    wtr.write_record(&["-----------------------------", "", "", "", "", ""])?;
-    wtr.write_record(&["Attachment Count Ranking:", "", "", "", "", ""])?;
-    wtr.write_record(&["-----------------------------", "", "", "", "", ""])?;
-    let mut attachments_count: Vec<(&u64, &Author)> = author_hashmap.iter().collect();
-    attachments_count.sort_by(|a, b| {
-        b.1.attachments_ledger
-            .len()
-            .cmp(&a.1.attachments_ledger.len())
-    });
-
-    for item in attachments_count {
+    // Write out list of everyone
+    wtr.write_record(&["Members of server:", "", "", "", "", ""])?;
+    for author in ranked(&author_hashmap, RANKINGS[0].1) {
+        let record_to_write = format!("{:?}", author.name_list());
+        let merged_ids = if author.merged_ids.is_empty() {
+            "".to_string()
+        } else {
+            format!("Merged: {:?}", author.merged_ids)
+        };
         wtr.write_record(&[
-            &item.1.names[0],
-            &item.1.attachments_ledger.len().to_string(),
-            "",
-            "",
-            "",
-            "",
+            author.id.to_string(),
+            record_to_write,
+            merged_ids,
+            "".to_string(),
+            "".to_string(),
+            "".to_string(),
         ])?;
     }
-    wtr.write_record(&["-----------------------------", "", "", "", "", ""])?;
-    wtr.write_record(&["Question Count Ranking:", "", "", "", "", ""])?;
-    wtr.write_record(&["-----------------------------", "", "", "", "", ""])?;
-    let mut question_count: Vec<(&u64, &Author)> = author_hashmap.iter().collect();
-    question_count.sort_by(|a, b| b.1.question_count.cmp(&a.1.question_count));
 
-    for item in question_count {
+    // Write out the bots that were kept out of the rankings.
+    // Only bots listed separately are ever taken out of the
+    // members, so with --bots include or exclude there are none
+    if !bot_hashmap.is_empty() {
+        wtr.write_record(&["-----------------------------", "", "", "", "", ""])?;
+        wtr.write_record(&["Bots (not ranked):", "", "", "", "", ""])?;
+        wtr.write_record(&["-----------------------------", "", "", "", "", ""])?;
         wtr.write_record(&[
-            &item.1.names[0],
-            &item.1.question_count.to_string(),
-            "",
-            "",
-            "",
-            "",
+            "ID:",
+            "Name:",
+            "Total Messages:",
+            "Total Words:",
+            "Total Characters:",
+            "Total Attachments:",
         ])?;
+        let mut bots: Vec<&Author> = bot_hashmap.values().collect();
+        bots.sort_by_key(|bot| Reverse(bot.message_count));
+
+        for bot in bots {
+            wtr.write_record(&[
+                bot.id.to_string(),
+                bot.display_name().to_string(),
+                bot.message_count.to_string(),
+                bot.word_count.to_string(),
+                bot.character_count.to_string(),
+                bot.attachments_ledger.len().to_string(),
+            ])?;
+        }
     }
-    wtr.write_record(&["-----------------------------", "", "", "", "", ""])?;
-    wtr.write_record(&["Vocabulary Count Ranking:", "", "", "", "", ""])?;
-    wtr.write_record(&["-----------------------------", "", "", "", "", ""])?;
-    let mut vocab_count: Vec<(&u64, &Author)> = author_hashmap.iter().collect();
-    vocab_count.sort_by(|a, b| b.1.vocab_dict.len().cmp(&a.1.vocab_dict.len()));
 
-    for item in vocab_count {
-        wtr.write_record(&[
-            &item.1.names[0],
-            &item.1.vocab_dict.len().to_string(),
-            "",
-            "",
-            "",
-            "",
-        ])?;
+    // Write out ranking lists, in the same order as every
+    // other format gives them
+    let headings = [
+        "Message Count Ranking:",
+        "Word Count Ranking:",
+        "Character Count Ranking:",
+        "Attachment Count Ranking:",
+        "Question Count Ranking:",
+        "Vocabulary Count Ranking:",
+    ];
+
+    for (heading, (_, metric)) in headings.iter().zip(RANKINGS) {
+        wtr.write_record(&["-----------------------------", "", "", "", "", ""])?;
+        wtr.write_record(&[heading, "", "", "", "", ""])?;
+        wtr.write_record(&["-----------------------------", "", "", "", "", ""])?;
+
+        for author in ranked(&author_hashmap, metric) {
+            wtr.write_record(&[author.display_name(), &metric(author).to_string(), "", "", "", ""])?;
+        }
     }
 
     wtr.write_record(&["-----------------------------", "", "", "", "", ""])?;
     wtr.write_record(&["Top 1000 Words:", "", "", "", "", ""])?;
     wtr.write_record(&["-----------------------------", "", "", "", "", ""])?;
-    let mut all_words: Vec<(&String, &u128)> = server.vocab_dict.iter().collect();
-    all_words.sort_by(|a, b| b.1.cmp(a.1));
+    let all_words: Vec<(&String, &u128)> = sorted_words(&server);
 
     let num_words;
 
@@ -446,35 +670,43 @@ pub fn export_all(
     }
 
     // Write buffer to file
-    wtr.flush()?;
+    wtr.flush()
+        .map_err(|error| StatsError::Export(error.to_string()))?;
 
-    let mut count = 0;
+    export_name_changes(path, &author_hashmap)?;
 
-    for (_, value) in author_hashmap {
-        count += 1;
-
-        eprintln!("{}: Exporting {}...", count, value.names[0]);
+    for (key, value) in author_hashmap {
+        // When only some authors have changed, leave the
+        // files of everyone else as they are
+        if let Some(only_authors) = only_authors {
+            if !only_authors.contains(&key) {
+                continue;
+            }
+        }
 
         let csv_result = export_author(author_path, all_words.clone(), value.clone());
 
-        if csv_result.is_err() {
-            eprintln!("- Error: Could not export csv! {}", csv_result.unwrap_err());
-        } else {
-            eprintln!("- Exported csv successfully!");
+        if let Err(error) = csv_result {
+            stage.warn(&format!(
+                "Could not export csv for {}! {}",
+                value.display_name(),
+                error
+            ));
         }
 
-        let title = format!("Time Map for {}", value.names[0]);
+        let title = format!("Time Map for {}", value.display_name());
 
         let graph_result = export_time_graph(&title, graph_path, value.clone());
 
-        if graph_result.is_err() {
-            eprintln!(
-                "- Error: Could not export graph! {}",
-                graph_result.unwrap_err()
-            );
-        } else {
-            eprintln!("- Exported graph successfully!");
+        if let Err(error) = graph_result {
+            stage.warn(&format!(
+                "Could not export graph for {}! {}",
+                value.display_name(),
+                error
+            ));
         }
+
+        stage.inc(1);
     }
 
     // Author exporting is done, now time for server graphs!
@@ -485,11 +717,15 @@ pub fn export_all(
         channel_id_dict.clone(),
     );
 
+    if let Err(error) = export_server_timeline_result {
+        stage.warn(&error.to_string());
+    }
+
     let server_timemap_graph_result =
         export_time_graph(&"Server Time Graph".to_string(), path, server.clone());
 
-    if server_timemap_graph_result.is_err() {
-        println!("{}", server_timemap_graph_result.unwrap_err());
+    if let Err(error) = server_timemap_graph_result {
+        stage.warn(&error.to_string());
     }
     
     let server_channel_graph_result = export_channel_graph(
@@ -499,8 +735,8 @@ pub fn export_all(
         channel_id_dict.clone(),
     );
 
-    if server_channel_graph_result.is_err() {
-        println!("{}", server_channel_graph_result.unwrap_err());
+    if let Err(error) = server_channel_graph_result {
+        stage.warn(&error.to_string());
     }
 
     let server_channel_timemap_graph_result = export_channel_timemap_graph(
@@ -510,8 +746,149 @@ pub fn export_all(
         channel_id_dict.clone(),
     );
 
-    if server_channel_timemap_graph_result.is_err() {
-        println!("{}", server_channel_timemap_graph_result.unwrap_err());
+    if let Err(error) = server_channel_timemap_graph_result {
+        stage.warn(&error.to_string());
+    }
+    Ok(())
+}
+
+// Writes the statistics out in every format asked for.
+// The messages are only needed by formats that write out
+// every message, and can't be given when exporting from
+// a snapshot.
+pub fn export_formats(
+    options: &ExportOptions,
+    messages: Option<&[Message]>,
+    dirs: &ExportDirs,
+    statistics: Statistics,
+    only_authors: Option<&HashSet<u64>>,
+    stage: &Stage,
+) -> Result<(), StatsError> {
+    let formats = &options.formats;
+    let path = &dirs.main;
+
+    if messages.is_none() {
+        if let Some(format) = formats.iter().find(|format| format.needs_messages()) {
+            return Err(StatsError::Argument(format!(
+                "The {} format is written from the messages, so needs the exports rather than a snapshot",
+                format!("{:?}", format).to_lowercase()
+            )));
+        }
+    }
+
+    if formats.contains(&OutputFormat::Json) {
+        export_json(
+            path,
+            &statistics.server,
+            &statistics.authors,
+            &statistics.bots,
+            &statistics.channels,
+            &statistics.excluded_channels,
+            statistics.date_range,
+        )?;
+    }
+
+    if formats.contains(&OutputFormat::Html) {
+        export_report(
+            path,
+            &statistics.server,
+            &statistics.authors,
+            &statistics.bots,
+            &statistics.channels,
+            &statistics.excluded_channels,
+            statistics.date_range,
+        )?;
+    }
+
+    if formats.contains(&OutputFormat::Markdown) {
+        export_markdown(
+            path,
+            &options.summary,
+            &statistics.server,
+            &statistics.authors,
+            &statistics.channels,
+            statistics.date_range,
+        )?;
+    }
+
+    if !options.templates.is_empty() {
+        let json = statistics_json(
+            &statistics.server,
+            &statistics.authors,
+            &statistics.bots,
+            &statistics.channels,
+            &statistics.excluded_channels,
+            statistics.date_range,
+        );
+
+        export_templates(
+            path,
+            &options.templates,
+            json,
+            &statistics.server,
+            &statistics.channels,
+            statistics.date_range,
+        )?;
+    }
+
+    if formats.contains(&OutputFormat::Tidy) {
+        export_tidy(
+            path,
+            &statistics.server,
+            &statistics.authors,
+            &statistics.bots,
+            &statistics.channels,
+            &statistics.excluded_channels,
+        )?;
+    }
+
+    if let Some(year) = options.wrapped {
+        export_wrapped(path, year, &statistics.server, &statistics.authors, &statistics.channels)?;
+    }
+
+    if formats.contains(&OutputFormat::Xlsx) {
+        export_xlsx(
+            path,
+            &statistics.server,
+            &statistics.authors,
+            &statistics.bots,
+            &statistics.channels,
+            &statistics.excluded_channels,
+        )?;
+    }
+
+    let image_formats: Vec<ImageFormat> = formats.iter().filter_map(|format| format.image_format()).collect();
+
+    if !image_formats.is_empty() {
+        export_images(
+            &image_formats,
+            dirs,
+            &statistics.server,
+            &statistics.authors,
+            &statistics.channels,
+            only_authors,
+            stage,
+        )?;
+    }
+
+    if let (true, Some(messages)) = (formats.contains(&OutputFormat::Sqlite), messages) {
+        export_sqlite(
+            path,
+            messages,
+            &statistics.authors,
+            &statistics.bots,
+            &statistics.channels,
+            &statistics.excluded_channels,
+        )?;
+    }
+
+    if let (true, Some(messages)) = (formats.contains(&OutputFormat::Parquet), messages) {
+        export_parquet(path, messages, &statistics.authors, &statistics.bots, &statistics.channels)?;
+    }
+
+    if formats.contains(&OutputFormat::Csv) {
+        export_all(dirs, statistics, only_authors, stage)?;
     }
+
     Ok(())
 }
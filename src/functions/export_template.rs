@@ -0,0 +1,281 @@
+use chrono::Timelike;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, MAIN_SEPARATOR};
+use tera::{Context, Tera};
+
+use super::error::StatsError;
+use super::export_json::date_value;
+use super::export_markdown::escape_markdown;
+use super::export_report::report_title;
+use super::filters::DateRange;
+use super::structs::*;
+use super::text;
+
+pub const TEMPLATES_HELP: &str = "\
+Templates are written with Tera (https://keats.github.io/tera/docs/), and each one writes a
+file of the same name, less any .tera ending, into the Reports folder.
+  html                  the bundled report.html template, a page of tables and bars
+  markdown              the bundled report.md template, a summary for a wiki or README
+  PATH                  any other template file, eg. weekly.md.tera writes Reports/weekly.md
+
+Variables:
+  everything in Statistics.json, laid out as in docs/statistics.schema.json: server,
+  date_range, channels, rankings, top_words, authors, bots, generated and schema_version
+  title                 \"Discord Statistics for\" the guilds read
+  hours                 the messages sent in each hour of the day, from 0 to 23, each with
+                        hour, label (eg. \"09:00\") and messages
+  channel_names         the name of each channel, by ID
+  end_inclusive         the last moment date_range covers, so the day it ends on, if it ends
+
+Filters, on top of Tera's own:
+  thousands             groups the digits of a number, 12345 becomes 12,345
+  percent(of=N)         a number as a percentage of N, to one decimal place
+  markdown              escapes text so it can't be read as markdown formatting
+
+Templates ending in .html, .htm or .xml escape every value written into them. A template can
+extend report.html or report.md, and only replace the blocks it wants to change.
+";
+
+const REPORT_HTML: &str = include_str!("templates/report.html");
+const REPORT_MD: &str = include_str!("templates/report.md");
+
+// A template to write a report from
+#[derive(Clone, Debug)]
+pub struct ReportTemplate {
+    // What the template is known by, which is also the name
+    // of the file it writes
+    pub name: String,
+    pub source: String,
+}
+
+impl ReportTemplate {
+    // "html" and "markdown" are the bundled templates, and
+    // anything else is read as the path to one
+    pub fn parse(value: &str) -> Result<ReportTemplate, String> {
+        let value = value.trim();
+
+        match value.to_lowercase().as_str() {
+            "html" => return Ok(ReportTemplate::bundled("report.html", REPORT_HTML)),
+            "markdown" | "md" => return Ok(ReportTemplate::bundled("report.md", REPORT_MD)),
+            _ => {}
+        }
+
+        let source = match fs::read_to_string(value) {
+            Ok(source) => source,
+            Err(error) => return Err(format!("Could not read template {}! {}", value, error)),
+        };
+
+        let file_name = match Path::new(value).file_name() {
+            Some(file_name) => file_name.to_string_lossy().to_string(),
+            None => return Err(format!("{} isn't a template file", value)),
+        };
+
+        let name = match file_name.strip_suffix(".tera") {
+            Some(name) if !name.is_empty() => String::from(name),
+            _ => file_name,
+        };
+
+        return Ok(ReportTemplate {
+            name: sanitize_filename::sanitize(name),
+            source: source,
+        });
+    }
+
+    // Reads a comma separated list, and checks every template
+    // can be compiled before anything is counted up
+    pub fn parse_list(text: &str) -> Result<Vec<ReportTemplate>, String> {
+        let mut templates: Vec<ReportTemplate> = Vec::new();
+        let mut names: HashSet<String> = HashSet::new();
+
+        for value in text.split(',').filter(|value| !value.trim().is_empty()) {
+            let template = ReportTemplate::parse(value)?;
+
+            if !names.insert(template.name.to_lowercase()) {
+                return Err(format!(
+                    "More than one template would write Reports{}{}",
+                    MAIN_SEPARATOR, template.name
+                ));
+            }
+
+            templates.push(template);
+        }
+
+        if templates.is_empty() {
+            return Err(String::from("No templates given"));
+        }
+
+        if let Err(error) = engine(&templates) {
+            return Err(describe(&error));
+        }
+
+        return Ok(templates);
+    }
+
+    fn bundled(name: &str, source: &str) -> ReportTemplate {
+        return ReportTemplate {
+            name: String::from(name),
+            source: String::from(source),
+        };
+    }
+}
+
+// Writes a report from each template into the "Reports"
+// folder, each given the statistics as laid out in
+// Statistics.json
+pub fn export_templates(
+    path: &String,
+    templates: &[ReportTemplate],
+    statistics: Value,
+    server: &Author,
+    channel_id_dict: &HashMap<u64, Channel>,
+    date_range: DateRange,
+) -> Result<(), StatsError> {
+    let reports_dir = format!("{}Reports{}", path, MAIN_SEPARATOR);
+
+    if let Err(error) = fs::create_dir_all(&reports_dir) {
+        return Err(StatsError::Io(format!("Could not create {}! {}", reports_dir, error)));
+    }
+
+    let tera = match engine(templates) {
+        Ok(tera) => tera,
+        Err(error) => return Err(StatsError::Export(describe(&error))),
+    };
+
+    let mut context = match Context::from_value(statistics) {
+        Ok(context) => context,
+        Err(error) => return Err(StatsError::Export(describe(&error))),
+    };
+
+    let mut counts = [0u64; 24];
+    for (point, _) in &server.time_ledger {
+        counts[point.hour() as usize] += 1;
+    }
+
+    let hours: Vec<Value> = counts
+        .iter()
+        .enumerate()
+        .map(|(hour, messages)| json!({ "hour": hour, "label": format!("{:02}:00", hour), "messages": messages }))
+        .collect();
+
+    let channel_names: HashMap<String, &String> = channel_id_dict
+        .values()
+        .map(|channel| (channel.id.to_string(), &channel.name))
+        .collect();
+
+    context.insert("title", &report_title(channel_id_dict));
+    context.insert("hours", &hours);
+    context.insert("channel_names", &channel_names);
+    context.insert("end_inclusive", &date_value(date_range.end_inclusive()));
+
+    for template in templates {
+        let report = match tera.render(&template.name, &context) {
+            Ok(report) => report,
+            Err(error) => return Err(StatsError::Export(describe(&error))),
+        };
+
+        let path_to_export = format!("{}{}", reports_dir, template.name);
+
+        if let Err(error) = fs::write(&path_to_export, report) {
+            return Err(StatsError::Io(format!("Could not write {}! {}", path_to_export, error)));
+        }
+    }
+
+    Ok(())
+}
+
+// The bundled templates are always there, so the ones given
+// can extend them
+fn engine(templates: &[ReportTemplate]) -> Result<Tera, tera::Error> {
+    let mut tera = Tera::default();
+
+    let mut sources: Vec<(&str, &str)> = vec![("report.html", REPORT_HTML), ("report.md", REPORT_MD)];
+    for template in templates {
+        sources.retain(|(name, _)| *name != template.name);
+        sources.push((&template.name, &template.source));
+    }
+
+    tera.add_raw_templates(sources)?;
+
+    tera.register_filter("thousands", thousands);
+    tera.register_filter("percent", percent);
+    tera.register_filter("markdown", markdown);
+
+    return Ok(tera);
+}
+
+// Tera puts what actually went wrong in the error's source,
+// under "Failed to render" and the like
+fn describe(error: &tera::Error) -> String {
+    let mut description = error.to_string();
+    let mut source = error.source();
+
+    while let Some(cause) = source {
+        description.push_str(&format!("\n  {}", cause));
+        source = cause.source();
+    }
+
+    return description;
+}
+
+fn thousands(value: &Value, _: &HashMap<String, Value>) -> tera::Result<Value> {
+    let number = match value.as_f64() {
+        Some(number) => number,
+        None => return Err(tera::Error::msg(format!("thousands needs a number, not {}", value))),
+    };
+
+    let number = number.round() as i128;
+    let sign = if number < 0 { "-" } else { "" };
+
+    return Ok(Value::from(format!("{}{}", sign, text::thousands(number.unsigned_abs()))));
+}
+
+fn percent(value: &Value, arguments: &HashMap<String, Value>) -> tera::Result<Value> {
+    let (part, whole) = match (value.as_f64(), arguments.get("of").and_then(|of| of.as_f64())) {
+        (Some(part), Some(whole)) => (part, whole),
+        _ => return Err(tera::Error::msg("percent needs a number, and another for of=")),
+    };
+
+    if whole == 0.0 {
+        return Ok(Value::from("0%"));
+    }
+
+    return Ok(Value::from(format!("{:.1}%", part * 100.0 / whole)));
+}
+
+fn markdown(value: &Value, _: &HashMap<String, Value>) -> tera::Result<Value> {
+    return match value {
+        Value::String(text) => Ok(Value::from(escape_markdown(text))),
+        value => Ok(Value::from(escape_markdown(&value.to_string()))),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::export_json::statistics_json;
+
+    #[test]
+    fn reports_end_on_the_last_day_of_the_range() {
+        let dir = std::env::temp_dir().join(format!("discord-statistics-templates-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = format!("{}{}", dir.to_string_lossy(), MAIN_SEPARATOR);
+
+        let date_range = DateRange {
+            start: DateRange::parse_start("2021-01-01"),
+            end: DateRange::parse_end("2021-12-31"),
+        };
+        let server = Author::new(0);
+        let statistics = statistics_json(&server, &HashMap::new(), &HashMap::new(), &HashMap::new(), &[], date_range);
+
+        let templates = ReportTemplate::parse_list("markdown").unwrap();
+        export_templates(&path, &templates, statistics, &server, &HashMap::new(), date_range).unwrap();
+
+        let report = fs::read_to_string(format!("{}Reports{}report.md", path, MAIN_SEPARATOR)).unwrap();
+        assert!(report.contains("Messages from 2021-01-01 to 2021-12-31."));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,258 @@
+use chrono::{NaiveDate, NaiveDateTime, Timelike};
+use csv::Writer;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+
+use super::error::StatsError;
+use super::rankings::*;
+use super::structs::*;
+
+const TIDY_DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+// Writes the statistics into the "Tidy" folder as one table
+// per kind of statistic, each with a single header row and
+// one observation per row, so they load straight into a
+// spreadsheet, pandas or R:
+//
+// server.csv                 the server totals
+// authors.csv                one row per author (and bot)
+// author_names.csv           every name each author has used
+// merged_ids.csv             alt accounts merged into each author
+// channels.csv               every channel found, and whether it was included
+// rankings.csv               every ranking, one row per author per ranking
+// author_channel_counts.csv  messages from each author in each channel
+// word_counts.csv            how often each author used each word
+// daily_activity.csv         messages from each author in each channel per day
+// hourly_activity.csv        messages from each author per hour of the day
+pub fn export_tidy(
+    path: &String,
+    server: &Author,
+    author_hashmap: &HashMap<u64, Author>,
+    bot_hashmap: &HashMap<u64, Author>,
+    channel_id_dict: &HashMap<u64, Channel>,
+    excluded_channels: &[Channel],
+) -> Result<(), StatsError> {
+    let tidy_path = format!("{}Tidy{}", path, std::path::MAIN_SEPARATOR);
+
+    if let Err(error) = fs::create_dir_all(&tidy_path) {
+        return Err(StatsError::Io(format!("Could not create {}! {}", tidy_path, error)));
+    }
+
+    write_tables(
+        &tidy_path,
+        server,
+        author_hashmap,
+        bot_hashmap,
+        channel_id_dict,
+        excluded_channels,
+    )?;
+
+    Ok(())
+}
+
+fn write_tables(
+    tidy_path: &str,
+    server: &Author,
+    author_hashmap: &HashMap<u64, Author>,
+    bot_hashmap: &HashMap<u64, Author>,
+    channel_id_dict: &HashMap<u64, Channel>,
+    excluded_channels: &[Channel],
+) -> Result<(), csv::Error> {
+    let everyone: Vec<&Author> = members_then_bots(author_hashmap, bot_hashmap)
+        .into_iter()
+        .map(|(author, _)| author)
+        .collect();
+
+    let mut wtr = Writer::from_path(format!("{}server.csv", tidy_path))?;
+    wtr.write_record([
+        "messages",
+        "words",
+        "characters",
+        "attachments",
+        "questions",
+        "vocabulary",
+        "authors",
+        "bots",
+        "first_message",
+        "last_message",
+    ])?;
+
+    let mut record = server.clone().return_stats();
+    record.push(author_hashmap.len().to_string());
+    record.push(bot_hashmap.len().to_string());
+    record.append(&mut first_and_last(server));
+    wtr.write_record(record)?;
+    wtr.flush()?;
+
+    let mut wtr = Writer::from_path(format!("{}authors.csv", tidy_path))?;
+    wtr.write_record([
+        "author_id",
+        "name",
+        "is_bot",
+        "messages",
+        "words",
+        "characters",
+        "attachments",
+        "questions",
+        "vocabulary",
+        "first_message",
+        "last_message",
+    ])?;
+
+    for author in &everyone {
+        let mut record = vec![
+            author.id.to_string(),
+            author.display_name().to_string(),
+            author.is_bot.to_string(),
+        ];
+        record.append(&mut (*author).clone().return_stats());
+        record.append(&mut first_and_last(author));
+
+        wtr.write_record(record)?;
+    }
+    wtr.flush()?;
+
+    let mut wtr = Writer::from_path(format!("{}author_names.csv", tidy_path))?;
+    wtr.write_record(["author_id", "name", "first_seen", "last_seen"])?;
+
+    for author in &everyone {
+        for record in author.name_history() {
+            wtr.write_record([
+                author.id.to_string(),
+                record.name,
+                record.first_seen.format(TIDY_DATE_FORMAT).to_string(),
+                record.last_seen.format(TIDY_DATE_FORMAT).to_string(),
+            ])?;
+        }
+    }
+    wtr.flush()?;
+
+    let mut wtr = Writer::from_path(format!("{}merged_ids.csv", tidy_path))?;
+    wtr.write_record(["author_id", "merged_id"])?;
+
+    for author in &everyone {
+        for merged_id in &author.merged_ids {
+            wtr.write_record([author.id.to_string(), merged_id.to_string()])?;
+        }
+    }
+    wtr.flush()?;
+
+    let channel_counts = server.messages_per_channel();
+
+    let mut channels: Vec<&Channel> = channel_id_dict.values().collect();
+    channels.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut wtr = Writer::from_path(format!("{}channels.csv", tidy_path))?;
+    wtr.write_record(["channel_id", "name", "category", "guild", "included", "messages"])?;
+
+    for channel in channels {
+        wtr.write_record([
+            channel.id.to_string(),
+            channel.name.clone(),
+            channel.category.clone(),
+            channel.guild.clone(),
+            "true".to_string(),
+            channel_counts.get(&channel.id).cloned().unwrap_or(0).to_string(),
+        ])?;
+    }
+
+    for channel in excluded_channels {
+        wtr.write_record([
+            channel.id.to_string(),
+            channel.name.clone(),
+            channel.category.clone(),
+            channel.guild.clone(),
+            "false".to_string(),
+            "0".to_string(),
+        ])?;
+    }
+    wtr.flush()?;
+
+    let mut wtr = Writer::from_path(format!("{}rankings.csv", tidy_path))?;
+    wtr.write_record(["ranking", "rank", "author_id", "name", "value"])?;
+
+    for (name, metric) in RANKINGS {
+        for (index, author) in ranked(author_hashmap, metric).iter().enumerate() {
+            wtr.write_record([
+                name.to_string(),
+                (index + 1).to_string(),
+                author.id.to_string(),
+                author.display_name().to_string(),
+                metric(author).to_string(),
+            ])?;
+        }
+    }
+    wtr.flush()?;
+
+    let mut wtr = Writer::from_path(format!("{}author_channel_counts.csv", tidy_path))?;
+    wtr.write_record(["author_id", "channel_id", "messages"])?;
+
+    for author in &everyone {
+        let counts: BTreeMap<u64, u64> = author.messages_per_channel().into_iter().collect();
+
+        for (channel_id, count) in counts {
+            wtr.write_record([author.id.to_string(), channel_id.to_string(), count.to_string()])?;
+        }
+    }
+    wtr.flush()?;
+
+    let mut wtr = Writer::from_path(format!("{}word_counts.csv", tidy_path))?;
+    wtr.write_record(["author_id", "word", "count"])?;
+
+    for author in &everyone {
+        for (word, count) in sorted_words(author) {
+            wtr.write_record([author.id.to_string(), word.clone(), count.to_string()])?;
+        }
+    }
+    wtr.flush()?;
+
+    let mut wtr = Writer::from_path(format!("{}daily_activity.csv", tidy_path))?;
+    wtr.write_record(["date", "author_id", "channel_id", "messages"])?;
+
+    for author in &everyone {
+        let mut counts: BTreeMap<(NaiveDate, u64), u64> = BTreeMap::new();
+        for (date, channel_id) in &author.time_ledger {
+            *counts.entry((date.date(), *channel_id)).or_insert(0) += 1;
+        }
+
+        for ((date, channel_id), count) in counts {
+            wtr.write_record([
+                date.format("%Y-%m-%d").to_string(),
+                author.id.to_string(),
+                channel_id.to_string(),
+                count.to_string(),
+            ])?;
+        }
+    }
+    wtr.flush()?;
+
+    let mut wtr = Writer::from_path(format!("{}hourly_activity.csv", tidy_path))?;
+    wtr.write_record(["author_id", "hour", "messages"])?;
+
+    for author in &everyone {
+        let mut counts: BTreeMap<u32, u64> = BTreeMap::new();
+        for (date, _) in &author.time_ledger {
+            *counts.entry(date.hour()).or_insert(0) += 1;
+        }
+
+        for (hour, count) in counts {
+            wtr.write_record([author.id.to_string(), hour.to_string(), count.to_string()])?;
+        }
+    }
+    wtr.flush()?;
+
+    Ok(())
+}
+
+// The dates of the first and last messages in a ledger,
+// left blank when there are none
+fn first_and_last(author: &Author) -> Vec<String> {
+    let format = |date: Option<NaiveDateTime>| match date {
+        Some(date) => date.format(TIDY_DATE_FORMAT).to_string(),
+        None => String::new(),
+    };
+
+    let (first, last) = author.first_and_last();
+
+    return vec![format(first), format(last)];
+}
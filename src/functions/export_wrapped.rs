@@ -0,0 +1,510 @@
+use ab_glyph::{Font, FontRef, PxScale, ScaleFont};
+use chrono::{NaiveDate, Timelike};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+
+use super::error::StatsError;
+use super::export_images::FONT;
+use super::rankings::*;
+use super::structs::*;
+use super::text::{escape_html, thousands};
+
+// How many words and emoji each card lists
+const FAVOURITES: usize = 3;
+
+const CARD_WIDTH: f32 = 600.0;
+const CARD_MARGIN: f32 = 48.0;
+
+// Words or emoji, each with how often it was used
+type WordCounts = Vec<(String, u128)>;
+
+// Writes a year in review card for the server and for every
+// member into the "Wrapped YEAR" folder, as svg images that
+// can be shared on their own, along with an index.html that
+// shows them all. The run is limited to the year, so the
+// counters of each author only cover it.
+pub fn export_wrapped(
+    path: &String,
+    year: i32,
+    server: &Author,
+    author_hashmap: &HashMap<u64, Author>,
+    channel_id_dict: &HashMap<u64, Channel>,
+) -> Result<(), StatsError> {
+    let wrapped_path = format!("{}Wrapped {}{}", path, year, std::path::MAIN_SEPARATOR);
+
+    if let Err(error) = fs::create_dir_all(&wrapped_path) {
+        return Err(StatsError::Io(format!("Could not create {}! {}", wrapped_path, error)));
+    }
+
+    // Clear out the cards of members who have since been
+    // renamed, as the cards are named after them
+    if let Ok(entries) = fs::read_dir(&wrapped_path) {
+        for entry in entries.flatten() {
+            if entry.path().extension().is_some_and(|extension| extension == "svg") {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+
+    let members = ranked(author_hashmap, RANKINGS[0].1);
+    let server_top_50: Vec<&String> = sorted_words(server).iter().take(50).map(|(word, _)| *word).collect();
+
+    let mut cards: Vec<(String, String)> = vec![(
+        String::from("Server.svg"),
+        server_card(year, server, &members, channel_id_dict),
+    )];
+
+    for (index, author) in members.iter().enumerate() {
+        let name = sanitize_filename::sanitize(author.display_name());
+        let mut file_name = format!("{}.svg", name);
+
+        // Two members can share a name, or a member can be
+        // called "Server", so the first keeps it and the
+        // others have their ID added
+        if cards.iter().any(|(card_name, _)| card_name.eq_ignore_ascii_case(&file_name)) {
+            file_name = format!("{} {}.svg", name, author.id);
+        }
+
+        let card = author_card(year, author, index + 1, members.len(), &server_top_50, channel_id_dict);
+        cards.push((file_name, card));
+    }
+
+    for (file_name, card) in &cards {
+        let card_path = format!("{}{}", wrapped_path, file_name);
+
+        if let Err(error) = fs::write(&card_path, card) {
+            return Err(StatsError::Io(format!("Could not write {}! {}", card_path, error)));
+        }
+    }
+
+    let mut index = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{} Wrapped</title>\n\
+         <style>body {{ background: #2c2f33; margin: 24px; }} img {{ width: 300px; margin: 8px; }}</style>\n\
+         </head>\n<body>\n",
+        year
+    );
+
+    for (file_name, _) in &cards {
+        index.push_str(&format!(
+            "<a href=\"{0}\"><img src=\"{0}\" alt=\"{1}\"></a>\n",
+            encode_path(file_name),
+            escape_html(file_name.trim_end_matches(".svg"))
+        ));
+    }
+
+    index.push_str("</body>\n</html>\n");
+
+    let index_path = format!("{}index.html", wrapped_path);
+
+    if let Err(error) = fs::write(&index_path, index) {
+        return Err(StatsError::Io(format!("Could not write {}! {}", index_path, error)));
+    }
+
+    Ok(())
+}
+
+fn author_card(
+    year: i32,
+    author: &Author,
+    rank: usize,
+    members: usize,
+    server_top_50: &[&String],
+    channel_id_dict: &HashMap<u64, Channel>,
+) -> String {
+    let (words, emoji) = favourites(author);
+
+    // Favourite words leave out what everyone says, as with
+    // the distinctive words of Statistics.json, unless that
+    // leaves nothing, as on small servers
+    let mut distinctive: Vec<String> = words
+        .iter()
+        .filter(|(word, _)| !server_top_50.contains(&word))
+        .take(FAVOURITES)
+        .map(|(word, _)| word.clone())
+        .collect();
+
+    if distinctive.is_empty() {
+        distinctive = words.into_iter().take(FAVOURITES).map(|(word, _)| word).collect();
+    }
+
+    return card(
+        year,
+        author.display_name(),
+        author.message_count,
+        if author.message_count == 1 { "message sent" } else { "messages sent" },
+        &format!("#{} of {} members", rank, members),
+        &[
+            ("Most active in", busiest_channel(author, channel_id_dict)),
+            ("Peak hour", peak_hour(author)),
+            ("Longest streak", longest_streak(author)),
+            ("Favourite words", list(&distinctive)),
+            ("Favourite emoji", list(&emoji.into_iter().take(FAVOURITES).map(|(e, _)| e).collect::<Vec<String>>())),
+        ],
+        &format!(
+            "{} · {} · {}",
+            count(author.word_count, "word"),
+            count(author.attachments_ledger.len() as u128, "attachment"),
+            count(author.question_count, "question")
+        ),
+    );
+}
+
+fn server_card(year: i32, server: &Author, members: &[&Author], channel_id_dict: &HashMap<u64, Channel>) -> String {
+    let (words, emoji) = favourites(server);
+
+    let guilds: BTreeSet<&str> = channel_id_dict.values().map(|channel| channel.guild.as_str()).collect();
+    let title = if guilds.is_empty() {
+        String::from("Server")
+    } else {
+        guilds.into_iter().collect::<Vec<&str>>().join(", ")
+    };
+
+    let top_poster = match members.first() {
+        Some(author) => format!("{} ({})", author.display_name(), thousands(author.message_count)),
+        None => String::from("-"),
+    };
+
+    return card(
+        year,
+        &title,
+        server.message_count,
+        if server.message_count == 1 { "message sent" } else { "messages sent" },
+        &format!("by {} members in {} channels", members.len(), channel_id_dict.len()),
+        &[
+            ("Top poster", top_poster),
+            ("Most active channel", busiest_channel(server, channel_id_dict)),
+            ("Peak hour", peak_hour(server)),
+            ("Longest streak", longest_streak(server)),
+            ("Top words", list(&words.into_iter().take(FAVOURITES).map(|(w, _)| w).collect::<Vec<String>>())),
+            ("Top emoji", list(&emoji.into_iter().take(FAVOURITES).map(|(e, _)| e).collect::<Vec<String>>())),
+        ],
+        &format!(
+            "{} · {} · {}",
+            count(server.word_count, "word"),
+            count(server.attachments_ledger.len() as u128, "attachment"),
+            count(server.question_count, "question")
+        ),
+    );
+}
+
+// Draws a card. Text is measured with the font the charts
+// are drawn in, the first one the card asks for, and
+// squeezed to fit when it would run off the edge.
+fn card(
+    year: i32,
+    title: &str,
+    total: u128,
+    total_label: &str,
+    subtitle: &str,
+    rows: &[(&str, String)],
+    footer: &str,
+) -> String {
+    let mut svg = String::from(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"600\" height=\"900\" viewBox=\"0 0 600 900\">\n\
+         <defs><linearGradient id=\"background\" x1=\"0\" y1=\"0\" x2=\"1\" y2=\"1\">\
+         <stop offset=\"0\" stop-color=\"#5865F2\"/><stop offset=\"1\" stop-color=\"#EB459E\"/>\
+         </linearGradient></defs>\n\
+         <rect width=\"600\" height=\"900\" rx=\"32\" fill=\"url(#background)\"/>\n\
+         <g font-family=\"DejaVu Sans, Verdana, Arial, sans-serif\" fill=\"#FFFFFF\">\n",
+    );
+
+    svg.push_str(&text(80, 22.0, false, "opacity=\"0.8\"", &format!("{} Wrapped", year)));
+    svg.push_str(&text(130, 38.0, true, "", &shorten(title, 40)));
+    svg.push_str(&text(250, 96.0, true, "", &thousands(total)));
+    svg.push_str(&text(290, 24.0, false, "", total_label));
+    svg.push_str(&text(326, 20.0, false, "opacity=\"0.8\"", subtitle));
+
+    for (index, (label, value)) in rows.iter().enumerate() {
+        let y = 400 + index * 76;
+
+        svg.push_str(&text(y, 16.0, false, "opacity=\"0.75\"", &label.to_uppercase()));
+        svg.push_str(&text(y + 32, 26.0, true, "", &shorten(value, 48)));
+    }
+
+    svg.push_str(&text(860, 15.0, false, "opacity=\"0.7\"", footer));
+    svg.push_str("</g>\n</svg>\n");
+
+    return svg;
+}
+
+// A line of text at the left margin of a card
+fn text(y: usize, size: f32, bold: bool, attributes: &str, content: &str) -> String {
+    let mut element = format!("<text x=\"{}\" y=\"{}\" font-size=\"{}\"", CARD_MARGIN, y, size);
+
+    if bold {
+        element.push_str(" font-weight=\"bold\"");
+    }
+
+    if !attributes.is_empty() {
+        element.push(' ');
+        element.push_str(attributes);
+    }
+
+    let room = CARD_WIDTH - 2.0 * CARD_MARGIN;
+    if text_width(content, size, bold) > room {
+        element.push_str(&format!(" textLength=\"{}\" lengthAdjust=\"spacingAndGlyphs\"", room));
+    }
+
+    element.push_str(&format!(">{}</text>\n", escape_html(content)));
+
+    return element;
+}
+
+// How wide DejaVu Sans draws the text. Bold is a little
+// wider than the regular face held for the charts.
+fn text_width(content: &str, size: f32, bold: bool) -> f32 {
+    let font = match FontRef::try_from_slice(FONT) {
+        Ok(font) => font,
+        Err(_) => return 0.0,
+    };
+
+    let scaled = font.as_scaled(PxScale::from(size));
+    let width: f32 = content.chars().map(|c| scaled.h_advance(font.glyph_id(c))).sum();
+
+    return if bold { width * 1.1 } else { width };
+}
+
+fn busiest_channel(author: &Author, channel_id_dict: &HashMap<u64, Channel>) -> String {
+    let busiest = author
+        .messages_per_channel()
+        .into_iter().max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)));
+
+    return match busiest {
+        Some((channel_id, count)) => {
+            let name = match channel_id_dict.get(&channel_id) {
+                Some(channel) => channel.name.clone(),
+                None => channel_id.to_string(),
+            };
+
+            format!("#{} ({})", name, thousands(count as u128))
+        }
+        None => String::from("-"),
+    };
+}
+
+fn peak_hour(author: &Author) -> String {
+    let mut counts = [0u128; 24];
+    for (date, _) in &author.time_ledger {
+        counts[date.hour() as usize] += 1;
+    }
+
+    let peak = counts
+        .iter()
+        .enumerate()
+        .filter(|(_, count)| **count > 0)
+        .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(&a.0)));
+
+    return match peak {
+        Some((hour, _)) => format!("{:02}:00 to {:02}:00", hour, (hour + 1) % 24),
+        None => String::from("-"),
+    };
+}
+
+// The most days in a row with at least one message
+fn longest_streak(author: &Author) -> String {
+    let days: BTreeSet<NaiveDate> = author.time_ledger.iter().map(|(date, _)| date.date()).collect();
+
+    let mut best: Option<(NaiveDate, NaiveDate)> = None;
+    let mut current: Option<(NaiveDate, NaiveDate)> = None;
+
+    for day in days {
+        current = match current {
+            Some((start, end)) if end.succ_opt() == Some(day) => Some((start, day)),
+            _ => Some((day, day)),
+        };
+
+        let length = |streak: Option<(NaiveDate, NaiveDate)>| match streak {
+            Some((start, end)) => (end - start).num_days() + 1,
+            None => 0,
+        };
+
+        if length(current) > length(best) {
+            best = current;
+        }
+    }
+
+    return match best {
+        Some((start, end)) if start == end => format!("1 day ({})", start.format("%-d %b")),
+        Some((start, end)) => format!(
+            "{} days ({} to {})",
+            (end - start).num_days() + 1,
+            start.format("%-d %b"),
+            end.format("%-d %b")
+        ),
+        None => String::from("-"),
+    };
+}
+
+// Splits the vocabulary into words and emoji, each most used
+// first. Custom emoji are counted by name, as ":name:".
+fn favourites(author: &Author) -> (WordCounts, WordCounts) {
+    let mut words: Vec<(String, u128)> = Vec::new();
+    let mut emoji: HashMap<String, u128> = HashMap::new();
+
+    for (token, count) in sorted_words(author) {
+        let found = emoji_in(token);
+
+        if found.is_empty() {
+            if token.chars().any(|c| c.is_alphanumeric()) {
+                words.push((token.clone(), *count));
+            }
+            continue;
+        }
+
+        for e in found {
+            *emoji.entry(e).or_insert(0) += count;
+        }
+    }
+
+    let mut emoji: Vec<(String, u128)> = emoji.into_iter().collect();
+    emoji.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    return (words, emoji);
+}
+
+fn emoji_in(token: &str) -> Vec<String> {
+    // <:name:id> and <a:name:id> as sent, or :name: as the
+    // exports show them
+    let custom = token
+        .strip_prefix("<a:")
+        .or_else(|| token.strip_prefix("<:"))
+        .and_then(|rest| rest.strip_suffix('>'))
+        .and_then(|rest| rest.split(':').next())
+        .or_else(|| token.strip_prefix(':').and_then(|rest| rest.strip_suffix(':')));
+
+    if let Some(name) = custom {
+        if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return vec![format!(":{}:", name)];
+        }
+    }
+
+    // Runs of emoji characters, kept together where they are
+    // joined, modified or paired up into flags
+    let mut found: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut joined = false;
+
+    for c in token.chars() {
+        let code = c as u32;
+        let is_modifier = (0x1F3FB..=0x1F3FF).contains(&code) || code == 0xFE0F;
+        let is_flag = (0x1F1E6..=0x1F1FF).contains(&code);
+        let pairs_flag = is_flag && current.chars().count() == 1 && current.chars().all(|c| (0x1F1E6..=0x1F1FF).contains(&(c as u32)));
+
+        if code == 0x200D && !current.is_empty() {
+            current.push(c);
+            joined = true;
+        } else if is_modifier && !current.is_empty() {
+            current.push(c);
+        } else if is_emoji(code) && (joined || pairs_flag) {
+            current.push(c);
+            joined = false;
+        } else if is_emoji(code) {
+            if !current.is_empty() {
+                found.push(current);
+            }
+            current = c.to_string();
+            joined = false;
+        } else {
+            if !current.is_empty() {
+                found.push(current);
+            }
+            current = String::new();
+            joined = false;
+        }
+    }
+
+    if !current.is_empty() {
+        found.push(current);
+    }
+
+    return found;
+}
+
+fn is_emoji(code: u32) -> bool {
+    return (0x1F000..=0x1FAFF).contains(&code)
+        || (0x2600..=0x27BF).contains(&code)
+        || (0x2B00..=0x2BFF).contains(&code)
+        || (0x2300..=0x23FF).contains(&code);
+}
+
+fn list(items: &[String]) -> String {
+    if items.is_empty() {
+        return String::from("-");
+    }
+
+    return items.join("  ");
+}
+
+fn count(number: u128, name: &str) -> String {
+    if number == 1 {
+        return format!("1 {}", name);
+    }
+
+    return format!("{} {}s", thousands(number), name);
+}
+
+fn shorten(text: &str, length: usize) -> String {
+    if text.chars().count() <= length {
+        return String::from(text);
+    }
+
+    return format!("{}…", text.chars().take(length - 1).collect::<String>());
+}
+
+// Percent encodes a file name for a link, as names can hold
+// "#" and other characters that mean something in a URL
+fn encode_path(name: &str) -> String {
+    let mut encoded = String::new();
+
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+
+    return encoded;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+
+    fn author(id: u64, name: &str) -> Author {
+        let date = NaiveDateTime::parse_from_str("2023-01-05 10:00", "%Y-%m-%d %H:%M").unwrap();
+
+        let mut author = Author::new(id);
+        author.names.push(NameRecord::new(String::from(name), date));
+
+        return author;
+    }
+
+    #[test]
+    fn cards_with_the_same_name_have_the_id_added() {
+        let dir = std::env::temp_dir().join(format!("discord-statistics-wrapped-names-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = format!("{}{}", dir.to_string_lossy(), std::path::MAIN_SEPARATOR);
+
+        let authors: HashMap<u64, Author> = vec![author(222, "alice"), author(333, "alice"), author(444, "Server")]
+            .into_iter()
+            .map(|author| (author.id, author))
+            .collect();
+
+        export_wrapped(&path, 2023, &Author::new(0), &authors, &HashMap::new()).unwrap();
+
+        let mut cards: Vec<String> = fs::read_dir(format!("{}Wrapped 2023", path))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        cards.sort();
+
+        assert_eq!(
+            cards,
+            vec!["Server 444.svg", "Server.svg", "alice 333.svg", "alice.svg", "index.html"]
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
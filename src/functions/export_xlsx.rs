@@ -0,0 +1,341 @@
+use chrono::NaiveDateTime;
+use rust_xlsxwriter::{Format, Workbook, XlsxError};
+use std::collections::{HashMap, HashSet};
+
+use super::error::StatsError;
+use super::rankings::*;
+use super::structs::*;
+
+// Excel only keeps 15 significant digits of a number, which
+// isn't enough for a Discord ID, so IDs are written as text
+enum Cell {
+    Text(String),
+    Number(f64),
+    Bool(bool),
+    Date(Option<NaiveDateTime>),
+    Percent(f64),
+}
+
+// Writes "Statistics.xlsx", a workbook with these sheets:
+//
+// Server             the server totals
+// Authors            every counter for each author (and bot)
+// <metric> ranking   one sheet per ranking, as in Statistics.json
+// Top words          the 1000 most used words
+// Channels           every channel found, and whether it was included
+// #<channel>         who sent the most messages in each included channel
+pub fn export_xlsx(
+    path: &String,
+    server: &Author,
+    author_hashmap: &HashMap<u64, Author>,
+    bot_hashmap: &HashMap<u64, Author>,
+    channel_id_dict: &HashMap<u64, Channel>,
+    excluded_channels: &[Channel],
+) -> Result<(), StatsError> {
+    let path_to_export = format!("{}Statistics.xlsx", path);
+
+    let result = write_workbook(
+        &path_to_export,
+        server,
+        author_hashmap,
+        bot_hashmap,
+        channel_id_dict,
+        excluded_channels,
+    );
+
+    if let Err(error) = result {
+        return Err(StatsError::Export(format!("Could not write {}! {}", path_to_export, error)));
+    }
+
+    Ok(())
+}
+
+fn write_workbook(
+    path: &str,
+    server: &Author,
+    author_hashmap: &HashMap<u64, Author>,
+    bot_hashmap: &HashMap<u64, Author>,
+    channel_id_dict: &HashMap<u64, Channel>,
+    excluded_channels: &[Channel],
+) -> Result<(), XlsxError> {
+    let mut workbook = Workbook::new();
+    let mut sheet_names: HashSet<String> = HashSet::new();
+
+    let (first_message, last_message) = server.first_and_last();
+
+    let mut rows: Vec<Vec<Cell>> = Vec::new();
+    for (name, metric) in RANKINGS {
+        rows.push(vec![Cell::Text(ranking_title(name)), Cell::Number(metric(server) as f64)]);
+    }
+    rows.push(vec![Cell::Text(String::from("Authors")), Cell::Number(author_hashmap.len() as f64)]);
+    rows.push(vec![Cell::Text(String::from("Bots")), Cell::Number(bot_hashmap.len() as f64)]);
+    rows.push(vec![Cell::Text(String::from("First message")), Cell::Date(first_message)]);
+    rows.push(vec![Cell::Text(String::from("Last message")), Cell::Date(last_message)]);
+
+    add_sheet(&mut workbook, &mut sheet_names, "Server", &["Statistic", "Value"], &rows)?;
+
+    let everyone = members_then_bots(author_hashmap, bot_hashmap);
+
+    let mut headers = vec![String::from("Author ID"), String::from("Name"), String::from("Bot"), String::from("Ranked")];
+    headers.extend(RANKINGS.iter().map(|(name, _)| ranking_title(name)));
+    headers.extend([String::from("First message"), String::from("Last message"), String::from("Merged IDs")]);
+
+    let rows: Vec<Vec<Cell>> = everyone
+        .iter()
+        .map(|(author, is_ranked)| {
+            let (first_message, last_message) = author.first_and_last();
+            let merged_ids: Vec<String> = author.merged_ids.iter().map(|id| id.to_string()).collect();
+
+            let mut row = vec![
+                Cell::Text(author.id.to_string()),
+                Cell::Text(author.display_name().to_string()),
+                Cell::Bool(author.is_bot),
+                Cell::Bool(*is_ranked),
+            ];
+            row.extend(RANKINGS.iter().map(|(_, metric)| Cell::Number(metric(author) as f64)));
+            row.push(Cell::Date(first_message));
+            row.push(Cell::Date(last_message));
+            row.push(Cell::Text(merged_ids.join(", ")));
+
+            return row;
+        })
+        .collect();
+
+    let headers: Vec<&str> = headers.iter().map(|header| header.as_str()).collect();
+    add_sheet(&mut workbook, &mut sheet_names, "Authors", &headers, &rows)?;
+
+    for (name, metric) in RANKINGS {
+        let rows: Vec<Vec<Cell>> = ranked(author_hashmap, metric)
+            .iter()
+            .enumerate()
+            .map(|(index, author)| {
+                vec![
+                    Cell::Number((index + 1) as f64),
+                    Cell::Text(author.id.to_string()),
+                    Cell::Text(author.display_name().to_string()),
+                    Cell::Number(metric(author) as f64),
+                ]
+            })
+            .collect();
+
+        add_sheet(
+            &mut workbook,
+            &mut sheet_names,
+            &format!("{} ranking", ranking_title(name)),
+            &["Rank", "Author ID", "Name", &ranking_title(name)],
+            &rows,
+        )?;
+    }
+
+    let rows: Vec<Vec<Cell>> = sorted_words(server)
+        .iter()
+        .take(1000)
+        .enumerate()
+        .map(|(index, (word, count))| {
+            vec![
+                Cell::Number((index + 1) as f64),
+                Cell::Text(word.to_string()),
+                Cell::Number(**count as f64),
+            ]
+        })
+        .collect();
+
+    add_sheet(&mut workbook, &mut sheet_names, "Top words", &["Rank", "Word", "Count"], &rows)?;
+
+    let channel_counts = server.messages_per_channel();
+
+    let mut included: Vec<&Channel> = channel_id_dict.values().collect();
+    included.sort_by(|a, b| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+
+    let rows: Vec<Vec<Cell>> = included
+        .iter()
+        .map(|channel| (*channel, true))
+        .chain(excluded_channels.iter().map(|channel| (channel, false)))
+        .map(|(channel, is_included)| {
+            vec![
+                Cell::Text(channel.id.to_string()),
+                Cell::Text(channel.name.clone()),
+                Cell::Text(channel.category.clone()),
+                Cell::Text(channel.guild.clone()),
+                Cell::Bool(is_included),
+                Cell::Number(*channel_counts.get(&channel.id).unwrap_or(&0) as f64),
+            ]
+        })
+        .collect();
+
+    add_sheet(
+        &mut workbook,
+        &mut sheet_names,
+        "Channels",
+        &["Channel ID", "Name", "Category", "Guild", "Included", "Messages"],
+        &rows,
+    )?;
+
+    // Messages from each author in each channel
+    let mut author_counts: HashMap<u64, HashMap<u64, u64>> = HashMap::new();
+    for author in author_hashmap.values() {
+        for (channel_id, count) in author.messages_per_channel() {
+            author_counts.entry(channel_id).or_default().insert(author.id, count);
+        }
+    }
+
+    for channel in included {
+        let total = *channel_counts.get(&channel.id).unwrap_or(&0);
+
+        let mut counts: Vec<(&Author, u64)> = match author_counts.get(&channel.id) {
+            Some(counts) => counts.iter().map(|(id, count)| (&author_hashmap[id], *count)).collect(),
+            None => Vec::new(),
+        };
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.id.cmp(&b.0.id)));
+
+        let rows: Vec<Vec<Cell>> = counts
+            .iter()
+            .enumerate()
+            .map(|(index, (author, count))| {
+                vec![
+                    Cell::Number((index + 1) as f64),
+                    Cell::Text(author.id.to_string()),
+                    Cell::Text(author.display_name().to_string()),
+                    Cell::Number(*count as f64),
+                    Cell::Percent(if total == 0 { 0.0 } else { *count as f64 / total as f64 }),
+                ]
+            })
+            .collect();
+
+        add_sheet(
+            &mut workbook,
+            &mut sheet_names,
+            &format!("#{}", channel.name),
+            &["Rank", "Author ID", "Name", "Messages", "Share of channel"],
+            &rows,
+        )?;
+    }
+
+    workbook.save(path)?;
+
+    Ok(())
+}
+
+// Adds a sheet holding a single table, with the header row
+// frozen and filterable
+fn add_sheet(
+    workbook: &mut Workbook,
+    sheet_names: &mut HashSet<String>,
+    name: &str,
+    headers: &[&str],
+    rows: &[Vec<Cell>],
+) -> Result<(), XlsxError> {
+    let header_format = Format::new().set_bold();
+    let date_format = Format::new().set_num_format("yyyy-mm-dd hh:mm:ss");
+    let percent_format = Format::new().set_num_format("0.0%");
+
+    let worksheet = workbook.add_worksheet();
+    worksheet.set_name(sheet_name(name, sheet_names))?;
+
+    for (col, header) in headers.iter().enumerate() {
+        worksheet.write_string_with_format(0, col as u16, *header, &header_format)?;
+    }
+
+    for (index, row) in rows.iter().enumerate() {
+        let row_number = (index + 1) as u32;
+
+        for (col, cell) in row.iter().enumerate() {
+            let col = col as u16;
+
+            match cell {
+                Cell::Text(text) => worksheet.write_string(row_number, col, text)?,
+                Cell::Number(number) => worksheet.write_number(row_number, col, *number)?,
+                Cell::Bool(value) => worksheet.write_boolean(row_number, col, *value)?,
+                Cell::Date(Some(date)) => worksheet.write_datetime_with_format(row_number, col, date, &date_format)?,
+                Cell::Date(None) => worksheet,
+                Cell::Percent(value) => worksheet.write_number_with_format(row_number, col, *value, &percent_format)?,
+            };
+        }
+    }
+
+    worksheet.set_freeze_panes(1, 0)?;
+    worksheet.autofilter(0, 0, rows.len() as u32, (headers.len() - 1) as u16)?;
+    worksheet.autofit();
+
+    Ok(())
+}
+
+// Sheet names can't use some characters, can't start or end
+// with an apostrophe, are limited to 31 characters and have
+// to be unique, ignoring case
+fn sheet_name(name: &str, sheet_names: &mut HashSet<String>) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| if "[]:*?/\\".contains(c) { '_' } else { c })
+        .collect();
+
+    let mut number = 1;
+    loop {
+        let suffix = if number == 1 { String::new() } else { format!(" ({})", number) };
+        let kept = 31 - suffix.chars().count();
+
+        // Cut to length first, so what is left can't end up
+        // ending in an apostrophe
+        let cut: String = cleaned.chars().take(kept).collect();
+        let cut = match cut.trim_matches('\'') {
+            "" => "Sheet",
+            cut => cut,
+        };
+
+        let candidate = format!("{}{}", cut, suffix);
+
+        if sheet_names.insert(candidate.to_lowercase()) {
+            return candidate;
+        }
+
+        number += 1;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_names_are_cut_to_31_characters() {
+        let mut sheet_names: HashSet<String> = HashSet::new();
+        let name = "a-very-long-channel-name-for-announcements";
+
+        let first = sheet_name(name, &mut sheet_names);
+        let second = sheet_name(name, &mut sheet_names);
+
+        assert_eq!(first, "a-very-long-channel-name-for-an");
+        assert_eq!(second, "a-very-long-channel-name-fo (2)");
+        assert!(second.chars().count() <= 31);
+    }
+
+    #[test]
+    fn names_differing_only_by_case_are_told_apart() {
+        let mut sheet_names: HashSet<String> = HashSet::new();
+
+        assert_eq!(sheet_name("General", &mut sheet_names), "General");
+        assert_eq!(sheet_name("general", &mut sheet_names), "general (2)");
+        assert_eq!(sheet_name("GENERAL", &mut sheet_names), "GENERAL (3)");
+    }
+
+    #[test]
+    fn forbidden_characters_and_apostrophes_are_cleaned() {
+        let mut sheet_names: HashSet<String> = HashSet::new();
+
+        assert_eq!(sheet_name("[]:*?/\\", &mut sheet_names), "_______");
+        assert_eq!(sheet_name("'''", &mut sheet_names), "Sheet");
+        assert_eq!(sheet_name("''", &mut sheet_names), "Sheet (2)");
+    }
+
+    #[test]
+    fn names_cut_to_length_do_not_end_in_an_apostrophe() {
+        let mut sheet_names: HashSet<String> = HashSet::new();
+        let name = format!("{}'s channel", "b".repeat(30));
+
+        let sheet = sheet_name(&name, &mut sheet_names);
+
+        assert_eq!(sheet, "b".repeat(30));
+        assert!(!sheet.ends_with('\''));
+    }
+}
@@ -0,0 +1,535 @@
+use chrono::*;
+use regex::Regex;
+use ::serde::{Deserialize, Serialize};
+
+use super::structs::*;
+
+// An inclusive start and exclusive end for which messages
+// are kept when importing. Either side may be left open.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct DateRange {
+    pub start: Option<NaiveDateTime>,
+    pub end: Option<NaiveDateTime>,
+}
+
+impl DateRange {
+    pub fn all() -> DateRange {
+        return DateRange {
+            start: None,
+            end: None,
+        };
+    }
+
+    // Creates a range covering the last span of time given,
+    // such as "90d", "12w", "6mo", "1y", "36h" or "90 days".
+    // Months and years are calendar months, so "1mo" on the
+    // 31st of March starts on the 28th or 29th of February.
+    pub fn last(value: &str, now: NaiveDateTime) -> Result<DateRange, String> {
+        let value = value.trim().to_lowercase();
+        let split_at = value
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(value.len());
+
+        let amount: u32 = match value[..split_at].parse() {
+            Ok(amount) => amount,
+            Err(_) => return Err(format!("\"{}\" doesn't start with a number", value)),
+        };
+
+        let start = match value[split_at..].trim() {
+            "min" | "mins" | "minute" | "minutes" => TimeDelta::try_minutes(amount as i64).and_then(|span| now.checked_sub_signed(span)),
+            "h" | "hour" | "hours" => TimeDelta::try_hours(amount as i64).and_then(|span| now.checked_sub_signed(span)),
+            "" | "d" | "day" | "days" => now.checked_sub_days(Days::new(amount as u64)),
+            "w" | "week" | "weeks" => now.checked_sub_days(Days::new(amount as u64 * 7)),
+            "mo" | "month" | "months" => now.checked_sub_months(Months::new(amount)),
+            "y" | "year" | "years" => amount.checked_mul(12).and_then(|months| now.checked_sub_months(Months::new(months))),
+            "m" => return Err(String::from("\"m\" could be minutes or months, so use \"min\" or \"mo\"")),
+            unit => return Err(format!("Unknown unit \"{}\", expected min, h, d, w, mo or y", unit)),
+        };
+
+        return match start {
+            Some(start) => Ok(DateRange {
+                start: Some(start),
+                end: None,
+            }),
+            None => Err(format!("\"{}\" goes back further than dates can", value)),
+        };
+    }
+
+    // Parses the lower bound of a range. Partial dates start
+    // at the very beginning of the year, month or day given.
+    pub fn parse_start(value: &str) -> Option<NaiveDateTime> {
+        if let Some((datetime, _)) = parse_datetime(value) {
+            return Some(datetime);
+        }
+
+        let (date, _) = parse_partial_date(value)?;
+
+        return date.and_hms_opt(0, 0, 0);
+    }
+
+    // Parses the upper bound of a range. Partial dates and
+    // times include the whole of the year, month, day, minute
+    // or second given, so "--end 2021" keeps every message
+    // sent in 2021, and "--end 2021-03-01T12:00" keeps those
+    // sent at 12:00:30.
+    pub fn parse_end(value: &str) -> Option<NaiveDateTime> {
+        if let Some((datetime, precision)) = parse_datetime(value) {
+            let span = match precision {
+                DatePrecision::Minute => TimeDelta::try_minutes(1)?,
+                DatePrecision::Second => TimeDelta::try_seconds(1)?,
+                _ => TimeDelta::nanoseconds(1),
+            };
+
+            return datetime.checked_add_signed(span);
+        }
+
+        let (date, precision) = parse_partial_date(value)?;
+
+        let next = match precision {
+            DatePrecision::Year => NaiveDate::from_ymd_opt(date.year() + 1, 1, 1)?,
+            DatePrecision::Month => date.with_day(1)?.checked_add_months(Months::new(1))?,
+            _ => date.succ_opt()?,
+        };
+
+        return next.and_hms_opt(0, 0, 0);
+    }
+
+    // The last moment inside the range, for showing the end
+    // as a date rather than as the start of the next one
+    pub fn end_inclusive(&self) -> Option<NaiveDateTime> {
+        return self.end.map(|end| end - TimeDelta::nanoseconds(1));
+    }
+
+    pub fn contains(&self, date: &NaiveDateTime) -> bool {
+        if let Some(start) = self.start {
+            if *date < start {
+                return false;
+            }
+        }
+
+        if let Some(end) = self.end {
+            if *date >= end {
+                return false;
+            }
+        }
+
+        return true;
+    }
+}
+
+// How much of a date or time was given
+enum DatePrecision {
+    Year,
+    Month,
+    Day,
+    Minute,
+    Second,
+    Fraction,
+}
+
+fn parse_datetime(value: &str) -> Option<(NaiveDateTime, DatePrecision)> {
+    let value = value.trim();
+
+    let formats = [
+        ("%Y-%m-%dT%H:%M:%S%.f", DatePrecision::Fraction),
+        ("%Y-%m-%dT%H:%M:%S", DatePrecision::Second),
+        ("%Y-%m-%dT%H:%M", DatePrecision::Minute),
+        ("%Y-%m-%d %H:%M:%S%.f", DatePrecision::Fraction),
+        ("%Y-%m-%d %H:%M:%S", DatePrecision::Second),
+        ("%Y-%m-%d %H:%M", DatePrecision::Minute),
+    ];
+
+    for (format, precision) in formats {
+        // "%.f" also matches no fraction at all, so leave
+        // those to the formats without one
+        if let DatePrecision::Fraction = precision {
+            if !value.contains('.') {
+                continue;
+            }
+        }
+
+        if let Ok(datetime) = NaiveDateTime::parse_from_str(value, format) {
+            return Some((datetime, precision));
+        }
+    }
+
+    // Full RFC 3339 timestamps carry an offset, so they are
+    // moved into local time, which is the clock --last and
+    // the exports made on this machine go by
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        let precision = if value.contains('.') {
+            DatePrecision::Fraction
+        } else {
+            DatePrecision::Second
+        };
+
+        return Some((datetime.with_timezone(&Local).naive_local(), precision));
+    }
+
+    return None;
+}
+
+fn parse_partial_date(value: &str) -> Option<(NaiveDate, DatePrecision)> {
+    let parts: Vec<&str> = value.trim().split('-').collect();
+
+    let year: i32 = parts.first()?.parse().ok()?;
+
+    return match parts.len() {
+        1 => Some((NaiveDate::from_ymd_opt(year, 1, 1)?, DatePrecision::Year)),
+        2 => Some((
+            NaiveDate::from_ymd_opt(year, parts[1].parse().ok()?, 1)?,
+            DatePrecision::Month,
+        )),
+        3 => Some((
+            NaiveDate::from_ymd_opt(year, parts[1].parse().ok()?, parts[2].parse().ok()?)?,
+            DatePrecision::Day,
+        )),
+        _ => None,
+    };
+}
+
+// A single entry of a channel filter. Entries are given as
+// a channel ID, a channel name, a glob pattern over names
+// (such as "*-log*"), or "category:NAME" where NAME may
+// also be a glob pattern.
+#[derive(Clone)]
+pub enum ChannelPattern {
+    Id(u64),
+    Name(Regex),
+    Category(Regex),
+}
+
+impl ChannelPattern {
+    pub fn parse(value: &str) -> Option<ChannelPattern> {
+        let value = value.trim();
+
+        if value.is_empty() {
+            return None;
+        }
+
+        if let Some(category) = value.strip_prefix("category:") {
+            return Some(ChannelPattern::Category(glob_to_regex(category.trim())?));
+        }
+
+        if let Ok(id) = value.parse::<u64>() {
+            return Some(ChannelPattern::Id(id));
+        }
+
+        return Some(ChannelPattern::Name(glob_to_regex(value.trim_start_matches('#'))?));
+    }
+
+    pub fn matches(&self, channel: &Channel) -> bool {
+        return match self {
+            ChannelPattern::Id(id) => channel.id == *id,
+            ChannelPattern::Name(pattern) => pattern.is_match(&channel.name),
+            ChannelPattern::Category(pattern) => pattern.is_match(&channel.category),
+        };
+    }
+}
+
+// Decides which channel exports are scraped at all. When
+// anything is included, only those channels are kept; any
+// channel matching an exclusion is always dropped.
+#[derive(Clone, Default)]
+pub struct ChannelFilter {
+    pub include: Vec<ChannelPattern>,
+    pub exclude: Vec<ChannelPattern>,
+}
+
+impl ChannelFilter {
+    pub fn all() -> ChannelFilter {
+        return ChannelFilter {
+            include: Vec::new(),
+            exclude: Vec::new(),
+        };
+    }
+
+    // Parses a comma separated list of patterns
+    pub fn parse_list(value: &str) -> Option<Vec<ChannelPattern>> {
+        return value
+            .split(',')
+            .filter(|s| !s.trim().is_empty())
+            .map(ChannelPattern::parse)
+            .collect();
+    }
+
+    pub fn allows(&self, channel: &Channel) -> bool {
+        if !self.include.is_empty() && !self.include.iter().any(|p| p.matches(channel)) {
+            return false;
+        }
+
+        return !self.exclude.iter().any(|p| p.matches(channel));
+    }
+}
+
+// Converts a shell style glob ("*" and "?") into a whole,
+// case insensitive match
+fn glob_to_regex(glob: &str) -> Option<Regex> {
+    let mut pattern = String::from("(?i)^");
+
+    for c in glob.chars() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            _ => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    pattern.push('$');
+
+    return Regex::new(&pattern).ok();
+}
+
+// What to do with messages sent by bots and webhooks
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum BotMode {
+    // Treat bots like any other member
+    Include,
+    // Drop their messages while importing
+    Exclude,
+    // Keep them out of the rankings and server totals,
+    // but list their statistics on their own
+    Separate,
+}
+
+impl BotMode {
+    pub fn parse(value: &str) -> Option<BotMode> {
+        return match value.trim().to_lowercase().as_str() {
+            "include" => Some(BotMode::Include),
+            "exclude" => Some(BotMode::Exclude),
+            "separate" => Some(BotMode::Separate),
+            _ => None,
+        };
+    }
+}
+
+// Decides which authors are bots. The export metadata is
+// trusted when present, on top of a list of known bot IDs
+// and, unless turned off, the "#0000" discriminator that
+// Discord gives to webhook messages.
+#[derive(Clone)]
+pub struct BotFilter {
+    pub mode: BotMode,
+    pub ids: Vec<u64>,
+    pub heuristics: bool,
+}
+
+impl BotFilter {
+    pub fn new() -> BotFilter {
+        return BotFilter {
+            mode: BotMode::Include,
+            ids: Vec::new(),
+            heuristics: true,
+        };
+    }
+
+    // Parses a comma separated list of author IDs
+    pub fn parse_ids(value: &str) -> Option<Vec<u64>> {
+        return value
+            .split(',')
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| s.trim().parse::<u64>().ok())
+            .collect();
+    }
+
+    pub fn is_bot(&self, msg: &Message) -> bool {
+        if msg.is_bot || self.ids.contains(&msg.author_id) {
+            return true;
+        }
+
+        return self.heuristics && msg.author_name.ends_with("#0000");
+    }
+}
+
+impl Default for BotFilter {
+    fn default() -> BotFilter {
+        return BotFilter::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datetime(value: &str) -> NaiveDateTime {
+        return NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f").unwrap();
+    }
+
+    #[test]
+    fn last_counts_back_from_now() {
+        let now = datetime("2021-03-31 12:00:00");
+
+        assert_eq!(DateRange::last("90d", now).unwrap().start, Some(datetime("2020-12-31 12:00:00")));
+        assert_eq!(DateRange::last("2 weeks", now).unwrap().start, Some(datetime("2021-03-17 12:00:00")));
+        assert_eq!(DateRange::last("36h", now).unwrap().start, Some(datetime("2021-03-30 00:00:00")));
+        assert_eq!(DateRange::last("90min", now).unwrap().start, Some(datetime("2021-03-31 10:30:00")));
+        assert_eq!(DateRange::last("7", now).unwrap().start, Some(datetime("2021-03-24 12:00:00")));
+        assert_eq!(DateRange::last("90d", now).unwrap().end, None);
+    }
+
+    #[test]
+    fn last_uses_calendar_months_and_years() {
+        let now = datetime("2021-03-31 12:00:00");
+
+        assert_eq!(DateRange::last("1mo", now).unwrap().start, Some(datetime("2021-02-28 12:00:00")));
+        assert_eq!(DateRange::last("6 months", now).unwrap().start, Some(datetime("2020-09-30 12:00:00")));
+
+        let leap_day = datetime("2024-02-29 00:00:00");
+        assert_eq!(DateRange::last("1y", leap_day).unwrap().start, Some(datetime("2023-02-28 00:00:00")));
+    }
+
+    #[test]
+    fn last_rejects_bad_spans() {
+        let now = datetime("2021-03-31 12:00:00");
+
+        assert!(DateRange::last("6m", now).is_err());
+        assert!(DateRange::last("d", now).is_err());
+        assert!(DateRange::last("-5d", now).is_err());
+        assert!(DateRange::last("5 fortnights", now).is_err());
+        assert!(DateRange::last("999999999y", now).is_err());
+        assert!(DateRange::last("4294967295d", now).is_err());
+        assert!(DateRange::last("99999999999999999999d", now).is_err());
+    }
+
+    #[test]
+    fn start_is_the_beginning_of_what_was_given() {
+        assert_eq!(DateRange::parse_start("2021"), Some(datetime("2021-01-01 00:00:00")));
+        assert_eq!(DateRange::parse_start("2021-03"), Some(datetime("2021-03-01 00:00:00")));
+        assert_eq!(DateRange::parse_start("2021-03-05"), Some(datetime("2021-03-05 00:00:00")));
+        assert_eq!(DateRange::parse_start("2021-03-05T12:30"), Some(datetime("2021-03-05 12:30:00")));
+        assert_eq!(DateRange::parse_start("2021-03-05 12:30:15"), Some(datetime("2021-03-05 12:30:15")));
+    }
+
+    #[test]
+    fn end_includes_the_whole_of_what_was_given() {
+        assert_eq!(DateRange::parse_end("2021"), Some(datetime("2022-01-01 00:00:00")));
+        assert_eq!(DateRange::parse_end("2021-12"), Some(datetime("2022-01-01 00:00:00")));
+        assert_eq!(DateRange::parse_end("2021-02"), Some(datetime("2021-03-01 00:00:00")));
+        assert_eq!(DateRange::parse_end("2021-02-28"), Some(datetime("2021-03-01 00:00:00")));
+        assert_eq!(DateRange::parse_end("2021-03-01T12:00"), Some(datetime("2021-03-01 12:01:00")));
+        assert_eq!(DateRange::parse_end("2021-03-01 12:00:30"), Some(datetime("2021-03-01 12:00:31")));
+        assert_eq!(
+            DateRange::parse_end("2021-03-01T12:00:30.250"),
+            Some(datetime("2021-03-01 12:00:30.250000001"))
+        );
+
+        let range = DateRange {
+            start: None,
+            end: DateRange::parse_end("2021-03-01T12:00"),
+        };
+        assert!(range.contains(&datetime("2021-03-01 12:00:30")));
+        assert!(!range.contains(&datetime("2021-03-01 12:01:00")));
+    }
+
+    #[test]
+    fn offsets_are_moved_into_local_time() {
+        let expected = DateTime::parse_from_rfc3339("2021-03-01T12:00:00+05:00")
+            .unwrap()
+            .with_timezone(&Local)
+            .naive_local();
+
+        assert_eq!(DateRange::parse_start("2021-03-01T12:00:00+05:00"), Some(expected));
+        assert_eq!(
+            DateRange::parse_end("2021-03-01T12:00:00+05:00"),
+            Some(expected + TimeDelta::try_seconds(1).unwrap())
+        );
+    }
+
+    #[test]
+    fn bad_dates_are_rejected() {
+        assert_eq!(DateRange::parse_start("yesterday"), None);
+        assert_eq!(DateRange::parse_start("2021-13"), None);
+        assert_eq!(DateRange::parse_end("2021-02-30"), None);
+        assert_eq!(DateRange::parse_end("2021-01-01-01"), None);
+        assert_eq!(DateRange::parse_end(""), None);
+    }
+
+    #[test]
+    fn ranges_are_inclusive_at_the_start_and_exclusive_at_the_end() {
+        let range = DateRange {
+            start: DateRange::parse_start("2021"),
+            end: DateRange::parse_end("2021"),
+        };
+
+        assert!(range.contains(&datetime("2021-01-01 00:00:00")));
+        assert!(range.contains(&datetime("2021-12-31 23:59:59.999")));
+        assert!(!range.contains(&datetime("2022-01-01 00:00:00")));
+        assert!(!range.contains(&datetime("2020-12-31 23:59:59")));
+        assert_eq!(range.end_inclusive(), Some(datetime("2021-12-31 23:59:59.999999999")));
+
+        assert!(DateRange::all().contains(&datetime("1970-01-01 00:00:00")));
+        assert_eq!(DateRange::all().end_inclusive(), None);
+    }
+
+    #[test]
+    fn channel_patterns() {
+        let channel = Channel {
+            id: 555,
+            name: String::from("bot-log"),
+            category: String::from("Logs"),
+            guild: String::from("Test Guild"),
+        };
+
+        let matches = |value: &str| ChannelPattern::parse(value).unwrap().matches(&channel);
+
+        assert!(matches("555"));
+        assert!(!matches("556"));
+        assert!(matches("#bot-log"));
+        assert!(matches("BOT-*"));
+        assert!(matches("bot?log"));
+        assert!(!matches("bot"));
+        assert!(matches("category:logs"));
+        assert!(!matches("category:Text*"));
+        assert!(ChannelPattern::parse(" ").is_none());
+    }
+
+    #[test]
+    fn channel_filters_drop_exclusions_over_inclusions() {
+        let channel = |id: u64, name: &str| Channel {
+            id: id,
+            name: String::from(name),
+            category: String::new(),
+            guild: String::new(),
+        };
+
+        let filter = ChannelFilter {
+            include: ChannelFilter::parse_list("general,*-log").unwrap(),
+            exclude: ChannelFilter::parse_list("555").unwrap(),
+        };
+
+        assert!(filter.allows(&channel(111, "general")));
+        assert!(filter.allows(&channel(112, "mod-log")));
+        assert!(!filter.allows(&channel(555, "bot-log")));
+        assert!(!filter.allows(&channel(666, "memes")));
+        assert!(ChannelFilter::all().allows(&channel(666, "memes")));
+    }
+
+    #[test]
+    fn bots_are_found_from_metadata_ids_and_webhooks() {
+        let message = |id: u64, name: &str, is_bot: bool| Message {
+            author_id: id,
+            channel_id: 1,
+            author_name: String::from(name),
+            date: datetime("2021-01-01 00:00:00"),
+            content: String::new(),
+            attachments: Vec::new(),
+            reactions: std::collections::HashMap::new(),
+            is_bot: is_bot,
+        };
+
+        let mut filter = BotFilter::new();
+        filter.ids = BotFilter::parse_ids("777, 778").unwrap();
+
+        assert!(filter.is_bot(&message(1, "MEE6#4876", true)));
+        assert!(filter.is_bot(&message(777, "Helper#1234", false)));
+        assert!(filter.is_bot(&message(2, "Hooky#0000", false)));
+        assert!(!filter.is_bot(&message(3, "alice#1234", false)));
+
+        filter.heuristics = false;
+        assert!(!filter.is_bot(&message(2, "Hooky#0000", false)));
+
+        assert_eq!(BotFilter::parse_ids("1,x"), None);
+        assert_eq!(BotMode::parse(" Separate "), Some(BotMode::Separate));
+        assert_eq!(BotMode::parse("hide"), None);
+    }
+}
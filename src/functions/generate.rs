@@ -0,0 +1,589 @@
+use chrono::{Datelike, Duration, NaiveDateTime, Weekday};
+use csv::Writer;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+
+use super::error::StatsError;
+use super::filters::DateRange;
+use super::query::tokenize;
+use super::structs::DATE_FORMAT;
+
+pub const GENERATE_HELP: &str = "\
+Terms (all optional):
+  channels=N             number of channels (default 6)
+  authors=N              number of members (default 50)
+  bots=N                 number of bots and webhooks, on top of the members (default 2)
+  messages=N             number of messages across every channel (default 10000)
+  from=DATE  to=DATE     dates the messages are spread over (default the year 2021)
+  pattern=P              when people are active: flat, daytime, evening or night (default evening)
+  attachments=RATE       share of messages with an attachment, 0 to 1 (default 0.05)
+  reactions=RATE         share of messages with reactions, 0 to 1 (default 0.1)
+  renames=RATE           share of members who change their name part way (default 0.1)
+  formats=csv,json       which export formats to write (default csv). Given both, each is written
+                         into a csv or json folder of its own, so either can be read on its own
+  seed=N                 the same seed always generates the same exports (default 1)
+";
+
+const CHANNEL_NAMES: [&str; 12] = [
+    "general", "memes", "gaming", "music", "off-topic", "art", "pets", "food", "movies", "homework",
+    "announcements", "bot-commands",
+];
+
+const CATEGORIES: [&str; 3] = ["Text Channels", "Hangout", "Projects"];
+
+const NAMES: [&str; 16] = [
+    "alex", "sam", "jordan", "taylor", "casey", "riley", "morgan", "quinn", "avery", "jamie", "robin",
+    "charlie", "drew", "skyler", "rowan", "reese",
+];
+
+// Ordered from most to least used, as the words are picked
+// with weights falling off down the list
+const WORDS: [&str; 80] = [
+    "the", "i", "you", "a", "to", "and", "it", "is", "that", "lol", "of", "in", "what", "this", "no",
+    "yeah", "do", "for", "we", "have", "just", "so", "on", "but", "like", "me", "was", "are", "not",
+    "be", "can", "my", "get", "with", "how", "they", "ok", "if", "at", "good", "all", "when", "one",
+    "know", "think", "why", "game", "time", "now", "people", "going", "really", "tomorrow", "play",
+    "today", "nice", "thanks", "wait", "anyone", "server", "song", "pizza", "homework", "cat",
+    "movie", "watching", "discord", "later", "weekend", "lmao", "meeting", "bruh", "maybe", "sure",
+    "again", "friday", "honestly", "chaos", "vibes", "legendary",
+];
+
+const EMOJIS: [&str; 8] = ["👍", "😂", "❤️", "🔥", "😮", "😢", "🎉", "👀"];
+
+// When in the day people send their messages
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ActivityPattern {
+    Flat,
+    Daytime,
+    Evening,
+    Night,
+}
+
+impl ActivityPattern {
+    pub fn parse(value: &str) -> Option<ActivityPattern> {
+        return match value.to_lowercase().as_str() {
+            "flat" => Some(ActivityPattern::Flat),
+            "daytime" | "day" => Some(ActivityPattern::Daytime),
+            "evening" => Some(ActivityPattern::Evening),
+            "night" => Some(ActivityPattern::Night),
+            _ => None,
+        };
+    }
+
+    // How likely a message is to be sent in each hour
+    fn hour_weights(&self) -> [u32; 24] {
+        return match self {
+            ActivityPattern::Flat => [1; 24],
+            ActivityPattern::Daytime => [
+                1, 1, 1, 1, 1, 1, 2, 4, 6, 8, 9, 9, 10, 10, 9, 9, 8, 7, 6, 5, 4, 3, 2, 1,
+            ],
+            ActivityPattern::Evening => [
+                4, 3, 2, 1, 1, 1, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5, 6, 8, 9, 10, 10, 9, 8, 6,
+            ],
+            ActivityPattern::Night => [
+                9, 10, 10, 9, 7, 5, 3, 1, 1, 1, 1, 1, 2, 2, 2, 3, 3, 4, 4, 5, 6, 7, 8, 9,
+            ],
+        };
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    fn extension(&self) -> &str {
+        return match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+        };
+    }
+}
+
+pub struct GeneratorConfig {
+    pub channels: usize,
+    pub authors: usize,
+    pub bots: usize,
+    pub messages: usize,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub pattern: ActivityPattern,
+    pub attachment_rate: f64,
+    pub reaction_rate: f64,
+    pub rename_rate: f64,
+    pub formats: Vec<ExportFormat>,
+    pub seed: u64,
+}
+
+impl GeneratorConfig {
+    pub fn parse(text: &str) -> Result<GeneratorConfig, String> {
+        return GeneratorConfig::from_terms(&tokenize(text));
+    }
+
+    pub fn from_terms(terms: &[String]) -> Result<GeneratorConfig, String> {
+        let mut config = GeneratorConfig {
+            channels: 6,
+            authors: 50,
+            bots: 2,
+            messages: 10000,
+            start: DateRange::parse_start("2021").unwrap(),
+            end: DateRange::parse_end("2021").unwrap(),
+            pattern: ActivityPattern::Evening,
+            attachment_rate: 0.05,
+            reaction_rate: 0.1,
+            rename_rate: 0.1,
+            formats: vec![ExportFormat::Csv],
+            seed: 1,
+        };
+
+        for term in terms {
+            let (key, value) = match term.find('=') {
+                Some(index) => (&term[..index], &term[index + 1..]),
+                None => return Err(format!("Expected KEY=VALUE, found \"{}\"", term)),
+            };
+
+            match key.to_lowercase().as_str() {
+                "channels" => config.channels = parse_number(value)?,
+                "authors" => config.authors = parse_number(value)?,
+                "bots" => config.bots = parse_number(value)?,
+                "messages" => config.messages = parse_number(value)?,
+                "seed" => config.seed = parse_number(value)? as u64,
+                "from" => match DateRange::parse_start(value) {
+                    Some(start) => config.start = start,
+                    None => return Err(format!("Could not parse date \"{}\"", value)),
+                },
+                "to" => match DateRange::parse_end(value) {
+                    Some(end) => config.end = end,
+                    None => return Err(format!("Could not parse date \"{}\"", value)),
+                },
+                "pattern" => match ActivityPattern::parse(value) {
+                    Some(pattern) => config.pattern = pattern,
+                    None => return Err(format!("Unknown activity pattern \"{}\"", value)),
+                },
+                "attachments" => config.attachment_rate = parse_rate(value)?,
+                "reactions" => config.reaction_rate = parse_rate(value)?,
+                "renames" => config.rename_rate = parse_rate(value)?,
+                "formats" => {
+                    config.formats.clear();
+
+                    for format in value.split(',') {
+                        let format = match format.to_lowercase().as_str() {
+                            "csv" => ExportFormat::Csv,
+                            "json" => ExportFormat::Json,
+                            _ => return Err(format!("Unknown format \"{}\"", format)),
+                        };
+
+                        if !config.formats.contains(&format) {
+                            config.formats.push(format);
+                        }
+                    }
+                }
+                _ => return Err(format!("Unknown term \"{}\"", key)),
+            }
+        }
+
+        if config.channels == 0 || config.authors + config.bots == 0 {
+            return Err(String::from("There must be at least one channel and one author"));
+        }
+
+        if config.end <= config.start {
+            return Err(String::from("The end date must come after the start date"));
+        }
+
+        return Ok(config);
+    }
+}
+
+fn parse_number(value: &str) -> Result<usize, String> {
+    return value
+        .parse()
+        .map_err(|_| format!("Could not parse number \"{}\"", value));
+}
+
+fn parse_rate(value: &str) -> Result<f64, String> {
+    return match value.parse::<f64>() {
+        Ok(rate) if (0.0..=1.0).contains(&rate) => Ok(rate),
+        _ => Err(format!("\"{}\" is not a rate between 0 and 1", value)),
+    };
+}
+
+struct FakeChannel {
+    id: u64,
+    name: String,
+    category: String,
+}
+
+struct FakeAuthor {
+    id: u64,
+    discriminator: String,
+    is_bot: bool,
+    // Names used, and the date each one was taken up
+    names: Vec<(NaiveDateTime, String)>,
+    // No messages are sent before this
+    joined: NaiveDateTime,
+}
+
+impl FakeAuthor {
+    fn name_at(&self, date: NaiveDateTime) -> &str {
+        let mut name = &self.names[0].1;
+
+        for (since, later_name) in &self.names {
+            if *since <= date {
+                name = later_name;
+            }
+        }
+
+        return name;
+    }
+}
+
+struct FakeMessage {
+    id: u64,
+    author: usize,
+    date: NaiveDateTime,
+    content: String,
+    attachments: Vec<String>,
+    reactions: Vec<(String, u32)>,
+}
+
+// A random ID in the range of real Discord snowflakes
+fn snowflake(rng: &mut StdRng) -> u64 {
+    return rng.gen_range(100_000_000_000_000_000..999_999_999_999_999_999);
+}
+
+// Weights falling off like real activity, where a few
+// members send most of the messages
+fn zipf_weights(count: usize) -> Vec<f64> {
+    return (0..count).map(|rank| 1.0 / (rank as f64 + 1.0).powf(1.1)).collect();
+}
+
+// Writes made up DiscordChatExporter exports into the
+// folder, one file per channel and format, returning the
+// paths written
+pub fn generate_exports(dir: &str, config: &GeneratorConfig) -> Result<Vec<String>, StatsError> {
+    if let Err(error) = fs::create_dir_all(dir) {
+        return Err(StatsError::Io(format!("Could not create {}! {}", dir, error)));
+    }
+
+    // Both formats of a channel in one folder would be read
+    // as the same channel twice, so each gets its own
+    let mut format_dirs: HashMap<ExportFormat, String> = HashMap::new();
+    for format in &config.formats {
+        let format_dir = if config.formats.len() > 1 {
+            format!("{}{}{}", dir, std::path::MAIN_SEPARATOR, format.extension())
+        } else {
+            String::from(dir)
+        };
+
+        if let Err(error) = fs::create_dir_all(&format_dir) {
+            return Err(StatsError::Io(format!("Could not create {}! {}", format_dir, error)));
+        }
+
+        format_dirs.insert(*format, format_dir);
+    }
+
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let guild = "Generated Guild";
+    let span = config.end - config.start;
+
+    let channels: Vec<FakeChannel> = (0..config.channels)
+        .map(|index| {
+            let base = CHANNEL_NAMES[index % CHANNEL_NAMES.len()];
+            let name = if index < CHANNEL_NAMES.len() {
+                String::from(base)
+            } else {
+                format!("{}-{}", base, index / CHANNEL_NAMES.len() + 1)
+            };
+
+            FakeChannel {
+                id: snowflake(&mut rng),
+                name: name,
+                category: String::from(CATEGORIES[index % CATEGORIES.len()]),
+            }
+        })
+        .collect();
+
+    let mut authors: Vec<FakeAuthor> = Vec::new();
+
+    for index in 0..config.authors + config.bots {
+        let is_bot = index >= config.authors;
+
+        let first_name = if is_bot {
+            format!("HelperBot{}", index - config.authors + 1)
+        } else {
+            format!("{}{}", NAMES[index % NAMES.len()], index / NAMES.len() + 1)
+        };
+
+        // Most people are there from the start, the rest
+        // join at some point along the way
+        let joined = if is_bot || rng.gen_bool(0.6) {
+            config.start
+        } else {
+            config.start + Duration::seconds(rng.gen_range(0..span.num_seconds().max(1)) * 4 / 5)
+        };
+
+        let mut names = vec![(config.start, first_name.clone())];
+
+        if !is_bot && rng.gen_bool(config.rename_rate) {
+            let remaining = (config.end - joined).num_seconds().max(1);
+            let since = joined + Duration::seconds(rng.gen_range(0..remaining));
+            names.push((since, format!("{}_{}", first_name, WORDS[rng.gen_range(40..WORDS.len())])));
+        }
+
+        authors.push(FakeAuthor {
+            id: snowflake(&mut rng),
+            // Webhooks carry the #0000 discriminator
+            discriminator: if is_bot {
+                String::from("0000")
+            } else {
+                format!("{:04}", rng.gen_range(1..10000))
+            },
+            is_bot: is_bot,
+            names: names,
+            joined: joined,
+        });
+    }
+
+    let channel_picker = WeightedIndex::new(zipf_weights(channels.len())).unwrap();
+    let author_picker = WeightedIndex::new(zipf_weights(authors.len())).unwrap();
+    let word_picker = WeightedIndex::new(zipf_weights(WORDS.len())).unwrap();
+    let hour_picker = WeightedIndex::new(config.pattern.hour_weights()).unwrap();
+
+    let mut channel_messages: Vec<Vec<FakeMessage>> = channels.iter().map(|_| Vec::new()).collect();
+
+    for _ in 0..config.messages {
+        let channel = channel_picker.sample(&mut rng);
+        let author = author_picker.sample(&mut rng);
+
+        // Pick a day after they joined, a bit more likely to
+        // be at the weekend, then an hour by the pattern
+        let days = (config.end - authors[author].joined).num_days().max(1);
+        let mut day = authors[author].joined.date() + Duration::days(rng.gen_range(0..days));
+        if matches!(day.weekday(), Weekday::Mon | Weekday::Tue | Weekday::Wed | Weekday::Thu) && rng.gen_bool(0.2) {
+            day = authors[author].joined.date() + Duration::days(rng.gen_range(0..days));
+        }
+
+        let date = day.and_hms_opt(hour_picker.sample(&mut rng) as u32, rng.gen_range(0..60), rng.gen_range(0..60));
+        let date = match date {
+            Some(date) if date >= config.start && date < config.end => date,
+            _ => config.start,
+        };
+
+        let length = rng.gen_range(1..16);
+        let mut words: Vec<&str> = (0..length).map(|_| WORDS[word_picker.sample(&mut rng)]).collect();
+        if rng.gen_bool(0.1) {
+            words.push("?");
+        }
+        let content = words.join(" ").replace(" ?", "?");
+
+        let message_id = snowflake(&mut rng);
+
+        let mut attachments: Vec<String> = Vec::new();
+        if rng.gen_bool(config.attachment_rate) {
+            attachments.push(format!(
+                "https://cdn.discordapp.com/attachments/{}/{}/image{}.png",
+                channels[channel].id,
+                message_id,
+                rng.gen_range(0..1000)
+            ));
+        }
+
+        let mut reactions: Vec<(String, u32)> = Vec::new();
+        if rng.gen_bool(config.reaction_rate) {
+            for _ in 0..rng.gen_range(1..4) {
+                let emoji = EMOJIS[rng.gen_range(0..EMOJIS.len())];
+                if !reactions.iter().any(|(e, _)| e == emoji) {
+                    reactions.push((String::from(emoji), rng.gen_range(1..8)));
+                }
+            }
+        }
+
+        channel_messages[channel].push(FakeMessage {
+            id: message_id,
+            author: author,
+            date: date,
+            content: content,
+            attachments: attachments,
+            reactions: reactions,
+        });
+    }
+
+    let mut paths: Vec<String> = Vec::new();
+
+    for (channel, messages) in channels.iter().zip(channel_messages.iter_mut()) {
+        messages.sort_by_key(|message| message.date);
+
+        for format in &config.formats {
+            let file_stem = format!(
+                "{}{}{} - {} - {} [{}]",
+                format_dirs[format],
+                std::path::MAIN_SEPARATOR,
+                guild,
+                channel.category,
+                channel.name,
+                channel.id
+            );
+
+            let path = match format {
+                ExportFormat::Csv => {
+                    let path = format!("{}.csv", file_stem);
+                    write_csv(&path, messages, &authors)?;
+                    path
+                }
+                ExportFormat::Json => {
+                    let path = format!("{}.json", file_stem);
+                    write_json(&path, guild, channel, messages, &authors)?;
+                    path
+                }
+            };
+
+            paths.push(path);
+        }
+    }
+
+    return Ok(paths);
+}
+
+fn write_csv(path: &str, messages: &[FakeMessage], authors: &[FakeAuthor]) -> Result<(), StatsError> {
+    let write = || -> Result<(), csv::Error> {
+        let mut wtr = Writer::from_path(path)?;
+
+        wtr.write_record(["AuthorID", "Author", "Date", "Content", "Attachments", "Reactions"])?;
+
+        for message in messages {
+            let author = &authors[message.author];
+            let reactions: Vec<String> = message
+                .reactions
+                .iter()
+                .map(|(emoji, count)| format!("{} ({})", emoji, count))
+                .collect();
+
+            wtr.write_record([
+                author.id.to_string(),
+                format!("{}#{}", author.name_at(message.date), author.discriminator),
+                message.date.format(DATE_FORMAT).to_string(),
+                message.content.clone(),
+                message.attachments.join(","),
+                reactions.join(","),
+            ])?;
+        }
+
+        wtr.flush()?;
+
+        Ok(())
+    };
+
+    return write().map_err(|error| StatsError::Export(format!("Could not write {}! {}", path, error)));
+}
+
+fn write_json(
+    path: &str,
+    guild: &str,
+    channel: &FakeChannel,
+    messages: &[FakeMessage],
+    authors: &[FakeAuthor],
+) -> Result<(), StatsError> {
+    let messages: Vec<Value> = messages
+        .iter()
+        .map(|message| {
+            let author = &authors[message.author];
+
+            json!({
+                "id": message.id.to_string(),
+                "type": "Default",
+                "timestamp": message.date.format("%Y-%m-%dT%H:%M:%S%.3f+00:00").to_string(),
+                "content": message.content,
+                "author": {
+                    "id": author.id.to_string(),
+                    "name": author.name_at(message.date),
+                    "discriminator": author.discriminator,
+                    "isBot": author.is_bot,
+                },
+                "attachments": message.attachments.iter().enumerate().map(|(index, url)| json!({
+                    "id": format!("{}{}", message.id, index),
+                    "url": url,
+                    "fileName": url.rsplit('/').next().unwrap_or(""),
+                })).collect::<Vec<Value>>(),
+                "reactions": message.reactions.iter().map(|(emoji, count)| json!({
+                    "emoji": { "id": "", "name": emoji, "isAnimated": false },
+                    "count": count,
+                })).collect::<Vec<Value>>(),
+            })
+        })
+        .collect();
+
+    let export = json!({
+        "guild": { "id": "0", "name": guild },
+        "channel": {
+            "id": channel.id.to_string(),
+            "type": "GuildTextChat",
+            "category": channel.category,
+            "name": channel.name,
+        },
+        "messages": messages,
+        "messageCount": messages.len(),
+    });
+
+    if let Err(error) = fs::write(path, export.to_string()) {
+        return Err(StatsError::Io(format!("Could not write {}! {}", path, error)));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::functions::filters::ChannelFilter;
+    use crate::functions::scrape_file::discover_files;
+    use std::path::MAIN_SEPARATOR;
+
+    // A folder of its own in the temp folder, emptied first
+    fn temp_folder(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("discord-statistics-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        return dir.to_string_lossy().to_string();
+    }
+
+    #[test]
+    fn one_format_is_written_straight_into_the_folder() {
+        let dir = temp_folder("generate-csv");
+        let config = GeneratorConfig::parse("channels=2 authors=3 messages=20").unwrap();
+
+        let paths = generate_exports(&dir, &config).unwrap();
+
+        assert_eq!(paths.len(), 2);
+        assert!(paths.iter().all(|path| path.starts_with(&format!("{}{}Generated", dir, MAIN_SEPARATOR))));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn each_format_gets_a_folder_of_its_own() {
+        let dir = temp_folder("generate-both");
+        let config = GeneratorConfig::parse("channels=3 authors=3 messages=30 formats=csv,json,csv").unwrap();
+
+        assert_eq!(config.formats, vec![ExportFormat::Csv, ExportFormat::Json]);
+
+        let paths = generate_exports(&dir, &config).unwrap();
+        assert_eq!(paths.len(), 6);
+
+        for format in ["csv", "json"] {
+            let format_dir = format!("{}{}{}", dir, MAIN_SEPARATOR, format);
+            let discovery = discover_files(&format_dir, &ChannelFilter::all()).unwrap();
+
+            assert_eq!(discovery.files.len(), 3);
+            assert!(discovery.files.iter().all(|file| file.format() == format));
+            assert!(discovery.duplicates.is_empty());
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
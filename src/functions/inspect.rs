@@ -0,0 +1,125 @@
+use chrono::NaiveDateTime;
+use std::collections::BTreeMap;
+
+use super::filters::*;
+use super::progress::Reporter;
+use super::scrape_file::*;
+
+// Prints what is in the source folder without aggregating
+// anything or writing any output: every guild and channel
+// found, how many rows each one holds and the dates they
+// span, as well as anything that will be skipped. Rows are
+// counted as they are in the files, before the date range
+// or the bot filters leave any of them out.
+pub fn inspect(source_path: &str, discovery: &Discovery, reporter: &Reporter) {
+    let stage = reporter.stage("Scanning files", "files", discovery.files.len() as u64);
+    let scraped = scrape_files(&discovery.files, DateRange::all(), &BotFilter::new(), &stage);
+    stage.finish();
+
+    println!("Source: {}", source_path);
+    println!();
+
+    // Count up the channels in each guild
+    let mut guilds: BTreeMap<&str, usize> = BTreeMap::new();
+    for file in &discovery.files {
+        *guilds.entry(file.channel.guild.as_str()).or_insert(0) += 1;
+    }
+
+    println!("Guilds:");
+    for (guild, channels) in &guilds {
+        println!("  {} ({} channels)", guild, channels);
+    }
+    println!();
+
+    println!(
+        "{:<20} {:<24} {:<20} {:<6} {:>8}  {:<16}  {:<16}",
+        "Channel ID", "Name", "Category", "Format", "Rows", "First message", "Last message"
+    );
+
+    let mut total_rows = 0;
+    let mut unreadable: Vec<String> = Vec::new();
+
+    for (file, result) in discovery.files.iter().zip(scraped.iter()) {
+        let messages = match result {
+            Ok(messages) => messages,
+            Err(error) => {
+                println!(
+                    "{:<20} {:<24} {:<20} {:<6} {:>8}",
+                    file.channel.id,
+                    file.channel.name,
+                    file.channel.category,
+                    file.format(),
+                    "error"
+                );
+                unreadable.push(error.to_string());
+                continue;
+            }
+        };
+
+        let first: Option<NaiveDateTime> = messages.iter().map(|m| m.date).min();
+        let last: Option<NaiveDateTime> = messages.iter().map(|m| m.date).max();
+
+        total_rows += messages.len();
+
+        println!(
+            "{:<20} {:<24} {:<20} {:<6} {:>8}  {:<16}  {:<16}",
+            file.channel.id,
+            file.channel.name,
+            file.channel.category,
+            file.format(),
+            messages.len(),
+            format_date(first),
+            format_date(last)
+        );
+    }
+    println!();
+
+    println!(
+        "Total: {} rows across {} channels",
+        total_rows,
+        discovery.files.len()
+    );
+    println!();
+
+    if !discovery.excluded.is_empty() {
+        println!("Excluded by channel filters:");
+        for channel in &discovery.excluded {
+            println!("  {} {} ({})", channel.id, channel.name, channel.category);
+        }
+        println!();
+    }
+
+    if !unreadable.is_empty() {
+        println!("Files that could not be read:");
+        for error in &unreadable {
+            println!("  {}", error);
+        }
+        println!();
+    }
+
+    if !discovery.duplicates.is_empty() {
+        println!("Channels exported in more than one format:");
+        for (file, kept) in &discovery.duplicates {
+            println!(
+                "  {} {} - reading {}, skipping {}",
+                file.channel.id, file.channel.name, kept, file.path
+            );
+        }
+        println!();
+    }
+
+    if !discovery.skipped.is_empty() {
+        println!("Skipped files:");
+        for (path, reason) in &discovery.skipped {
+            println!("  {} - {}", path, reason);
+        }
+        println!();
+    }
+}
+
+fn format_date(date: Option<NaiveDateTime>) -> String {
+    return match date {
+        Some(date) => date.format("%Y-%m-%d %H:%M").to_string(),
+        None => String::from("-"),
+    };
+}
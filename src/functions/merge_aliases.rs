@@ -0,0 +1,186 @@
+use csv::ReaderBuilder;
+use std::collections::{HashMap, HashSet};
+
+use super::error::StatsError;
+use super::structs::*;
+
+// Reads a file of alias groups, one person per line. The
+// first ID on each line is the account the others are
+// merged into, for example:
+//
+// 222222222222222222,888888888888888888,999999999999999999
+//
+// An ID can only be in one group, and 0 can't be in any, as
+// that is the ID the server totals are counted under.
+pub fn load_aliases(path: &str) -> Result<Vec<Vec<u64>>, StatsError> {
+    let mut rdr = match ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .comment(Some(b'#'))
+        .from_path(path)
+    {
+        Ok(rdr) => rdr,
+        Err(error) => return Err(StatsError::Io(format!("Could not read {}! {}", path, error))),
+    };
+
+    let mut groups: Vec<Vec<u64>> = Vec::new();
+
+    for result in rdr.records() {
+        let record = match result {
+            Ok(record) => record,
+            Err(error) => return Err(StatsError::Parse(format!("{}: {}", path, error))),
+        };
+
+        let mut group: Vec<u64> = Vec::new();
+        for field in record.iter() {
+            if !field.trim().is_empty() {
+                match field.trim().parse() {
+                    Ok(id) => group.push(id),
+                    Err(_) => {
+                        return Err(StatsError::Parse(format!(
+                            "{}: \"{}\" is not an author ID",
+                            path,
+                            field.trim()
+                        )))
+                    }
+                }
+            }
+        }
+
+        if group.len() > 1 {
+            groups.push(group);
+        }
+    }
+
+    if let Err(reason) = check_groups(&groups) {
+        return Err(StatsError::Config(format!("{}: {}", path, reason)));
+    }
+
+    return Ok(groups);
+}
+
+// Makes sure the groups can be merged without anyone being
+// counted twice, or anyone being merged into the server
+pub fn check_groups(groups: &[Vec<u64>]) -> Result<(), String> {
+    let mut seen: HashSet<u64> = HashSet::new();
+
+    for group in groups {
+        for id in group {
+            if *id == 0 {
+                return Err(String::from("0 is the server's ID, so can't be an alias"));
+            }
+
+            if !seen.insert(*id) {
+                return Err(format!("{} is in more than one alias group", id));
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+// Folds every alias account into the first account of its
+// group, so that each person is only counted once
+pub fn merge_aliases(author_map: &mut HashMap<u64, Author>, groups: &[Vec<u64>]) {
+    for group in groups {
+        let primary_id = group[0];
+
+        let mut primary = author_map
+            .remove(&primary_id)
+            .unwrap_or(Author::new(primary_id));
+
+        for alias_id in &group[1..] {
+            if let Some(alias) = author_map.remove(alias_id) {
+                primary = primary.merge(alias);
+            }
+        }
+
+        // Only keep the person if any of their accounts
+        // actually sent messages
+        if primary.message_count > 0 {
+            author_map.insert(primary_id, primary);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use std::fs;
+
+    fn author(id: u64, name: &str, messages: usize) -> Author {
+        let mut author = Author::new(id);
+        let date = NaiveDate::from_ymd_opt(2021, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+
+        for _ in 0..messages {
+            author = author.process_message(Message {
+                author_id: id,
+                channel_id: 1,
+                author_name: String::from(name),
+                date: date,
+                content: String::from("hello"),
+                attachments: Vec::new(),
+                reactions: HashMap::new(),
+                is_bot: false,
+            });
+        }
+
+        return author;
+    }
+
+    fn load(text: &str) -> Result<Vec<Vec<u64>>, StatsError> {
+        let path = std::env::temp_dir().join(format!("discord-statistics-aliases-{:?}.txt", std::thread::current().id()));
+        fs::write(&path, text).unwrap();
+
+        let groups = load_aliases(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+
+        return groups;
+    }
+
+    #[test]
+    fn groups_are_read_one_per_line() {
+        let groups = load("# main, alts\n1, 2,3\n\n4,5,\n6\n").unwrap();
+
+        // A line with only one ID has nothing to merge
+        assert_eq!(groups, vec![vec![1, 2, 3], vec![4, 5]]);
+    }
+
+    #[test]
+    fn bad_groups_are_rejected() {
+        assert!(matches!(load("1,x\n"), Err(StatsError::Parse(_))));
+        assert!(matches!(load("1,0\n"), Err(StatsError::Config(_))));
+        assert!(matches!(load("1,2\n3,2\n"), Err(StatsError::Config(_))));
+        assert!(matches!(load("1,2,1\n"), Err(StatsError::Config(_))));
+        assert!(matches!(load_aliases("/nonexistent/aliases.txt"), Err(StatsError::Io(_))));
+    }
+
+    #[test]
+    fn alts_are_merged_into_the_first_account() {
+        let mut authors: HashMap<u64, Author> = HashMap::new();
+        authors.insert(1, author(1, "main#0001", 2));
+        authors.insert(2, author(2, "alt#0002", 3));
+        authors.insert(4, author(4, "other#0004", 1));
+
+        merge_aliases(&mut authors, &[vec![1, 2, 3], vec![5, 6]]);
+
+        let mut ids: Vec<&u64> = authors.keys().collect();
+        ids.sort();
+        assert_eq!(ids, vec![&1, &4]);
+
+        assert_eq!(authors[&1].message_count, 5);
+        assert_eq!(authors[&1].merged_ids, vec![2]);
+    }
+
+    #[test]
+    fn a_main_account_without_messages_takes_on_its_alts() {
+        let mut authors: HashMap<u64, Author> = HashMap::new();
+        authors.insert(2, author(2, "alt#0002", 3));
+
+        merge_aliases(&mut authors, &[vec![1, 2]]);
+
+        assert_eq!(authors.len(), 1);
+        assert_eq!(authors[&1].message_count, 3);
+    }
+}
@@ -1,5 +1,29 @@
 pub mod create_authors;
+pub mod diff;
+pub mod error;
+pub mod export_images;
+pub mod export_json;
+pub mod export_markdown;
+pub mod export_parquet;
+pub mod export_report;
+pub mod export_sqlite;
 pub mod export_stats;
+pub mod export_template;
+pub mod export_tidy;
+pub mod export_wrapped;
+pub mod export_xlsx;
+pub mod filters;
+pub mod generate;
+pub mod inspect;
+pub mod merge_aliases;
+pub mod progress;
+pub mod query;
+pub mod rankings;
+pub mod repl;
 pub mod scrape_file;
 pub mod scrape_images;
+pub mod serve;
+pub mod snapshot;
 pub mod structs;
+pub mod text;
+pub mod watch;
@@ -0,0 +1,160 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use serde_json::json;
+use std::time;
+
+// How progress should be reported while running
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogMode {
+    // Progress bars and timings for a person at a terminal
+    Human,
+    // Nothing but errors
+    Quiet,
+    // One JSON object per line, for scheduled runs
+    Json,
+}
+
+impl LogMode {
+    pub fn parse(value: &str) -> Option<LogMode> {
+        return match value.trim().to_lowercase().as_str() {
+            "human" => Some(LogMode::Human),
+            "quiet" => Some(LogMode::Quiet),
+            "json" => Some(LogMode::Json),
+            _ => None,
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct Reporter {
+    pub mode: LogMode,
+    start: time::Instant,
+}
+
+impl Reporter {
+    pub fn new(mode: LogMode) -> Reporter {
+        return Reporter {
+            mode: mode,
+            start: time::Instant::now(),
+        };
+    }
+
+    // Starts timing a stage of the run, counting up towards
+    // `total` items of the given unit (files, messages..)
+    pub fn stage(&self, name: &str, unit: &str, total: u64) -> Stage {
+        let bar = if self.mode == LogMode::Human {
+            let bar = ProgressBar::new(total);
+            bar.set_style(
+                ProgressStyle::with_template(
+                    "{msg:<24} [{bar:40.cyan/blue}] {pos}/{len} {prefix} ({elapsed})",
+                )
+                .unwrap()
+                .progress_chars("=> "),
+            );
+            bar.set_message(String::from(name));
+            bar.set_prefix(String::from(unit));
+            bar
+        } else {
+            ProgressBar::hidden()
+        };
+
+        // Hidden bars still need to know their length to count
+        bar.set_length(total);
+
+        return Stage {
+            name: String::from(name),
+            unit: String::from(unit),
+            mode: self.mode,
+            start: time::Instant::now(),
+            bar: bar,
+        };
+    }
+
+    // Logs a message that isn't tied to any stage
+    pub fn info(&self, message: &str) {
+        match self.mode {
+            LogMode::Human => eprintln!("{}", message),
+            LogMode::Quiet => {}
+            LogMode::Json => eprintln!("{}", json!({ "event": "info", "message": message })),
+        }
+    }
+
+    // Reports something that went wrong outside of any stage.
+    // Like stage warnings, these are shown even in quiet mode.
+    pub fn warn(&self, message: &str) {
+        match self.mode {
+            LogMode::Human | LogMode::Quiet => eprintln!("Warning: {}", message),
+            LogMode::Json => eprintln!("{}", json!({ "event": "warning", "message": message })),
+        }
+    }
+
+    pub fn finish(&self) {
+        let elapsed = self.start.elapsed().as_millis() as u64;
+
+        match self.mode {
+            LogMode::Human => eprintln!("\nAll done! in {} ms", elapsed),
+            LogMode::Quiet => {}
+            LogMode::Json => eprintln!("{}", json!({ "event": "finished", "elapsed_ms": elapsed })),
+        }
+    }
+}
+
+// A single timed stage with a progress bar. Cloning a stage
+// shares the bar, so worker threads can count towards it.
+#[derive(Clone)]
+pub struct Stage {
+    name: String,
+    unit: String,
+    mode: LogMode,
+    start: time::Instant,
+    bar: ProgressBar,
+}
+
+impl Stage {
+    pub fn inc(&self, amount: u64) {
+        self.bar.inc(amount);
+    }
+
+    pub fn count(&self) -> u64 {
+        return self.bar.position();
+    }
+
+    // Reports something that went wrong without stopping the run.
+    // Warnings are shown even in quiet mode.
+    pub fn warn(&self, message: &str) {
+        match self.mode {
+            LogMode::Human | LogMode::Quiet => self.bar.suspend(|| eprintln!("Warning: {}", message)),
+            LogMode::Json => eprintln!(
+                "{}",
+                json!({ "event": "warning", "stage": self.name, "message": message })
+            ),
+        }
+    }
+
+    pub fn finish(self) {
+        let elapsed = self.start.elapsed().as_millis() as u64;
+
+        match self.mode {
+            LogMode::Human => {
+                self.bar.finish_and_clear();
+                eprintln!(
+                    "{}... Done! {} {} in {} ms",
+                    self.name,
+                    self.count(),
+                    self.unit,
+                    elapsed
+                );
+            }
+            LogMode::Quiet => {}
+            LogMode::Json => eprintln!(
+                "{}",
+                json!({
+                    "event": "stage",
+                    "stage": self.name,
+                    "unit": self.unit,
+                    "count": self.count(),
+                    "elapsed_ms": elapsed,
+                })
+            ),
+        }
+    }
+}
@@ -0,0 +1,681 @@
+use chrono::*;
+use csv::Writer;
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::*;
+use std::fs;
+
+use super::filters::*;
+use super::structs::*;
+
+pub const QUERY_HELP: &str = "\
+A query is a list of KEY=VALUE terms, all of which are optional:
+
+  author=NAME|ID        only messages from a member, by any name they have used or by ID
+  channel=PATTERN       only messages in a channel ID, name, glob pattern or category:NAME
+  from=DATE             only messages from this date onwards
+  to=DATE               only messages up to and including this date
+  match=REGEX           only messages whose content matches the regular expression
+  group=KEY[,KEY..]     group by author, channel, hour, day, week, month or year
+  agg=AGG[,AGG..]       aggregate count, words, chars, attachments or reactions (default count)
+  sort=AGG              sort groups by this aggregate, largest first
+  top=N                 only keep the first N groups
+  out=PATH              also write the result to PATH (.csv or .json)
+
+Values with spaces can be quoted, for example: author=\"some name\"
+Example: author=alice channel=general from=2021-03 group=day agg=count,words";
+
+// Which messages a query looks at
+#[derive(Clone, Default)]
+pub struct MessageFilter {
+    pub authors: Vec<String>,
+    pub channels: Vec<ChannelPattern>,
+    pub date_range: DateRange,
+    pub content: Option<Regex>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GroupBy {
+    Author,
+    Channel,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aggregate {
+    Count,
+    Words,
+    Characters,
+    Attachments,
+    Reactions,
+}
+
+#[derive(Clone, Default)]
+pub struct Query {
+    pub filter: MessageFilter,
+    pub group_by: Vec<GroupBy>,
+    pub aggregates: Vec<Aggregate>,
+    pub sort: Option<Aggregate>,
+    pub top: Option<usize>,
+    pub output_path: Option<String>,
+}
+
+// The result of a query: named columns of rows, with the
+// aggregated columns marked as numeric
+#[derive(Clone, Debug)]
+pub struct Table {
+    pub headers: Vec<String>,
+    pub numeric: Vec<bool>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl GroupBy {
+    pub fn parse(value: &str) -> Option<GroupBy> {
+        return match value.trim().to_lowercase().as_str() {
+            "author" => Some(GroupBy::Author),
+            "channel" => Some(GroupBy::Channel),
+            "hour" => Some(GroupBy::Hour),
+            "day" => Some(GroupBy::Day),
+            "week" => Some(GroupBy::Week),
+            "month" => Some(GroupBy::Month),
+            "year" => Some(GroupBy::Year),
+            _ => None,
+        };
+    }
+
+    fn headers(&self) -> Vec<&str> {
+        return match self {
+            GroupBy::Author => vec!["Author", "Author ID"],
+            GroupBy::Channel => vec!["Channel", "Channel ID"],
+            GroupBy::Hour => vec!["Hour"],
+            GroupBy::Day => vec!["Day"],
+            GroupBy::Week => vec!["Week"],
+            GroupBy::Month => vec!["Month"],
+            GroupBy::Year => vec!["Year"],
+        };
+    }
+
+    // The value a message is grouped under. Authors and
+    // channels group by ID, and are named afterwards.
+    fn key(&self, message: &Message) -> String {
+        let date = message.date;
+
+        return match self {
+            GroupBy::Author => message.author_id.to_string(),
+            GroupBy::Channel => message.channel_id.to_string(),
+            GroupBy::Hour => format!("{:02}", date.hour()),
+            GroupBy::Day => date.format("%Y-%m-%d").to_string(),
+            GroupBy::Week => {
+                let week = date.iso_week();
+                format!("{}-W{:02}", week.year(), week.week())
+            }
+            GroupBy::Month => date.format("%Y-%m").to_string(),
+            GroupBy::Year => date.format("%Y").to_string(),
+        };
+    }
+}
+
+impl Aggregate {
+    pub fn parse(value: &str) -> Option<Aggregate> {
+        return match value.trim().to_lowercase().as_str() {
+            "count" | "messages" => Some(Aggregate::Count),
+            "words" => Some(Aggregate::Words),
+            "chars" | "characters" => Some(Aggregate::Characters),
+            "attachments" => Some(Aggregate::Attachments),
+            "reactions" => Some(Aggregate::Reactions),
+            _ => None,
+        };
+    }
+
+    fn header(&self) -> &str {
+        return match self {
+            Aggregate::Count => "Messages",
+            Aggregate::Words => "Words",
+            Aggregate::Characters => "Characters",
+            Aggregate::Attachments => "Attachments",
+            Aggregate::Reactions => "Reactions",
+        };
+    }
+
+    // Counted the same way as the author statistics
+    fn value(&self, message: &Message) -> u128 {
+        return match self {
+            Aggregate::Count => 1,
+            Aggregate::Words => message.content.split(' ').count() as u128,
+            Aggregate::Characters => message.content.len() as u128,
+            Aggregate::Attachments => message.attachments.len() as u128,
+            Aggregate::Reactions => message.reactions.values().sum(),
+        };
+    }
+}
+
+impl MessageFilter {
+    // Finds the IDs of every author matching the author
+    // terms, by ID, by full tag or by the name before the
+    // discriminator, ignoring case
+    pub fn author_ids(&self, messages: &[Message]) -> Option<HashSet<u64>> {
+        if self.authors.is_empty() {
+            return None;
+        }
+
+        let values: Vec<String> = self
+            .authors
+            .iter()
+            .map(|a| a.trim_start_matches('@').to_lowercase())
+            .collect();
+
+        let mut ids: HashSet<u64> = HashSet::new();
+
+        for message in messages {
+            let name = message.author_name.to_lowercase();
+            let short_name = name.split('#').next().unwrap_or("");
+
+            for value in &values {
+                if *value == message.author_id.to_string()
+                    || *value == name
+                    || *value == short_name
+                {
+                    ids.insert(message.author_id);
+                }
+            }
+        }
+
+        return Some(ids);
+    }
+
+    pub fn matches(
+        &self,
+        message: &Message,
+        author_ids: &Option<HashSet<u64>>,
+        channel_id_dict: &HashMap<u64, Channel>,
+    ) -> bool {
+        if let Some(author_ids) = author_ids {
+            if !author_ids.contains(&message.author_id) {
+                return false;
+            }
+        }
+
+        if !self.channels.is_empty() {
+            match channel_id_dict.get(&message.channel_id) {
+                Some(channel) => {
+                    if !self.channels.iter().any(|p| p.matches(channel)) {
+                        return false;
+                    }
+                }
+                None => return false,
+            }
+        }
+
+        if let Some(content) = &self.content {
+            if !content.is_match(&message.content) {
+                return false;
+            }
+        }
+
+        return self.date_range.contains(&message.date);
+    }
+
+    // All messages passing the filter
+    pub fn select<'a>(
+        &self,
+        messages: &'a [Message],
+        channel_id_dict: &HashMap<u64, Channel>,
+    ) -> Vec<&'a Message> {
+        let author_ids = self.author_ids(messages);
+
+        return messages
+            .iter()
+            .filter(|m| self.matches(m, &author_ids, channel_id_dict))
+            .collect();
+    }
+}
+
+impl Query {
+    pub fn parse(text: &str) -> Result<Query, String> {
+        return Query::from_terms(&tokenize(text));
+    }
+
+    pub fn from_terms(terms: &[String]) -> Result<Query, String> {
+        let mut query = Query::default();
+
+        for term in terms {
+            let (key, value) = match term.find('=') {
+                Some(index) => (&term[..index], &term[index + 1..]),
+                None => return Err(format!("Expected KEY=VALUE, found \"{}\"", term)),
+            };
+
+            match key.to_lowercase().as_str() {
+                "author" => query.filter.authors.push(String::from(value)),
+                "channel" => match ChannelPattern::parse(value) {
+                    Some(pattern) => query.filter.channels.push(pattern),
+                    None => return Err(format!("Could not parse channel \"{}\"", value)),
+                },
+                "from" => match DateRange::parse_start(value) {
+                    Some(start) => query.filter.date_range.start = Some(start),
+                    None => return Err(format!("Could not parse date \"{}\"", value)),
+                },
+                "to" => match DateRange::parse_end(value) {
+                    Some(end) => query.filter.date_range.end = Some(end),
+                    None => return Err(format!("Could not parse date \"{}\"", value)),
+                },
+                "match" => match Regex::new(value) {
+                    Ok(regex) => query.filter.content = Some(regex),
+                    Err(error) => return Err(format!("Could not parse regex \"{}\": {}", value, error)),
+                },
+                // Groups and aggregates given more than once, such
+                // as by a repl command and again after it, only
+                // make one column each
+                "group" => {
+                    for group in value.split(',') {
+                        match GroupBy::parse(group) {
+                            Some(group) if query.group_by.contains(&group) => {}
+                            Some(group) => query.group_by.push(group),
+                            None => return Err(format!("Unknown group \"{}\"", group)),
+                        }
+                    }
+                }
+                "agg" => {
+                    for aggregate in value.split(',') {
+                        match Aggregate::parse(aggregate) {
+                            Some(aggregate) if query.aggregates.contains(&aggregate) => {}
+                            Some(aggregate) => query.aggregates.push(aggregate),
+                            None => return Err(format!("Unknown aggregate \"{}\"", aggregate)),
+                        }
+                    }
+                }
+                "sort" => match Aggregate::parse(value) {
+                    Some(aggregate) => query.sort = Some(aggregate),
+                    None => return Err(format!("Unknown aggregate \"{}\"", value)),
+                },
+                "top" => match value.parse() {
+                    Ok(top) => query.top = Some(top),
+                    Err(_) => return Err(format!("Could not parse number \"{}\"", value)),
+                },
+                "out" => query.output_path = Some(String::from(value)),
+                _ => return Err(format!("Unknown term \"{}\"", key)),
+            }
+        }
+
+        if query.aggregates.is_empty() {
+            query.aggregates.push(Aggregate::Count);
+        }
+
+        if let Some(sort) = query.sort {
+            if !query.aggregates.contains(&sort) {
+                query.aggregates.push(sort);
+            }
+        }
+
+        return Ok(query);
+    }
+
+    pub fn run(&self, messages: &[Message], channel_id_dict: &HashMap<u64, Channel>) -> Table {
+        let selected = self.filter.select(messages, channel_id_dict);
+
+        // Sum every aggregate for each group. Without any
+        // groups, everything falls into a single row.
+        let mut groups: BTreeMap<Vec<String>, Vec<u128>> = BTreeMap::new();
+
+        // The most recent name of each author, for display
+        let mut author_names: HashMap<u64, (NaiveDateTime, String)> = HashMap::new();
+
+        for message in &selected {
+            let key: Vec<String> = self.group_by.iter().map(|g| g.key(message)).collect();
+
+            let sums = groups
+                .entry(key)
+                .or_insert(vec![0; self.aggregates.len()]);
+
+            for (index, aggregate) in self.aggregates.iter().enumerate() {
+                sums[index] += aggregate.value(message);
+            }
+
+            let name = author_names
+                .entry(message.author_id)
+                .or_insert((message.date, message.author_name.clone()));
+            if message.date >= name.0 {
+                *name = (message.date, message.author_name.clone());
+            }
+        }
+
+        let mut results: Vec<(Vec<String>, Vec<u128>)> = groups.into_iter().collect();
+
+        // Groups over time read best in order, anything else
+        // reads best as a ranking
+        let sort = match self.sort {
+            Some(sort) => Some(sort),
+            None => {
+                if self.group_by.contains(&GroupBy::Author) || self.group_by.contains(&GroupBy::Channel) {
+                    Some(self.aggregates[0])
+                } else {
+                    None
+                }
+            }
+        };
+
+        if let Some(sort) = sort {
+            let index = self.aggregates.iter().position(|a| *a == sort).unwrap();
+            results.sort_by(|a, b| b.1[index].cmp(&a.1[index]));
+        }
+
+        if let Some(top) = self.top {
+            results.truncate(top);
+        }
+
+        let mut table = Table {
+            headers: Vec::new(),
+            numeric: Vec::new(),
+            rows: Vec::new(),
+        };
+
+        for group in &self.group_by {
+            for header in group.headers() {
+                table.headers.push(String::from(header));
+                table.numeric.push(false);
+            }
+        }
+
+        for aggregate in &self.aggregates {
+            table.headers.push(String::from(aggregate.header()));
+            table.numeric.push(true);
+        }
+
+        for (key, sums) in results {
+            let mut row: Vec<String> = Vec::new();
+
+            for (group, value) in self.group_by.iter().zip(key.iter()) {
+                match group {
+                    GroupBy::Author => {
+                        let id: u64 = value.parse().unwrap();
+                        let name = match author_names.get(&id) {
+                            Some((_, name)) => name.clone(),
+                            None => String::new(),
+                        };
+                        row.push(name);
+                        row.push(value.clone());
+                    }
+                    GroupBy::Channel => {
+                        let id: u64 = value.parse().unwrap();
+                        let name = match channel_id_dict.get(&id) {
+                            Some(channel) => channel.name.clone(),
+                            None => String::new(),
+                        };
+                        row.push(name);
+                        row.push(value.clone());
+                    }
+                    _ => row.push(value.clone()),
+                }
+            }
+
+            for sum in sums {
+                row.push(sum.to_string());
+            }
+
+            table.rows.push(row);
+        }
+
+        return table;
+    }
+}
+
+impl Table {
+    // Prints the rows under a header, padding every column
+    // to the width of its widest cell
+    pub fn print(&self) {
+        let mut widths: Vec<usize> = self.headers.iter().map(|h| h.chars().count()).collect();
+
+        for row in &self.rows {
+            for (index, cell) in row.iter().enumerate() {
+                widths[index] = widths[index].max(cell.chars().count());
+            }
+        }
+
+        let pad = |index: usize, cell: &str| -> String {
+            if self.numeric[index] {
+                return format!("{:>width$}", cell, width = widths[index]);
+            }
+            return format!("{:<width$}", cell, width = widths[index]);
+        };
+
+        let header: Vec<String> = self
+            .headers
+            .iter()
+            .enumerate()
+            .map(|(index, h)| pad(index, h))
+            .collect();
+        println!("{}", header.join("  ").trim_end());
+
+        let divider: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+        println!("{}", divider.join("  "));
+
+        for row in &self.rows {
+            let cells: Vec<String> = row
+                .iter()
+                .enumerate()
+                .map(|(index, cell)| pad(index, cell))
+                .collect();
+            println!("{}", cells.join("  ").trim_end());
+        }
+    }
+
+    pub fn write_csv(&self, path: &str) -> Result<(), csv::Error> {
+        let mut wtr = Writer::from_path(path)?;
+
+        wtr.write_record(&self.headers)?;
+        for row in &self.rows {
+            wtr.write_record(row)?;
+        }
+
+        wtr.flush()?;
+
+        Ok(())
+    }
+
+    // The rows as an array of objects keyed by the column
+    // headers, keeping numbers as numbers
+    pub fn to_json(&self) -> Value {
+        let mut objects: Vec<Value> = Vec::new();
+
+        for row in &self.rows {
+            let mut object = Map::new();
+
+            for (index, cell) in row.iter().enumerate() {
+                let value = if self.numeric[index] {
+                    match cell.parse::<u64>() {
+                        Ok(number) => Value::from(number),
+                        Err(_) => Value::from(cell.clone()),
+                    }
+                } else {
+                    Value::from(cell.clone())
+                };
+
+                object.insert(self.headers[index].clone(), value);
+            }
+
+            objects.push(Value::Object(object));
+        }
+
+        return Value::Array(objects);
+    }
+
+    pub fn write_json(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        fs::write(path, serde_json::to_string_pretty(&self.to_json())?)?;
+
+        Ok(())
+    }
+
+    // Writes the table, picking the format from the extension
+    pub fn write(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        if path.to_lowercase().ends_with(".json") {
+            return self.write_json(path);
+        }
+
+        self.write_csv(path)?;
+
+        Ok(())
+    }
+}
+
+// Splits a line on whitespace, keeping quoted values together
+pub fn tokenize(line: &str) -> Vec<String> {
+    let mut tokens: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+
+    for c in line.trim().chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    tokens.push(current.clone());
+                    current.clear();
+                }
+            }
+            c => current.push(c),
+        }
+    }
+
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+
+    return tokens;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(author_id: u64, author_name: &str, date: &str, content: &str) -> Message {
+        return Message {
+            author_id: author_id,
+            channel_id: 111,
+            author_name: String::from(author_name),
+            date: NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M").unwrap(),
+            content: String::from(content),
+            attachments: Vec::new(),
+            reactions: HashMap::new(),
+            is_bot: false,
+        };
+    }
+
+    fn messages() -> Vec<Message> {
+        return vec![
+            message(222, "alice#1234", "2021-03-01 10:00", "hello there"),
+            message(333, "bob#5678", "2021-03-02 11:00", "hi"),
+            message(222, "alice#1234", "2021-04-01 12:00", "good morning all"),
+            message(222, "alice#1234", "2021-04-02 13:00", "bye"),
+        ];
+    }
+
+    #[test]
+    fn tokenize_splits_on_whitespace() {
+        assert_eq!(tokenize("  group=day \t agg=count  "), vec!["group=day", "agg=count"]);
+        assert!(tokenize("   ").is_empty());
+    }
+
+    #[test]
+    fn tokenize_keeps_quoted_values_together() {
+        assert_eq!(
+            tokenize("author=\"some name\" match=\"a  b\" top=3"),
+            vec!["author=some name", "match=a  b", "top=3"]
+        );
+        assert_eq!(tokenize("author=\"\""), vec!["author="]);
+    }
+
+    #[test]
+    fn query_defaults_to_counting_messages() {
+        let query = Query::parse("").unwrap();
+
+        assert!(query.group_by.is_empty());
+        assert_eq!(query.aggregates, vec![Aggregate::Count]);
+    }
+
+    #[test]
+    fn query_reads_every_term() {
+        let query = Query::parse(
+            "author=alice channel=general from=2021-03 to=2021-03 match=^h \
+             group=author,day agg=words sort=chars top=5 out=x.csv",
+        )
+        .unwrap();
+        let date = |text| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap();
+
+        assert_eq!(query.filter.authors, vec!["alice"]);
+        assert_eq!(query.filter.channels.len(), 1);
+        assert!(query.filter.date_range.contains(&date("2021-03-31 23:59")));
+        assert!(!query.filter.date_range.contains(&date("2021-04-01 00:00")));
+        assert!(query.filter.content.is_some());
+        assert_eq!(query.group_by, vec![GroupBy::Author, GroupBy::Day]);
+        // Sorting by an aggregate adds it to the columns
+        assert_eq!(query.aggregates, vec![Aggregate::Words, Aggregate::Characters]);
+        assert_eq!(query.sort, Some(Aggregate::Characters));
+        assert_eq!(query.top, Some(5));
+        assert_eq!(query.output_path.as_deref(), Some("x.csv"));
+    }
+
+    #[test]
+    fn query_rejects_what_it_cannot_read() {
+        assert!(Query::parse("group").is_err());
+        assert!(Query::parse("group=fortnight").is_err());
+        assert!(Query::parse("agg=median").is_err());
+        assert!(Query::parse("from=yesterday").is_err());
+        assert!(Query::parse("match=(").is_err());
+        assert!(Query::parse("top=many").is_err());
+        assert!(Query::parse("colour=blue").is_err());
+    }
+
+    #[test]
+    fn repeated_groups_and_aggregates_make_one_column() {
+        // As the repl's authors command does, before the terms typed after it
+        let terms: Vec<String> = [
+            "group=author",
+            "agg=count,words,attachments",
+            "agg=words,reactions",
+            "group=author",
+        ]
+        .iter()
+        .map(|t| String::from(*t))
+        .collect();
+        let query = Query::from_terms(&terms).unwrap();
+
+        assert_eq!(query.group_by, vec![GroupBy::Author]);
+        assert_eq!(
+            query.aggregates,
+            vec![Aggregate::Count, Aggregate::Words, Aggregate::Attachments, Aggregate::Reactions]
+        );
+
+        let table = query.run(&messages(), &HashMap::new());
+        assert_eq!(
+            table.headers,
+            vec!["Author", "Author ID", "Messages", "Words", "Attachments", "Reactions"]
+        );
+    }
+
+    #[test]
+    fn query_groups_and_ranks_authors() {
+        let query = Query::parse("group=author agg=count,words").unwrap();
+        let table = query.run(&messages(), &HashMap::new());
+
+        assert_eq!(
+            table.rows,
+            vec![
+                vec!["alice#1234", "222", "3", "6"],
+                vec!["bob#5678", "333", "1", "1"],
+            ]
+        );
+    }
+
+    #[test]
+    fn query_filters_before_grouping_over_time() {
+        let query = Query::parse("author=alice from=2021-04 group=month").unwrap();
+        let table = query.run(&messages(), &HashMap::new());
+
+        assert_eq!(table.headers, vec!["Month", "Messages"]);
+        assert_eq!(table.rows, vec![vec!["2021-04", "2"]]);
+    }
+}
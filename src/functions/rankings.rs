@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use super::structs::*;
+
+// What a ranking ranks an author by
+pub type Metric = fn(&Author) -> u128;
+
+// The rankings of the server statistics, and what each
+// one ranks an author by
+pub const RANKINGS: [(&str, Metric); 6] = [
+    ("messages", |a| a.message_count),
+    ("words", |a| a.word_count),
+    ("characters", |a| a.character_count),
+    ("attachments", |a| a.attachments_ledger.len() as u128),
+    ("questions", |a| a.question_count),
+    ("vocabulary", |a| a.vocab_dict.len() as u128),
+];
+
+// The name of a ranking as a heading, "messages" becomes
+// "Messages"
+pub fn ranking_title(name: &str) -> String {
+    let mut chars = name.chars();
+
+    return match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    };
+}
+
+// Authors from highest to lowest, ties going to the lower ID
+// so the order is the same every time
+pub fn ranked(authors: &HashMap<u64, Author>, metric: Metric) -> Vec<&Author> {
+    let mut ranked: Vec<&Author> = authors.values().collect();
+    ranked.sort_by(|a, b| metric(b).cmp(&metric(a)).then(a.id.cmp(&b.id)));
+
+    return ranked;
+}
+
+// The place of everyone in a ranking, counted from 1
+pub fn ranks(authors: &HashMap<u64, Author>, metric: Metric) -> HashMap<u64, usize> {
+    return ranked(authors, metric)
+        .iter()
+        .enumerate()
+        .map(|(index, author)| (author.id, index + 1))
+        .collect();
+}
+
+// The members by messages sent, followed by the bots, along
+// with whether each one is ranked, which bots never are
+pub fn members_then_bots<'a>(
+    author_hashmap: &'a HashMap<u64, Author>,
+    bot_hashmap: &'a HashMap<u64, Author>,
+) -> Vec<(&'a Author, bool)> {
+    let mut everyone: Vec<(&Author, bool)> = ranked(author_hashmap, RANKINGS[0].1)
+        .into_iter()
+        .map(|author| (author, true))
+        .collect();
+    everyone.extend(ranked(bot_hashmap, RANKINGS[0].1).into_iter().map(|author| (author, false)));
+
+    return everyone;
+}
+
+// Words from most to least used, ties in alphabetical order
+pub fn sorted_words(author: &Author) -> Vec<(&String, &u128)> {
+    let mut words: Vec<(&String, &u128)> = author.vocab_dict.iter().collect();
+    words.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+
+    return words;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn author(id: u64, messages: u128, is_bot: bool) -> Author {
+        let mut author = Author::new(id);
+        author.message_count = messages;
+        author.is_bot = is_bot;
+
+        return author;
+    }
+
+    fn map(authors: Vec<Author>) -> HashMap<u64, Author> {
+        return authors.into_iter().map(|author| (author.id, author)).collect();
+    }
+
+    #[test]
+    fn ties_go_to_the_lower_id() {
+        let authors = map(vec![author(333, 5, false), author(222, 5, false), author(444, 9, false)]);
+
+        let ids: Vec<u64> = ranked(&authors, RANKINGS[0].1).iter().map(|a| a.id).collect();
+        assert_eq!(ids, vec![444, 222, 333]);
+
+        let places = ranks(&authors, RANKINGS[0].1);
+        assert_eq!((places[&444], places[&222], places[&333]), (1, 2, 3));
+    }
+
+    #[test]
+    fn bots_come_after_every_member_and_are_not_ranked() {
+        let authors = map(vec![author(222, 1, false), author(333, 4, false)]);
+        let bots = map(vec![author(777, 100, true)]);
+
+        let everyone: Vec<(u64, bool)> = members_then_bots(&authors, &bots)
+            .iter()
+            .map(|(author, ranked)| (author.id, *ranked))
+            .collect();
+        assert_eq!(everyone, vec![(333, true), (222, true), (777, false)]);
+    }
+
+    #[test]
+    fn rankings_have_titles() {
+        let titles: Vec<String> = RANKINGS.iter().map(|(name, _)| ranking_title(name)).collect();
+        assert_eq!(titles[0], "Messages");
+        assert_eq!(titles[5], "Vocabulary");
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, Write};
+
+use super::error::StatsError;
+use super::query::*;
+use super::structs::*;
+
+const HELP: &str = "\
+Commands:
+  count    [TERMS]    messages, words, characters and attachments sent
+  authors  [TERMS]    authors ranked by messages sent
+  channels [TERMS]    channels ranked by messages sent
+  hours    [TERMS]    messages sent in each hour of the day
+  words    [TERMS]    most used words
+  query    [TERMS]    run a query of your own
+  help                show this message
+  quit                leave the shell
+
+Every command takes the same terms as a query, which are
+added on to what the command already does. For example,
+\"authors channel=general from=2021-03 to=2021-03\" ranks
+the authors of #general in March 2021, and \"channels
+group=month\" splits each channel up by month.
+";
+
+// Loads the messages once, then answers questions about
+// them read line by line from standard input
+pub fn run_repl(messages: Vec<Message>, channel_id_dict: HashMap<u64, Channel>) -> Result<(), StatsError> {
+    let authors: usize = messages
+        .iter()
+        .map(|m| m.author_id)
+        .collect::<HashSet<u64>>()
+        .len();
+
+    println!(
+        "Loaded {} messages from {} authors in {} channels. Type \"help\" for commands.",
+        messages.len(),
+        authors,
+        channel_id_dict.len()
+    );
+
+    let stdin = io::stdin();
+
+    loop {
+        print!("> ");
+        if let Err(error) = io::stdout().flush() {
+            return Err(StatsError::Io(format!("Could not write to the terminal! {}", error)));
+        }
+
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(error) => return Err(StatsError::Io(format!("Could not read from the terminal! {}", error))),
+        }
+
+        let tokens = tokenize(&line);
+
+        if tokens.is_empty() {
+            continue;
+        }
+
+        let command = tokens[0].to_lowercase();
+
+        // Each command is a query with a few terms filled in
+        // ahead of the ones given
+        let preset: &[&str] = match command.as_str() {
+            "quit" | "exit" => break,
+            "help" => {
+                println!("{}", HELP);
+                println!("{}", QUERY_HELP);
+                continue;
+            }
+            "count" => &["agg=count,words,chars,attachments"],
+            "authors" => &["group=author", "agg=count,words,attachments", "top=25"],
+            "channels" => &["group=channel", "agg=count,words"],
+            "hours" => &["group=hour", "agg=count"],
+            "words" | "query" => &[],
+            _ => {
+                println!("Unknown command \"{}\". Type \"help\" for commands.", command);
+                continue;
+            }
+        };
+
+        let mut terms: Vec<String> = preset.iter().map(|t| String::from(*t)).collect();
+        terms.extend_from_slice(&tokens[1..]);
+
+        let query = match Query::from_terms(&terms) {
+            Ok(query) => query,
+            Err(error) => {
+                println!("Error: {}", error);
+                continue;
+            }
+        };
+
+        let table = if command == "words" {
+            top_words(&query, &messages, &channel_id_dict)
+        } else {
+            query.run(&messages, &channel_id_dict)
+        };
+
+        table.print();
+
+        if let Some(path) = &query.output_path {
+            match table.write(path) {
+                Ok(_) => println!("Written to {}", path),
+                Err(error) => println!("Error: Could not write {}! {}", path, error),
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+// Ranks the words used in the messages the query selects
+fn top_words(query: &Query, messages: &[Message], channel_id_dict: &HashMap<u64, Channel>) -> Table {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+
+    for message in query.filter.select(messages, channel_id_dict) {
+        for word in message.content.split(' ').filter(|word| !word.is_empty()) {
+            *counts.entry(word).or_insert(0) += 1;
+        }
+    }
+
+    let mut ranked: Vec<(&str, usize)> = counts.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+    let rows = ranked
+        .iter()
+        .take(query.top.unwrap_or(25))
+        .enumerate()
+        .map(|(index, (word, count))| {
+            vec![(index + 1).to_string(), word.to_string(), count.to_string()]
+        })
+        .collect();
+
+    return Table {
+        headers: vec![String::from("#"), String::from("Word"), String::from("Count")],
+        numeric: vec![true, false, true],
+        rows: rows,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn message(content: &str) -> Message {
+        return Message {
+            author_id: 222,
+            channel_id: 111,
+            author_name: String::from("alice"),
+            date: NaiveDate::from_ymd_opt(2023, 1, 1).unwrap().and_hms_opt(10, 0, 0).unwrap(),
+            content: String::from(content),
+            attachments: Vec::new(),
+            reactions: HashMap::new(),
+            is_bot: false,
+        };
+    }
+
+    #[test]
+    fn words_leaves_out_empty_messages_and_double_spaces() {
+        let messages = vec![message(""), message("hi  there"), message("hi"), message("")];
+        let query = Query::from_terms(&[]).unwrap();
+
+        let table = top_words(&query, &messages, &HashMap::new());
+
+        let words: Vec<(&str, &str)> = table
+            .rows
+            .iter()
+            .map(|row| (row[1].as_str(), row[2].as_str()))
+            .collect();
+        assert_eq!(words, vec![("hi", "2"), ("there", "1")]);
+    }
+}
@@ -1,15 +1,448 @@
-use csv::*;
+use chrono::DateTime;
+use csv::Reader;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::thread;
 
+use super::error::StatsError;
+use super::filters::*;
+use super::progress::Stage;
 use super::structs::*;
 
-pub fn scrape_file(string_path: String, channel_id: u64) -> Vec<Message> {
-    let mut rdr = Reader::from_path(string_path).unwrap();
+// An export file found in the source folder, along with
+// the channel it holds
+#[derive(Clone)]
+pub struct SourceFile {
+    pub path: String,
+    pub channel: Channel,
+}
+
+impl SourceFile {
+    pub fn format(&self) -> &str {
+        if self.path.ends_with(".json") {
+            return "json";
+        }
+
+        return "csv";
+    }
+}
+
+// Everything found when looking through the source folder
+pub struct Discovery {
+    // Files that will be scraped
+    pub files: Vec<SourceFile>,
+    // Channels left out by the channel filters
+    pub excluded: Vec<Channel>,
+    // Paths that can't be scraped, and why
+    pub skipped: Vec<(String, String)>,
+    // Files left out because their channel is also in
+    // another file, along with the path of the one kept
+    pub duplicates: Vec<(SourceFile, String)>,
+}
+
+// The parts of a DiscordChatExporter JSON export that we use.
+// Unlike the CSV format, it carries author metadata such as
+// whether the account is a bot.
+#[derive(Deserialize)]
+struct JsonExport {
+    messages: Vec<JsonMessage>,
+}
+
+#[derive(Deserialize)]
+struct JsonMessage {
+    timestamp: String,
+    #[serde(default)]
+    content: String,
+    author: JsonAuthor,
+    #[serde(default)]
+    attachments: Vec<JsonAttachment>,
+    #[serde(default)]
+    reactions: Vec<JsonReaction>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonAuthor {
+    id: String,
+    name: String,
+    #[serde(default)]
+    discriminator: String,
+    #[serde(default)]
+    is_bot: bool,
+}
+
+#[derive(Deserialize)]
+struct JsonAttachment {
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct JsonReaction {
+    emoji: JsonEmoji,
+    count: u128,
+}
+
+#[derive(Deserialize)]
+struct JsonEmoji {
+    name: String,
+}
+
+// Goes over every path in the source folder, making sure
+// that it has an extension, and then seeing if it's a csv
+// or json file. If so, and the channel passes the filters,
+// it's queued up to be scraped. A channel exported more than
+// once, such as both as csv and json, is only scraped from
+// one file, preferring json as it knows which authors are bots
+pub fn discover_files(source_path: &str, channel_filter: &ChannelFilter) -> Result<Discovery, StatsError> {
+    let mut discovery = Discovery {
+        files: Vec::new(),
+        excluded: Vec::new(),
+        skipped: Vec::new(),
+        duplicates: Vec::new(),
+    };
+
+    let entries = match fs::read_dir(source_path) {
+        Ok(entries) => entries,
+        Err(error) => return Err(StatsError::Io(format!("Could not read {}! {}", source_path, error))),
+    };
+
+    let mut paths: Vec<_> = entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect();
+    paths.sort();
+
+    for path in paths {
+        let string_path = path.to_string_lossy().to_string();
+
+        if path.is_dir() {
+            discovery
+                .skipped
+                .push((string_path, String::from("is a directory")));
+            continue;
+        }
+
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+
+        if extension != "csv" && extension != "json" {
+            discovery
+                .skipped
+                .push((string_path, String::from("not a csv or json export")));
+            continue;
+        }
+
+        // Get the channel from the file name
+        match Channel::from_path(&path) {
+            Some(channel) => {
+                if channel_filter.allows(&channel) {
+                    let file = SourceFile {
+                        path: string_path,
+                        channel: channel,
+                    };
+
+                    match discovery.files.iter().position(|f| f.channel.id == file.channel.id) {
+                        Some(index) if file.format() == "json" && discovery.files[index].format() != "json" => {
+                            let kept = file.path.clone();
+                            let replaced = std::mem::replace(&mut discovery.files[index], file);
+                            discovery.duplicates.push((replaced, kept));
+                        }
+                        Some(index) => {
+                            let kept = discovery.files[index].path.clone();
+                            discovery.duplicates.push((file, kept));
+                        }
+                        None => discovery.files.push(file),
+                    }
+                } else {
+                    discovery.excluded.push(channel);
+                }
+            }
+            None => discovery
+                .skipped
+                .push((string_path, String::from("no channel ID in the file name"))),
+        }
+    }
+
+    return Ok(discovery);
+}
+
+// Scrapes every file on its own thread, returning the
+// messages of each file, or why it couldn't be read, in
+// the same order as given
+pub fn scrape_files(
+    files: &[SourceFile],
+    date_range: DateRange,
+    bot_filter: &BotFilter,
+    stage: &Stage,
+) -> Vec<Result<Vec<Message>, StatsError>> {
+    let mut threads = Vec::new();
+
+    for file in files {
+        let string_path = file.path.clone();
+        let channel_id = file.channel.id;
+        let bot_filter = bot_filter.clone();
+        let stage = stage.clone();
+
+        threads.push(thread::spawn(move || {
+            let messages = scrape_file(string_path, channel_id, date_range, bot_filter);
+            stage.inc(1);
+            messages
+        }));
+    }
+
+    return threads
+        .into_iter()
+        .zip(files.iter())
+        .map(|(thread, file)| match thread.join() {
+            Ok(messages) => messages,
+            Err(_) => Err(StatsError::Parse(format!(
+                "{}: reading the file stopped unexpectedly",
+                file.path
+            ))),
+        })
+        .collect();
+}
+
+pub fn scrape_file(
+    string_path: String,
+    channel_id: u64,
+    date_range: DateRange,
+    bot_filter: BotFilter,
+) -> Result<Vec<Message>, StatsError> {
+    if string_path.ends_with(".json") {
+        return scrape_json(&string_path, channel_id, date_range, &bot_filter);
+    }
+
+    return scrape_csv(&string_path, channel_id, date_range, &bot_filter);
+}
+
+// Whether a message is kept, checked as each row is read so
+// that anything outside of the requested dates, or from an
+// excluded bot, is never held onto or cloned later. Marks the
+// message as a bot's if the bot filter finds it is one.
+fn keep_message(message: &mut Message, date_range: DateRange, bot_filter: &BotFilter) -> bool {
+    if !date_range.contains(&message.date) {
+        return false;
+    }
+
+    message.is_bot = bot_filter.is_bot(message);
+
+    return !(message.is_bot && bot_filter.mode == BotMode::Exclude);
+}
+
+fn scrape_csv(
+    string_path: &str,
+    channel_id: u64,
+    date_range: DateRange,
+    bot_filter: &BotFilter,
+) -> Result<Vec<Message>, StatsError> {
+    let mut rdr = match Reader::from_path(string_path) {
+        Ok(rdr) => rdr,
+        Err(error) => return Err(StatsError::Io(format!("Could not read {}! {}", string_path, error))),
+    };
 
     let mut message_vec: Vec<Message> = Vec::new();
 
-    for result in rdr.records() {
-        message_vec.push(Message::from_csv_string(result.unwrap(), channel_id));
+    for (index, result) in rdr.records().enumerate() {
+        // Rows are counted from 1, after the header row
+        let row = index + 2;
+
+        let record = match result {
+            Ok(record) => record,
+            Err(error) => return Err(StatsError::Parse(format!("{} row {}: {}", string_path, row, error))),
+        };
+
+        let mut message = match Message::from_csv_string(record, channel_id) {
+            Ok(message) => message,
+            Err(reason) => return Err(StatsError::Parse(format!("{} row {}: {}", string_path, row, reason))),
+        };
+
+        if keep_message(&mut message, date_range, bot_filter) {
+            message_vec.push(message);
+        }
+    }
+
+    return Ok(message_vec);
+}
+
+fn scrape_json(
+    string_path: &str,
+    channel_id: u64,
+    date_range: DateRange,
+    bot_filter: &BotFilter,
+) -> Result<Vec<Message>, StatsError> {
+    let file = match fs::File::open(string_path) {
+        Ok(file) => file,
+        Err(error) => return Err(StatsError::Io(format!("Could not read {}! {}", string_path, error))),
+    };
+
+    let export: JsonExport = match serde_json::from_reader(std::io::BufReader::new(file)) {
+        Ok(export) => export,
+        Err(error) => return Err(StatsError::Parse(format!("{}: {}", string_path, error))),
+    };
+
+    let mut message_vec: Vec<Message> = Vec::new();
+
+    for msg in export.messages {
+        // Match the "name#discriminator" tags of the CSV export
+        let author_name = if msg.author.discriminator.is_empty() {
+            msg.author.name
+        } else {
+            format!("{}#{}", msg.author.name, msg.author.discriminator)
+        };
+
+        let mut reactions: HashMap<String, u128> = HashMap::new();
+        for reaction in msg.reactions {
+            reactions.insert(reaction.emoji.name, reaction.count);
+        }
+
+        let author_id: u64 = match msg.author.id.parse() {
+            Ok(id) => id,
+            Err(_) => {
+                return Err(StatsError::Parse(format!(
+                    "{}: \"{}\" is not an author ID",
+                    string_path, msg.author.id
+                )))
+            }
+        };
+
+        let date = match DateTime::parse_from_rfc3339(&msg.timestamp) {
+            Ok(date) => date.naive_local(),
+            Err(_) => {
+                return Err(StatsError::Parse(format!(
+                    "{}: \"{}\" is not a timestamp",
+                    string_path, msg.timestamp
+                )))
+            }
+        };
+
+        let mut message = Message {
+            author_id: author_id,
+            channel_id: channel_id,
+            author_name: author_name,
+            date: date,
+            content: msg.content,
+            attachments: msg.attachments.into_iter().map(|a| a.url).collect(),
+            reactions: reactions,
+            is_bot: msg.author.is_bot,
+        };
+
+        if keep_message(&mut message, date_range, bot_filter) {
+            message_vec.push(message);
+        }
+    }
+
+    return Ok(message_vec);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::functions::progress::{LogMode, Reporter};
+    use std::path::PathBuf;
+
+    const CSV: &str = "\
+AuthorID,Author,Date,Content,Attachments,Reactions
+222222222222222222,alice#1234,15-Mar-20 06:30 PM,hello there?,,
+333333333333333333,bob#5678,16-Mar-21 07:30 AM,hi alice,,
+444444444444444444,Hooky#0000,17-Mar-21 11:00 PM,webhook post,,
+";
+
+    const JSON: &str = r#"{"messages":[
+{"timestamp":"2020-03-15T18:30:00+00:00","content":"hello there?","author":{"id":"222222222222222222","name":"alice","discriminator":"1234"}},
+{"timestamp":"2021-03-16T07:30:00+00:00","content":"hi alice","author":{"id":"333333333333333333","name":"bob","discriminator":"5678"}},
+{"timestamp":"2021-03-17T23:00:00+00:00","content":"beep","author":{"id":"777777777777777777","name":"MEE6","discriminator":"4876","isBot":true}}
+]}"#;
+
+    // A folder of its own in the temp folder, emptied first
+    fn source_folder(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("discord-statistics-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        return dir;
+    }
+
+    #[test]
+    fn channels_exported_twice_are_only_read_once() {
+        let dir = source_folder("mixed-formats");
+
+        fs::write(dir.join("Guild - Text - general [111111111111111111].csv"), CSV).unwrap();
+        fs::write(dir.join("Guild - Text - general [111111111111111111].json"), JSON).unwrap();
+        fs::write(dir.join("Guild - Text - memes [666666666666666666].csv"), CSV).unwrap();
+        fs::write(dir.join("Guild - Text - memes (old) [666666666666666666].csv"), CSV).unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+
+        let discovery = discover_files(dir.to_str().unwrap(), &ChannelFilter::all()).unwrap();
+
+        let files: Vec<(u64, &str)> = discovery.files.iter().map(|f| (f.channel.id, f.format())).collect();
+        assert_eq!(files, vec![(111111111111111111, "json"), (666666666666666666, "csv")]);
+
+        // json is kept over csv, and otherwise the first file
+        assert_eq!(discovery.duplicates.len(), 2);
+        assert!(discovery.duplicates[0].0.path.ends_with("general [111111111111111111].csv"));
+        assert!(discovery.duplicates[0].1.ends_with("general [111111111111111111].json"));
+        assert!(discovery.duplicates[1].0.path.ends_with("memes [666666666666666666].csv"));
+        assert_eq!(discovery.skipped.len(), 1);
+
+        let stage = Reporter::new(LogMode::Quiet).stage("Importing data", "files", 0);
+        let messages: usize = scrape_files(&discovery.files, DateRange::all(), &BotFilter::new(), &stage)
+            .into_iter()
+            .map(|result| result.unwrap().len())
+            .sum();
+
+        assert_eq!(messages, 6);
+
+        fs::remove_dir_all(&dir).unwrap();
     }
 
-    return message_vec;
+    #[test]
+    fn rows_are_filtered_as_they_are_read() {
+        let dir = source_folder("filtered-rows");
+        let csv_path = dir.join("general [111111111111111111].csv");
+        let json_path = dir.join("general [111111111111111111].json");
+        fs::write(&csv_path, CSV).unwrap();
+        fs::write(&json_path, JSON).unwrap();
+
+        let date_range = DateRange {
+            start: DateRange::parse_start("2021"),
+            end: None,
+        };
+
+        let mut bot_filter = BotFilter::new();
+        bot_filter.mode = BotMode::Exclude;
+
+        for path in [&csv_path, &json_path] {
+            let messages = scrape_file(path.to_string_lossy().to_string(), 1, date_range, bot_filter.clone()).unwrap();
+
+            let authors: Vec<u64> = messages.iter().map(|m| m.author_id).collect();
+            assert_eq!(authors, vec![333333333333333333]);
+        }
+
+        bot_filter.mode = BotMode::Separate;
+
+        let messages = scrape_file(csv_path.to_string_lossy().to_string(), 1, date_range, bot_filter).unwrap();
+        let bots: Vec<bool> = messages.iter().map(|m| m.is_bot).collect();
+        assert_eq!(bots, vec![false, true]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn bad_reaction_counts_name_the_file_and_row() {
+        let dir = source_folder("bad-reactions");
+        let path = dir.join("general [111111111111111111].csv");
+        fs::write(
+            &path,
+            format!("{}222222222222222222,alice#1234,18-Mar-21 10:00 AM,hi,,👍 (lots)\n", CSV),
+        )
+        .unwrap();
+
+        let error = scrape_file(path.to_string_lossy().to_string(), 1, DateRange::all(), BotFilter::new())
+            .err()
+            .unwrap();
+
+        assert_eq!(error.exit_code(), 4);
+        assert!(error.to_string().contains("general [111111111111111111].csv row 5"));
+        assert!(error.to_string().contains("\"👍 (lots)\" is not a reaction count"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,540 @@
+use chrono::NaiveDateTime;
+use serde_json::{json, Value};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use tiny_http::{Header, Response, Server};
+
+use super::create_authors::*;
+use super::error::StatsError;
+use super::export_json::{date_value, stats_json};
+use super::export_report::PLOTLY_JS;
+use super::export_stats::*;
+use super::filters::*;
+use super::progress::*;
+use super::query::*;
+use super::rankings::sorted_words;
+use super::structs::*;
+
+const DASHBOARD_HTML: &str = include_str!("dashboard.html");
+
+// How many date ranges are kept counted up at once, as each
+// one holds a copy of every author
+const CACHE_SIZE: usize = 8;
+
+// Everything counted up for one date range
+struct Aggregates {
+    server: Author,
+    authors: HashMap<u64, Author>,
+    bots: HashMap<u64, Author>,
+    channels: HashMap<u64, Author>,
+    // Messages sent by each author in each channel
+    channel_authors: HashMap<u64, HashMap<u64, u64>>,
+}
+
+type CacheKey = (Option<NaiveDateTime>, Option<NaiveDateTime>);
+
+// Holds the imported messages and answers the dashboard's
+// requests, keeping the date ranges most recently asked for
+// counted up
+pub struct Dashboard {
+    messages: Vec<Message>,
+    channel_id_dict: HashMap<u64, Channel>,
+    alias_groups: Vec<Vec<u64>>,
+    bot_mode: BotMode,
+    cache: HashMap<CacheKey, Aggregates>,
+    // The cached date ranges, least recently used first
+    cache_order: Vec<CacheKey>,
+}
+
+impl Dashboard {
+    pub fn new(
+        messages: Vec<Message>,
+        channel_id_dict: HashMap<u64, Channel>,
+        alias_groups: Vec<Vec<u64>>,
+        bot_mode: BotMode,
+    ) -> Dashboard {
+        return Dashboard {
+            messages: messages,
+            channel_id_dict: channel_id_dict,
+            alias_groups: alias_groups,
+            bot_mode: bot_mode,
+            cache: HashMap::new(),
+            cache_order: Vec::new(),
+        };
+    }
+
+    // Counts up the messages in the date range the same way
+    // a full export does
+    fn aggregates(&mut self, date_range: DateRange) -> Result<&Aggregates, StatsError> {
+        let key = (date_range.start, date_range.end);
+
+        if !self.cache.contains_key(&key) {
+            let messages: Vec<Message> = self
+                .messages
+                .iter()
+                .filter(|m| date_range.contains(&m.date))
+                .cloned()
+                .collect();
+
+            let stage = Reporter::new(LogMode::Quiet).stage("Processing authors", "messages", 0);
+            let channels = create_channels(&messages, self.bot_mode);
+            let authors = create_authors(&messages, self.bot_mode, stage)?;
+
+            let (server, authors, bots) = consolidate_authors(authors, &self.alias_groups, self.bot_mode)?;
+
+            let mut channel_authors: HashMap<u64, HashMap<u64, u64>> = HashMap::new();
+            for author in authors.values() {
+                for (channel_id, count) in author.messages_per_channel() {
+                    channel_authors.entry(channel_id).or_default().insert(author.id, count);
+                }
+            }
+
+            // Make room by dropping the range unused the longest
+            while self.cache.len() >= CACHE_SIZE && !self.cache_order.is_empty() {
+                let oldest = self.cache_order.remove(0);
+                self.cache.remove(&oldest);
+            }
+
+            self.cache.insert(
+                key,
+                Aggregates {
+                    server: server,
+                    authors: authors,
+                    bots: bots,
+                    channels: channels,
+                    channel_authors: channel_authors,
+                },
+            );
+        }
+
+        self.cache_order.retain(|cached| *cached != key);
+        self.cache_order.push(key);
+
+        return Ok(self.cache.get(&key).unwrap());
+    }
+
+    // Answers a single request, returning the status code,
+    // content type and body
+    pub fn handle(&mut self, url: &str) -> (u16, &'static str, String) {
+        let (path, query_string) = match url.find('?') {
+            Some(index) => (&url[..index], &url[index + 1..]),
+            None => (url, ""),
+        };
+
+        let params = parse_query_string(query_string);
+
+        let mut date_range = DateRange::all();
+
+        if let Some(from) = params.get("from").filter(|v| !v.is_empty()) {
+            match DateRange::parse_start(from) {
+                Some(start) => date_range.start = Some(start),
+                None => return error_response(400, &format!("Could not parse date \"{}\"", from)),
+            }
+        }
+
+        if let Some(to) = params.get("to").filter(|v| !v.is_empty()) {
+            match DateRange::parse_end(to) {
+                Some(end) => date_range.end = Some(end),
+                None => return error_response(400, &format!("Could not parse date \"{}\"", to)),
+            }
+        }
+
+        let parts: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+        if path == "/" || path == "/index.html" {
+            return (200, "text/html; charset=utf-8", String::from(DASHBOARD_HTML));
+        }
+
+        if path == "/plotly.min.js" {
+            return (200, "text/javascript; charset=utf-8", String::from(PLOTLY_JS));
+        }
+
+        if parts[0] != "api" {
+            return error_response(404, "Not found");
+        }
+
+        // Queries run straight over the messages
+        if parts.len() == 2 && parts[1] == "query" {
+            let text = params.get("q").cloned().unwrap_or_default();
+
+            return match Query::parse(&text) {
+                Ok(mut query) => {
+                    // The dashboard dates narrow down the query
+                    if query.filter.date_range.start.is_none() {
+                        query.filter.date_range.start = date_range.start;
+                    }
+                    if query.filter.date_range.end.is_none() {
+                        query.filter.date_range.end = date_range.end;
+                    }
+
+                    let table = query.run(&self.messages, &self.channel_id_dict);
+                    json_response(&table.to_json())
+                }
+                Err(error) => error_response(400, &error),
+            };
+        }
+
+        let channel_id_dict = self.channel_id_dict.clone();
+        let aggregates = match self.aggregates(date_range) {
+            Ok(aggregates) => aggregates,
+            Err(error) => return error_response(500, &error.to_string()),
+        };
+
+        // Routes for a single author or channel end with its ID
+        let id: Option<u64> = parts.last().and_then(|id| id.parse().ok());
+
+        return match (&parts[1..], id) {
+            (["server"], _) => json_response(&server_json(aggregates, &channel_id_dict)),
+            (["authors"], _) => {
+                let mut authors: Vec<&Author> = aggregates.authors.values().collect();
+                authors.sort_by_key(|author| Reverse(author.message_count));
+
+                let authors: Vec<Value> = authors.iter().map(|a| author_summary(a)).collect();
+                json_response(&Value::from(authors))
+            }
+            (["authors", _], Some(id)) => {
+                let author = aggregates.authors.get(&id).or(aggregates.bots.get(&id));
+
+                match author {
+                    Some(author) => json_response(&author_json(author, &channel_id_dict)),
+                    None => error_response(404, "No author with that ID"),
+                }
+            }
+            (["channels"], _) => {
+                let mut channels: Vec<&Author> = aggregates.channels.values().collect();
+                channels.sort_by_key(|channel| Reverse(channel.message_count));
+
+                let channels: Vec<Value> = channels
+                    .iter()
+                    .map(|c| channel_summary(c, aggregates, &channel_id_dict))
+                    .collect();
+                json_response(&Value::from(channels))
+            }
+            (["channels", _], Some(id)) => match aggregates.channels.get(&id) {
+                Some(channel) => json_response(&channel_json(channel, aggregates, &channel_id_dict)),
+                None => error_response(404, "No channel with that ID"),
+            },
+            (["graphs", "server-timeline"], _) => {
+                plot_response(server_timeline("Server Timeline Graph", &aggregates.server).to_json())
+            }
+            (["graphs", "server-timemap"], _) => {
+                plot_response(time_graph("Server Time Graph", &aggregates.server).to_json())
+            }
+            (["graphs", "channels"], _) => plot_response(
+                channel_graph("Channel Time Graph", &aggregates.server, &channel_id_dict).to_json(),
+            ),
+            (["graphs", "authors", _], Some(id)) => {
+                let author = aggregates.authors.get(&id).or(aggregates.bots.get(&id));
+
+                match author {
+                    Some(author) => {
+                        let title = format!("Time Map for {}", author.display_name());
+                        plot_response(time_graph(&title, author).to_json())
+                    }
+                    None => error_response(404, "No author with that ID"),
+                }
+            }
+            (["graphs", "channels", _], Some(id)) => match aggregates.channels.get(&id) {
+                Some(channel) => {
+                    let title = format!("Time Map for #{}", channel_name(id, &channel_id_dict));
+                    plot_response(time_graph(&title, channel).to_json())
+                }
+                None => error_response(404, "No channel with that ID"),
+            },
+            _ => error_response(404, "Not found"),
+        };
+    }
+}
+
+// Runs the dashboard on the given port until the process
+// is stopped
+pub fn serve(dashboard: &mut Dashboard, port: u16, reporter: &Reporter) -> Result<(), StatsError> {
+    let server = match Server::http(("127.0.0.1", port)) {
+        Ok(server) => server,
+        Err(error) => {
+            return Err(StatsError::Io(format!(
+                "Could not start the server on port {}! {}",
+                port, error
+            )))
+        }
+    };
+
+    reporter.info(&format!("Dashboard running at http://127.0.0.1:{}/", port));
+
+    for request in server.incoming_requests() {
+        let (status, content_type, body) = dashboard.handle(request.url());
+
+        let response = Response::from_string(body)
+            .with_status_code(status)
+            .with_header(Header::from_bytes(&b"Content-Type"[..], content_type.as_bytes()).unwrap());
+
+        if let Err(error) = request.respond(response) {
+            reporter.info(&format!("Could not send response! {}", error));
+        }
+    }
+
+    Ok(())
+}
+
+fn json_response(value: &Value) -> (u16, &'static str, String) {
+    return (200, "application/json", value.to_string());
+}
+
+fn plot_response(plot_json: String) -> (u16, &'static str, String) {
+    return (200, "application/json", plot_json);
+}
+
+fn error_response(status: u16, message: &str) -> (u16, &'static str, String) {
+    return (status, "application/json", json!({ "error": message }).to_string());
+}
+
+fn channel_name(id: u64, channel_id_dict: &HashMap<u64, Channel>) -> String {
+    return match channel_id_dict.get(&id) {
+        Some(channel) => channel.name.clone(),
+        None => id.to_string(),
+    };
+}
+
+fn top_words(author: &Author, count: usize) -> Vec<Value> {
+    return sorted_words(author)
+        .iter()
+        .take(count)
+        .map(|(word, count)| json!({ "word": word, "count": **count as u64 }))
+        .collect();
+}
+
+// IDs are sent as strings, as they are too large for
+// JavaScript numbers
+fn author_summary(author: &Author) -> Value {
+    return json!({
+        "id": author.id.to_string(),
+        "name": author.display_name(),
+        "is_bot": author.is_bot,
+        "merged_ids": author.merged_ids.iter().map(|id| id.to_string()).collect::<Vec<String>>(),
+        "stats": stats_json(author),
+    });
+}
+
+fn author_json(author: &Author, channel_id_dict: &HashMap<u64, Channel>) -> Value {
+    let mut channels: Vec<(u64, u64)> = author.messages_per_channel().into_iter().collect();
+    channels.sort_by_key(|&(_, count)| Reverse(count));
+
+    let names: Vec<Value> = author
+        .name_history()
+        .iter()
+        .map(|record| {
+            json!({
+                "name": record.name,
+                "first_seen": date_value(Some(record.first_seen)),
+                "last_seen": date_value(Some(record.last_seen)),
+            })
+        })
+        .collect();
+
+    let mut value = author_summary(author);
+    value["names"] = Value::from(names);
+    value["top_words"] = Value::from(top_words(author, 50));
+    value["channels"] = Value::from(
+        channels
+            .iter()
+            .map(|(id, count)| {
+                json!({
+                    "id": id.to_string(),
+                    "name": channel_name(*id, channel_id_dict),
+                    "messages": count,
+                })
+            })
+            .collect::<Vec<Value>>(),
+    );
+
+    return value;
+}
+
+fn channel_summary(
+    channel: &Author,
+    aggregates: &Aggregates,
+    channel_id_dict: &HashMap<u64, Channel>,
+) -> Value {
+    let (category, guild) = match channel_id_dict.get(&channel.id) {
+        Some(info) => (info.category.clone(), info.guild.clone()),
+        None => (String::new(), String::new()),
+    };
+
+    let members = match aggregates.channel_authors.get(&channel.id) {
+        Some(authors) => authors.len(),
+        None => 0,
+    };
+
+    return json!({
+        "id": channel.id.to_string(),
+        "name": channel_name(channel.id, channel_id_dict),
+        "category": category,
+        "guild": guild,
+        "members": members,
+        "stats": stats_json(channel),
+    });
+}
+
+fn channel_json(
+    channel: &Author,
+    aggregates: &Aggregates,
+    channel_id_dict: &HashMap<u64, Channel>,
+) -> Value {
+    let mut authors: Vec<(u64, u64)> = match aggregates.channel_authors.get(&channel.id) {
+        Some(authors) => authors.iter().map(|(id, count)| (*id, *count)).collect(),
+        None => Vec::new(),
+    };
+    authors.sort_by_key(|&(_, count)| Reverse(count));
+
+    let mut value = channel_summary(channel, aggregates, channel_id_dict);
+    value["top_words"] = Value::from(top_words(channel, 50));
+    value["authors"] = Value::from(
+        authors
+            .iter()
+            .map(|(id, count)| {
+                let name = match aggregates.authors.get(id) {
+                    Some(author) => author.display_name().to_string(),
+                    None => id.to_string(),
+                };
+
+                json!({ "id": id.to_string(), "name": name, "messages": *count })
+            })
+            .collect::<Vec<Value>>(),
+    );
+
+    return value;
+}
+
+fn server_json(aggregates: &Aggregates, channel_id_dict: &HashMap<u64, Channel>) -> Value {
+    let mut bots: Vec<&Author> = aggregates.bots.values().collect();
+    bots.sort_by_key(|author| Reverse(author.message_count));
+
+    return json!({
+        "stats": stats_json(&aggregates.server),
+        "members": aggregates.authors.len(),
+        "channels": aggregates.channels.len(),
+        "guilds": channel_id_dict
+            .values()
+            .map(|c| c.guild.clone())
+            .collect::<std::collections::BTreeSet<String>>(),
+        "top_words": top_words(&aggregates.server, 100),
+        "bots": bots.iter().map(|b| author_summary(b)).collect::<Vec<Value>>(),
+    });
+}
+
+// Splits "a=1&b=2" into its decoded keys and values
+fn parse_query_string(query_string: &str) -> HashMap<String, String> {
+    let mut params: HashMap<String, String> = HashMap::new();
+
+    for pair in query_string.split('&').filter(|p| !p.is_empty()) {
+        let (key, value) = match pair.find('=') {
+            Some(index) => (&pair[..index], &pair[index + 1..]),
+            None => (pair, ""),
+        };
+
+        params.insert(percent_decode(key), percent_decode(value));
+    }
+
+    return params;
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded: Vec<u8> = Vec::new();
+    let mut index = 0;
+
+    while index < bytes.len() {
+        match bytes[index] {
+            b'+' => decoded.push(b' '),
+            b'%' if index + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[index + 1..index + 3]).unwrap_or("");
+
+                match u8::from_str_radix(hex, 16) {
+                    Ok(byte) => {
+                        decoded.push(byte);
+                        index += 2;
+                    }
+                    Err(_) => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        index += 1;
+    }
+
+    return String::from_utf8_lossy(&decoded).to_string();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(author_id: u64, day: u32, is_bot: bool) -> Message {
+        return Message {
+            author_id: author_id,
+            channel_id: 111,
+            author_name: format!("author{}", author_id),
+            date: DateRange::parse_start(&format!("2023-01-{:02}", day)).unwrap(),
+            content: String::from("hello"),
+            attachments: Vec::new(),
+            reactions: HashMap::new(),
+            is_bot: is_bot,
+        };
+    }
+
+    fn dashboard(bot_mode: BotMode) -> Dashboard {
+        let messages = vec![message(222, 1, false), message(333, 2, false), message(444, 3, true)];
+        return Dashboard::new(messages, HashMap::new(), Vec::new(), bot_mode);
+    }
+
+    fn body(dashboard: &mut Dashboard, url: &str) -> Value {
+        let (status, _, body) = dashboard.handle(url);
+        assert_eq!(status, 200, "{}", body);
+        return serde_json::from_str(&body).unwrap();
+    }
+
+    #[test]
+    fn plotly_is_served_from_the_bundled_copy() {
+        let mut dashboard = dashboard(BotMode::Include);
+
+        let (status, content_type, body) = dashboard.handle("/plotly.min.js");
+        assert_eq!(status, 200);
+        assert!(content_type.starts_with("text/javascript"));
+        assert!(body.contains("plotly.js v1.54.6"));
+
+        let (_, _, page) = dashboard.handle("/");
+        assert!(!page.contains("cdn.plot.ly"));
+    }
+
+    #[test]
+    fn channel_totals_match_the_server_totals() {
+        for (bot_mode, expected) in [(BotMode::Include, 3), (BotMode::Separate, 2)] {
+            let mut dashboard = dashboard(bot_mode);
+
+            let server = body(&mut dashboard, "/api/server");
+            let channels = body(&mut dashboard, "/api/channels");
+
+            assert_eq!(server["stats"]["messages"], expected);
+            assert_eq!(channels[0]["stats"]["messages"], expected);
+        }
+    }
+
+    #[test]
+    fn cache_keeps_the_most_recently_used_ranges() {
+        let mut dashboard = dashboard(BotMode::Include);
+
+        for day in 1..=CACHE_SIZE + 3 {
+            body(&mut dashboard, &format!("/api/server?from=2023-01-{:02}", day));
+            // Keep the first range in use throughout
+            body(&mut dashboard, "/api/server?from=2023-01-01");
+        }
+
+        assert_eq!(dashboard.cache.len(), CACHE_SIZE);
+        assert_eq!(dashboard.cache_order.len(), CACHE_SIZE);
+
+        let first = DateRange::parse_start("2023-01-01");
+        let second = DateRange::parse_start("2023-01-02");
+        assert!(dashboard.cache.contains_key(&(first, None)));
+        assert!(!dashboard.cache.contains_key(&(second, None)));
+    }
+}
@@ -0,0 +1,294 @@
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{BufReader, BufWriter};
+
+use super::create_authors::*;
+use super::error::StatsError;
+use super::filters::*;
+use super::progress::Stage;
+use super::structs::*;
+
+// Bumped whenever the layout of a snapshot changes, so old
+// versions of the program don't misread newer snapshots
+pub const SNAPSHOT_VERSION: u32 = 3;
+
+// Everything the reports are made from, once the messages
+// have been counted up. Reports can be regenerated from a
+// snapshot without the raw exports it was made from.
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    // When the snapshot was taken
+    pub created: NaiveDateTime,
+    // The dates the messages were limited to
+    pub date_range: DateRange,
+    // How bots were treated when counting up
+    pub bot_mode: BotMode,
+    // The alt accounts merged into each main account, which
+    // snapshots before version 3 didn't keep
+    #[serde(default)]
+    pub alias_groups: Option<Vec<Vec<u64>>>,
+    // Totals for the whole server
+    pub server: Author,
+    // Every member, with alt accounts already merged
+    pub authors: HashMap<u64, Author>,
+    // Bots, when they are kept separate from the members
+    pub bots: HashMap<u64, Author>,
+    // Totals for each channel, keyed by channel ID
+    pub channels: HashMap<u64, Author>,
+    // The name, category and guild of each channel
+    pub channel_info: HashMap<u64, Channel>,
+    // Channels left out by the channel filters
+    pub excluded_channels: Vec<Channel>,
+}
+
+impl Snapshot {
+    pub fn save(&self, path: &str) -> Result<(), StatsError> {
+        let file = match fs::File::create(path) {
+            Ok(file) => file,
+            Err(error) => return Err(StatsError::Io(format!("Could not create {}! {}", path, error))),
+        };
+
+        if let Err(error) = serde_json::to_writer(BufWriter::new(file), self) {
+            return Err(StatsError::Export(format!("Could not write {}! {}", path, error)));
+        }
+
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<Snapshot, StatsError> {
+        let file = match fs::File::open(path) {
+            Ok(file) => file,
+            Err(error) => return Err(StatsError::Io(format!("Could not read {}! {}", path, error))),
+        };
+
+        let snapshot: Snapshot = match serde_json::from_reader(BufReader::new(file)) {
+            Ok(snapshot) => snapshot,
+            Err(error) => return Err(StatsError::Parse(format!("{}: {}", path, error))),
+        };
+
+        if snapshot.version > SNAPSHOT_VERSION {
+            return Err(StatsError::Parse(format!(
+                "{} is a version {} snapshot, but only up to version {} can be read",
+                path, snapshot.version, SNAPSHOT_VERSION
+            )));
+        }
+
+        return Ok(snapshot);
+    }
+}
+
+// How the messages of an archive are counted up, which
+// every snapshot in it shares
+pub struct ArchiveSettings {
+    pub date_range: DateRange,
+    pub bot_mode: BotMode,
+    pub alias_groups: Vec<Vec<u64>>,
+}
+
+// Saves a snapshot for the end of every month the messages
+// cover into the given folder, named "Snapshot YYYY-MM.json".
+// Each one counts up everything from the start up to the end
+// of its month, so any two can be compared. Returns the paths
+// of the snapshots written.
+pub fn archive_monthly(
+    dir: &str,
+    messages: &[Message],
+    channel_info: &HashMap<u64, Channel>,
+    excluded_channels: &[Channel],
+    settings: &ArchiveSettings,
+    stage: &Stage,
+) -> Result<Vec<String>, StatsError> {
+    let date_range = settings.date_range;
+    let bot_mode = settings.bot_mode;
+    let alias_groups = &settings.alias_groups;
+
+    if let Err(error) = fs::create_dir_all(dir) {
+        return Err(StatsError::Io(format!("Could not create {}! {}", dir, error)));
+    }
+
+    let mut months: BTreeMap<(i32, u32), Vec<&Message>> = BTreeMap::new();
+    for message in messages {
+        months
+            .entry((message.date.year(), message.date.month()))
+            .or_default()
+            .push(message);
+    }
+
+    // Running totals up to the end of the current month
+    let mut authors: HashMap<u64, Author> = HashMap::new();
+    let mut channels: HashMap<u64, Author> = HashMap::new();
+
+    // Lent to each snapshot while it is saved, and taken
+    // back afterwards, rather than copied for every month
+    let mut channel_info = channel_info.clone();
+    let mut excluded_channels = excluded_channels.to_vec();
+
+    let mut paths: Vec<String> = Vec::new();
+
+    for ((year, month), month_messages) in months {
+        merge_author_parts(&mut channels, create_channels(month_messages.iter().copied(), bot_mode));
+        merge_author_parts(
+            &mut authors,
+            create_authors(month_messages.iter().copied(), bot_mode, stage.clone())?,
+        );
+
+        // Merging the alt accounts changes the authors, so the
+        // running totals are kept apart from what is saved
+        let (server, members, bots) = consolidate_authors(authors.clone(), alias_groups, bot_mode)?;
+
+        let next_month = if month == 12 {
+            NaiveDate::from_ymd_opt(year + 1, 1, 1)
+        } else {
+            NaiveDate::from_ymd_opt(year, month + 1, 1)
+        };
+
+        // The snapshot covers up to the end of the month, or
+        // the end of the range if that comes first
+        let mut month_range = date_range;
+        let month_end = next_month.and_then(|date| date.and_hms_opt(0, 0, 0));
+        if date_range.end.is_none() || month_end < date_range.end {
+            month_range.end = month_end;
+        }
+
+        let snapshot = Snapshot {
+            version: SNAPSHOT_VERSION,
+            created: Local::now().naive_local(),
+            date_range: month_range,
+            bot_mode: bot_mode,
+            alias_groups: Some(alias_groups.to_vec()),
+            server: server,
+            authors: members,
+            bots: bots,
+            channels: channels,
+            channel_info: channel_info,
+            excluded_channels: excluded_channels,
+        };
+
+        let path = format!(
+            "{}{}Snapshot {:04}-{:02}.json",
+            dir,
+            std::path::MAIN_SEPARATOR,
+            year,
+            month
+        );
+        snapshot.save(&path)?;
+
+        channels = snapshot.channels;
+        channel_info = snapshot.channel_info;
+        excluded_channels = snapshot.excluded_channels;
+
+        paths.push(path);
+    }
+
+    return Ok(paths);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::functions::progress::{LogMode, Reporter};
+
+    // A folder of its own in the temp folder, emptied first
+    fn temp_folder(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("discord-statistics-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        return dir.to_string_lossy().to_string();
+    }
+
+    fn message(author_id: u64, date: &str) -> Message {
+        return Message {
+            author_id: author_id,
+            channel_id: 111,
+            author_name: format!("author{}", author_id),
+            date: NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M").unwrap(),
+            content: String::from("hello there"),
+            attachments: Vec::new(),
+            reactions: HashMap::new(),
+            is_bot: false,
+        };
+    }
+
+    fn archive(dir: &str, alias_groups: &[Vec<u64>]) -> Vec<String> {
+        let messages = vec![
+            message(222, "2023-01-05 10:00"),
+            message(333, "2023-01-06 10:00"),
+            message(222, "2023-03-01 10:00"),
+        ];
+        let stage = Reporter::new(LogMode::Quiet).stage("Archiving snapshots", "messages", 0);
+
+        return archive_monthly(
+            dir,
+            &messages,
+            &HashMap::new(),
+            &[],
+            &ArchiveSettings {
+                date_range: DateRange::all(),
+                bot_mode: BotMode::Include,
+                alias_groups: alias_groups.to_vec(),
+            },
+            &stage,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn monthly_snapshots_count_up_to_the_end_of_each_month() {
+        let dir = temp_folder("archive-monthly");
+
+        let paths = archive(&dir, &[vec![222, 333]]);
+
+        assert_eq!(paths.len(), 2);
+        assert!(paths[0].ends_with("Snapshot 2023-01.json"));
+        assert!(paths[1].ends_with("Snapshot 2023-03.json"));
+
+        let january = Snapshot::load(&paths[0]).unwrap();
+        let march = Snapshot::load(&paths[1]).unwrap();
+
+        assert_eq!(january.server.message_count, 2);
+        assert_eq!(march.server.message_count, 3);
+        assert_eq!(january.date_range.end, DateRange::parse_start("2023-02"));
+        // The alt account is merged into the main account
+        assert_eq!(march.authors.len(), 1);
+        assert_eq!(march.authors[&222].message_count, 3);
+        assert_eq!(march.alias_groups, Some(vec![vec![222, 333]]));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn newer_snapshots_are_refused() {
+        let dir = temp_folder("snapshot-version");
+        let path = archive(&dir, &[]).remove(0);
+
+        let mut snapshot = Snapshot::load(&path).unwrap();
+        snapshot.version = SNAPSHOT_VERSION + 1;
+        snapshot.save(&path).unwrap();
+
+        let error = Snapshot::load(&path).err().unwrap();
+        assert_eq!(error.exit_code(), 4);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn older_snapshots_without_aliases_still_load() {
+        let dir = temp_folder("snapshot-old");
+        let path = archive(&dir, &[]).remove(0);
+
+        let mut value: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        value["version"] = serde_json::Value::from(1);
+        value.as_object_mut().unwrap().remove("alias_groups");
+        fs::write(&path, value.to_string()).unwrap();
+
+        let snapshot = Snapshot::load(&path).unwrap();
+        assert_eq!(snapshot.alias_groups, None);
+        assert_eq!(snapshot.server.message_count, 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,15 +1,18 @@
 use chrono::NaiveDateTime;
 use csv::StringRecord;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::Path;
 
 pub const DATE_FORMAT: &str = "%d-%b-%y %I:%M %p";
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Author {
     // Numerical Author ID
     pub id: u64,
-    // List of all discord tags associated with the ID
-    pub names: Vec<String>,
+    // History of all discord tags associated with the ID
+    pub names: Vec<NameRecord>,
     // Total Message Count
     pub message_count: u128,
     // Total Word Count
@@ -24,12 +27,20 @@ pub struct Author {
     pub times_minority: u128,
     // Ledger of timestamps and channel IDs for each of their messages
     pub time_ledger: Vec<(NaiveDateTime, u64)>,
+    // Ledger of timestamps and which of their names, as an
+    // index into names, each of their messages was sent under
+    #[serde(default)]
+    pub name_ledger: Vec<(NaiveDateTime, usize)>,
     // Ledger of all attachments they have sent
     pub attachments_ledger: Vec<String>,
     // Dictionary of their vocabulary
     pub vocab_dict: HashMap<String, u128>,
     // A hashmap of who they have agreed with in polls
     pub agreement_dict: HashMap<String, u128>,
+    // Whether the account was detected to be a bot or webhook
+    pub is_bot: bool,
+    // IDs of other accounts that were merged into this one
+    pub merged_ids: Vec<u64>,
 }
 
 impl Author {
@@ -44,16 +55,41 @@ impl Author {
             times_majority: 0,
             times_minority: 0,
             time_ledger: Vec::new(),
+            name_ledger: Vec::new(),
             attachments_ledger: Vec::new(),
             vocab_dict: HashMap::new(),
             agreement_dict: HashMap::new(),
+            is_bot: false,
+            merged_ids: Vec::new(),
         };
     }
 
-    pub fn process_message(mut self, mut msg: Message) -> Self {
-        // Add to all known names
-        if !self.names.contains(&msg.author_name) {
-            self.names.push(msg.author_name.clone());
+    pub fn process_message(mut self, msg: Message) -> Self {
+        self.record_message(&msg);
+
+        return self;
+    }
+
+    // Counts a message in place, for running totals that
+    // take in many messages, like those of a channel
+    pub fn record_message(&mut self, msg: &Message) {
+        // Add to all known names, or widen the time the
+        // name has been seen in use
+        let name_index = match self.names.iter().position(|n| n.name == msg.author_name) {
+            Some(index) => {
+                self.names[index].update(msg.date, msg.date);
+                index
+            }
+            None => {
+                self.names.push(NameRecord::new(msg.author_name.clone(), msg.date));
+                self.names.len() - 1
+            }
+        };
+
+        self.name_ledger.push((msg.date, name_index));
+
+        if msg.is_bot {
+            self.is_bot = true;
         }
 
         // Create temp list of words in message
@@ -74,23 +110,48 @@ impl Author {
         self.time_ledger.push((msg.date, msg.channel_id));
 
         // Add to attachment ledger
-        self.attachments_ledger.append(&mut msg.attachments);
+        self.attachments_ledger.extend(msg.attachments.iter().cloned());
 
         // Add to vocab dict
         for word in word_list {
             let vocab = self.vocab_dict.entry(String::from(word)).or_insert(0);
             *vocab += 1;
         }
-
-        return self;
     }
 
     pub fn merge(mut self, mut other: Author) -> Self {
         // Add to all known names
 
-        for name in other.names.clone() {
-            if !self.names.contains(&name) {
-                self.names.push(name);
+        // Where each of the other author's names ends up
+        let mut name_indexes: Vec<usize> = Vec::new();
+
+        for other_record in other.names.clone() {
+            match self.names.iter().position(|n| n.name == other_record.name) {
+                Some(index) => {
+                    self.names[index].update(other_record.first_seen, other_record.last_seen);
+                    name_indexes.push(index);
+                }
+                None => {
+                    self.names.push(other_record);
+                    name_indexes.push(self.names.len() - 1);
+                }
+            }
+        }
+
+        for (date, index) in other.name_ledger.drain(..) {
+            self.name_ledger.push((date, name_indexes[index]));
+        }
+
+        self.is_bot = self.is_bot || other.is_bot;
+
+        // Keep track of any other accounts folded into this one
+        if other.id != self.id && !self.merged_ids.contains(&other.id) {
+            self.merged_ids.push(other.id);
+        }
+
+        for id in other.merged_ids.clone() {
+            if id != self.id && !self.merged_ids.contains(&id) {
+                self.merged_ids.push(id);
             }
         }
 
@@ -119,9 +180,74 @@ impl Author {
         return self;
     }
 
+    // The most recently used name, which is what the
+    // reports show for the author
+    pub fn display_name(&self) -> &str {
+        return match self.names.iter().max_by_key(|n| (n.last_seen, n.first_seen)) {
+            Some(record) => record.name.as_str(),
+            None => "",
+        };
+    }
+
+    // All names the author has gone by, oldest first
+    pub fn name_list(&self) -> Vec<String> {
+        return self.name_history().iter().map(|n| n.name.clone()).collect();
+    }
+
+    // Name records sorted by when each name was first seen
+    pub fn name_history(&self) -> Vec<NameRecord> {
+        let mut history = self.names.clone();
+        history.sort_by_key(|name| name.first_seen);
+
+        return history;
+    }
+
+    // Every time the author started going by another name,
+    // in order, worked out from the names their messages were
+    // sent under, so changing back to an old name counts too
+    pub fn name_changes(&self) -> Vec<NameChange> {
+        let mut ledger = self.name_ledger.clone();
+        ledger.sort();
+
+        let mut changes: Vec<NameChange> = Vec::new();
+
+        for pair in ledger.windows(2) {
+            let ((_, previous), (date, current)) = (pair[0], pair[1]);
+
+            if previous != current {
+                changes.push(NameChange {
+                    date: date,
+                    previous_name: self.names[previous].name.clone(),
+                    new_name: self.names[current].name.clone(),
+                });
+            }
+        }
+
+        return changes;
+    }
+
+    // When the first and last of their messages were sent
+    pub fn first_and_last(&self) -> (Option<NaiveDateTime>, Option<NaiveDateTime>) {
+        let first = self.time_ledger.iter().map(|(date, _)| *date).min();
+        let last = self.time_ledger.iter().map(|(date, _)| *date).max();
+
+        return (first, last);
+    }
+
+    // How many of their messages were sent in each channel
+    pub fn messages_per_channel(&self) -> HashMap<u64, u64> {
+        let mut channels: HashMap<u64, u64> = HashMap::new();
+        for (_, channel_id) in &self.time_ledger {
+            *channels.entry(*channel_id).or_insert(0) += 1;
+        }
+
+        return channels;
+    }
+
     pub fn print_stats(self) {
         println!("ID: {}", self.id);
-        println!("Name(s): {:?}", self.names);
+        println!("Name(s): {:?}", self.name_list());
+        println!("Merged ID(s): {:?}", self.merged_ids);
         println!(
             "Messages: {} Words: {} Characters: {} Questions: {}",
             self.message_count, self.word_count, self.character_count, self.question_count
@@ -143,7 +269,46 @@ impl Author {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NameRecord {
+    // The discord tag itself
+    pub name: String,
+    // Timestamp of the first message sent under the name
+    pub first_seen: NaiveDateTime,
+    // Timestamp of the last message sent under the name
+    pub last_seen: NaiveDateTime,
+}
+
+impl NameRecord {
+    pub fn new(name: String, date: NaiveDateTime) -> NameRecord {
+        return NameRecord {
+            name: name,
+            first_seen: date,
+            last_seen: date,
+        };
+    }
+
+    pub fn update(&mut self, first_seen: NaiveDateTime, last_seen: NaiveDateTime) {
+        if first_seen < self.first_seen {
+            self.first_seen = first_seen;
+        }
+
+        if last_seen > self.last_seen {
+            self.last_seen = last_seen;
+        }
+    }
+}
+
+// A change from one name to another, dated by the first
+// message sent under the new name
+#[derive(Clone, Debug, PartialEq)]
+pub struct NameChange {
+    pub date: NaiveDateTime,
+    pub previous_name: String,
+    pub new_name: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Message {
     pub author_id: u64,
     pub channel_id: u64,
@@ -152,24 +317,38 @@ pub struct Message {
     pub content: String,
     pub attachments: Vec<String>,
     pub reactions: HashMap<String, u128>,
+    // Set when the export metadata or the bot filter marks
+    // the author of the message as a bot
+    pub is_bot: bool,
 }
 
 impl Message {
-    pub fn from_csv_string(record: StringRecord, channel_id: u64) -> Message {
-        let author_id: u64 = record.get(0).unwrap().parse().unwrap();
-        let author_name: String = String::from(record.get(1).unwrap());
-        let date: NaiveDateTime =
-            NaiveDateTime::parse_from_str(record.get(2).unwrap(), DATE_FORMAT).unwrap();
-        let content: String = String::from(record.get(3).unwrap());
+    // Reads a row of a DiscordChatExporter CSV export, or
+    // says what is wrong with it
+    pub fn from_csv_string(record: StringRecord, channel_id: u64) -> Result<Message, String> {
+        if record.len() < 6 {
+            return Err(format!("expected 6 columns, found {}", record.len()));
+        }
+
+        let author_id: u64 = match record[0].parse() {
+            Ok(id) => id,
+            Err(_) => return Err(format!("\"{}\" is not an author ID", &record[0])),
+        };
+        let author_name: String = String::from(&record[1]);
+        let date: NaiveDateTime = match NaiveDateTime::parse_from_str(&record[2], DATE_FORMAT) {
+            Ok(date) => date,
+            Err(_) => return Err(format!("\"{}\" is not a date", &record[2])),
+        };
+        let content: String = String::from(&record[3]);
         let mut attachments: Vec<String> = Vec::new();
-        if record.get(4).unwrap().len() > 5 {
-            let attachments_split = record.get(4).unwrap().split(',');
+        if record[4].len() > 5 {
+            let attachments_split = record[4].split(',');
             for s in attachments_split {
                 attachments.push(String::from(s));
             }
         }
 
-        let reactions_string = record.get(5).unwrap();
+        let reactions_string = &record[5];
         let mut reactions: HashMap<String, u128> = HashMap::new();
 
         if reactions_string.len() > 5 {
@@ -178,18 +357,22 @@ impl Message {
             for s in reactions_split {
                 let reaction_key_value = s.split(' ').collect::<Vec<&str>>();
 
-                reactions.insert(
-                    String::from(reaction_key_value[0]),
-                    reaction_key_value[1]
-                        .replace("(", "")
-                        .replace(")", "")
-                        .parse()
-                        .unwrap_or_default(),
-                );
+                let count_text = reaction_key_value
+                    .get(1)
+                    .unwrap_or(&"")
+                    .replace("(", "")
+                    .replace(")", "");
+
+                let count: u128 = match count_text.parse() {
+                    Ok(count) => count,
+                    Err(_) => return Err(format!("\"{}\" is not a reaction count", s)),
+                };
+
+                reactions.insert(String::from(reaction_key_value[0]), count);
             }
         }
 
-        return Message {
+        return Ok(Message {
             author_id: author_id,
             channel_id: channel_id,
             author_name: author_name,
@@ -197,6 +380,175 @@ impl Message {
             content: content,
             attachments: attachments,
             reactions: reactions,
+            is_bot: false,
+        });
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Channel {
+    // Numerical Channel ID
+    pub id: u64,
+    // Name of the guild (server) the channel belongs to
+    pub guild: String,
+    // Name of the category the channel is sorted under, if any
+    pub category: String,
+    // Name of the channel itself
+    pub name: String,
+}
+
+impl Channel {
+    // Reads the channel out of an export file name, which
+    // DiscordChatExporter writes by default as:
+    // "Guild - Category - channel [123456789012345678].csv"
+    pub fn from_path(path: &Path) -> Option<Channel> {
+        let file_stem = path.file_stem()?.to_str()?;
+
+        let channel_id_regex = Regex::new(r#"\[([0-9]{17,20})\]"#).unwrap();
+        let captures = channel_id_regex.captures_iter(file_stem).last()?;
+
+        let id: u64 = captures.get(1)?.as_str().parse().ok()?;
+        let prefix = file_stem[..captures.get(0)?.start()].trim();
+
+        let mut parts: Vec<&str> = prefix.split(" - ").collect();
+
+        let name = String::from(parts.pop().unwrap_or(""));
+        let category = if parts.len() >= 2 {
+            String::from(parts.pop().unwrap())
+        } else {
+            String::new()
+        };
+        let guild = parts.join(" - ");
+
+        return Some(Channel {
+            id: id,
+            guild: guild,
+            category: category,
+            name: name,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(name: &str, date: &str) -> Message {
+        return Message {
+            author_id: 222,
+            channel_id: 111,
+            author_name: String::from(name),
+            date: NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M").unwrap(),
+            content: String::from("hello"),
+            attachments: Vec::new(),
+            reactions: HashMap::new(),
+            is_bot: false,
         };
     }
+
+    fn changes(author: &Author) -> Vec<(String, String, String)> {
+        return author
+            .name_changes()
+            .into_iter()
+            .map(|change| {
+                (
+                    change.date.format("%Y-%m-%d").to_string(),
+                    change.previous_name,
+                    change.new_name,
+                )
+            })
+            .collect();
+    }
+
+    #[test]
+    fn changing_back_to_an_old_name_is_a_change() {
+        let mut author = Author::new(222);
+        for (name, date) in [
+            ("alice", "2023-01-01 10:00"),
+            ("bob", "2023-02-01 10:00"),
+            ("bob", "2023-02-02 10:00"),
+            ("alice", "2023-03-01 10:00"),
+        ] {
+            author = author.process_message(message(name, date));
+        }
+
+        assert_eq!(
+            changes(&author),
+            vec![
+                (String::from("2023-02-01"), String::from("alice"), String::from("bob")),
+                (String::from("2023-03-01"), String::from("bob"), String::from("alice")),
+            ]
+        );
+        assert_eq!(author.names.len(), 2);
+    }
+
+    #[test]
+    fn name_changes_follow_the_dates_not_the_reading_order() {
+        let mut author = Author::new(222);
+        for (name, date) in [
+            ("alice", "2023-03-01 10:00"),
+            ("bob", "2023-02-01 10:00"),
+            ("alice", "2023-01-01 10:00"),
+        ] {
+            author = author.process_message(message(name, date));
+        }
+
+        assert_eq!(
+            changes(&author),
+            vec![
+                (String::from("2023-02-01"), String::from("alice"), String::from("bob")),
+                (String::from("2023-03-01"), String::from("bob"), String::from("alice")),
+            ]
+        );
+    }
+
+    #[test]
+    fn name_changes_survive_merging_parts() {
+        // As when the messages are counted up on more than one thread
+        let first = Author::new(222)
+            .process_message(message("bob", "2023-02-01 10:00"))
+            .process_message(message("alice", "2023-03-01 10:00"));
+        let second = Author::new(222)
+            .process_message(message("alice", "2023-01-01 10:00"))
+            .process_message(message("carol", "2023-04-01 10:00"));
+
+        let author = first.merge(second);
+
+        assert_eq!(
+            changes(&author),
+            vec![
+                (String::from("2023-02-01"), String::from("alice"), String::from("bob")),
+                (String::from("2023-03-01"), String::from("bob"), String::from("alice")),
+                (String::from("2023-04-01"), String::from("alice"), String::from("carol")),
+            ]
+        );
+    }
+
+    #[test]
+    fn one_name_has_no_changes() {
+        let author = Author::new(222)
+            .process_message(message("alice", "2023-01-01 10:00"))
+            .process_message(message("alice", "2023-02-01 10:00"));
+
+        assert!(author.name_changes().is_empty());
+    }
+
+    #[test]
+    fn reactions_are_read_with_their_counts() {
+        let record = StringRecord::from(vec!["222", "alice#1234", "18-Mar-21 10:00 AM", "hi", "", "👍 (3),🎉 (12)"]);
+
+        let message = Message::from_csv_string(record, 111).unwrap();
+
+        assert_eq!(message.reactions["👍"], 3);
+        assert_eq!(message.reactions["🎉"], 12);
+    }
+
+    #[test]
+    fn reaction_counts_that_are_not_numbers_are_refused() {
+        let record = StringRecord::from(vec!["222", "alice#1234", "18-Mar-21 10:00 AM", "hi", "", "👍 (3),🎉 (lots)"]);
+
+        let error = Message::from_csv_string(record, 111).err().unwrap();
+
+        assert_eq!(error, "\"🎉 (lots)\" is not a reaction count");
+    }
 }
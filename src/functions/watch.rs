@@ -0,0 +1,489 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use super::create_authors::*;
+use super::export_stats::*;
+use super::filters::*;
+use super::progress::*;
+use super::scrape_file::*;
+use super::structs::*;
+
+// A file that has been imported, along with everything
+// worked out from it, so it only has to be redone when
+// the file itself changes
+struct WatchedFile {
+    stamp: (SystemTime, u64),
+    messages: Vec<Message>,
+    authors: HashMap<u64, Author>,
+}
+
+impl WatchedFile {
+    // Stands in for a file that couldn't be counted up, so it
+    // is only tried again once it changes
+    fn empty(stamp: (SystemTime, u64)) -> WatchedFile {
+        return WatchedFile {
+            stamp: stamp,
+            messages: Vec::new(),
+            authors: HashMap::new(),
+        };
+    }
+}
+
+// Everything needed to import and aggregate the files
+pub struct WatchSettings {
+    pub channel_filter: ChannelFilter,
+    pub date_range: DateRange,
+    // The span given with --last, if any, which starts the
+    // date range that long before each pass
+    pub last: Option<String>,
+    pub bot_filter: BotFilter,
+    pub alias_groups: Vec<Vec<u64>>,
+    pub export_options: ExportOptions,
+    pub interval: Duration,
+}
+
+impl WatchSettings {
+    // The dates to import on this pass. A --last span is
+    // worked out again from now, so the window keeps moving
+    // along for as long as the folder is watched.
+    fn date_range(&self) -> DateRange {
+        let mut date_range = self.date_range;
+
+        if let Some(last) = &self.last {
+            if let Ok(range) = DateRange::last(last, chrono::Local::now().naive_local()) {
+                date_range.start = range.start;
+            }
+        }
+
+        return date_range;
+    }
+}
+
+// Keeps the reports up to date with the source folder,
+// checking it for new or changed exports every interval.
+// Only changed files are imported again, and only the
+// authors who appear in them have their files rewritten
+pub fn watch(source_path: &str, dirs: &ExportDirs, settings: &WatchSettings, reporter: &Reporter) {
+    let mut watched: HashMap<String, WatchedFile> = HashMap::new();
+
+    // Files that have changed, but might still be being
+    // written to by the exporter
+    let mut pending: HashMap<String, (SystemTime, u64)> = HashMap::new();
+
+    // What was exported last time, to spot renames and the
+    // server's top words moving
+    let mut exported_names: HashMap<u64, String> = HashMap::new();
+    let mut server_top_words: Vec<String> = Vec::new();
+
+    let mut first_pass = true;
+
+    loop {
+        let date_range = settings.date_range();
+
+        let discovery = match discover_files(source_path, &settings.channel_filter) {
+            Ok(discovery) => discovery,
+            Err(error) => {
+                reporter.warn(&error.to_string());
+                thread::sleep(settings.interval);
+                continue;
+            }
+        };
+
+        if first_pass {
+            for (file, kept) in &discovery.duplicates {
+                reporter.warn(&format!(
+                    "Skipping {}, as channel {} is already read from {}",
+                    file.path, file.channel.id, kept
+                ));
+            }
+        }
+
+        let mut changed: Vec<SourceFile> = Vec::new();
+
+        for file in &discovery.files {
+            let stamp = match file_stamp(&file.path) {
+                Some(stamp) => stamp,
+                None => continue,
+            };
+
+            if let Some(existing) = watched.get(&file.path) {
+                if existing.stamp == stamp {
+                    pending.remove(&file.path);
+                    continue;
+                }
+            }
+
+            // Wait until a file has stopped changing for a whole
+            // interval before importing it, so half written
+            // exports are never read
+            if first_pass || pending.get(&file.path) == Some(&stamp) {
+                pending.remove(&file.path);
+                changed.push(file.clone());
+            } else {
+                pending.insert(file.path.clone(), stamp);
+            }
+        }
+
+        let current: HashSet<&String> = discovery.files.iter().map(|f| &f.path).collect();
+        let removed: Vec<String> = watched
+            .keys()
+            .filter(|path| !current.contains(path))
+            .cloned()
+            .collect();
+
+        let expired = expired_files(&watched, &changed, date_range);
+
+        if !first_pass && changed.is_empty() && removed.is_empty() && expired.is_empty() {
+            thread::sleep(settings.interval);
+            continue;
+        }
+
+        // Everyone who had messages in a changed or removed file
+        // needs their files written again
+        let mut affected: HashSet<u64> = HashSet::new();
+
+        for path in removed.iter().chain(changed.iter().map(|f| &f.path)) {
+            if let Some(existing) = watched.remove(path) {
+                affected.extend(existing.authors.keys());
+            }
+        }
+
+        let stage = reporter.stage("Importing data", "files", changed.len() as u64);
+        let scraped = scrape_files(&changed, date_range, &settings.bot_filter, &stage);
+        stage.finish();
+
+        let total: usize = scraped.iter().flatten().map(|m| m.len()).sum();
+        let stage = reporter.stage("Processing authors", "messages", total as u64);
+
+        let bot_mode = settings.bot_filter.mode;
+
+        for path in &expired {
+            if let Some(file) = watched.get_mut(path) {
+                affected.extend(file.authors.keys());
+
+                file.messages.retain(|m| date_range.contains(&m.date));
+                file.authors = match create_authors(&file.messages, bot_mode, stage.clone()) {
+                    Ok(authors) => authors,
+                    Err(error) => {
+                        reporter.warn(&error.to_string());
+                        *file = WatchedFile::empty(file.stamp);
+                        continue;
+                    }
+                };
+            }
+        }
+
+        let mut threads = Vec::new();
+
+        for (file, result) in changed.iter().zip(scraped) {
+            let stamp = match file_stamp(&file.path) {
+                Some(stamp) => stamp,
+                None => continue,
+            };
+
+            // Keep a file that can't be read as empty, so it is
+            // only tried again once it changes
+            let messages = match result {
+                Ok(messages) => messages,
+                Err(error) => {
+                    reporter.warn(&error.to_string());
+                    Vec::new()
+                }
+            };
+
+            let stage = stage.clone();
+
+            let thread = thread::spawn(move || {
+                let authors = create_authors(&messages, bot_mode, stage);
+                (messages, authors)
+            });
+
+            threads.push((file.path.clone(), stamp, thread));
+        }
+
+        for (path, stamp, thread) in threads {
+            let file = match thread.join() {
+                Ok((messages, Ok(authors))) => WatchedFile {
+                    stamp: stamp,
+                    messages: messages,
+                    authors: authors,
+                },
+                Ok((_, Err(error))) => {
+                    reporter.warn(&error.to_string());
+                    WatchedFile::empty(stamp)
+                }
+                Err(_) => {
+                    reporter.warn(&format!("Counting up the authors of {} stopped unexpectedly", path));
+                    WatchedFile::empty(stamp)
+                }
+            };
+
+            affected.extend(file.authors.keys());
+            watched.insert(path, file);
+        }
+
+        stage.finish();
+
+        // Fold the files back together into the same maps a
+        // full run ends up with
+        let stage = reporter.stage("Consolidating authors", "files", watched.len() as u64);
+
+        let mut master_author_map: HashMap<u64, Author> = HashMap::new();
+        for file in watched.values() {
+            merge_author_parts(&mut master_author_map, file.authors.clone());
+            stage.inc(1);
+        }
+
+        // With nothing to import the server still needs an
+        // author to hang its totals off
+        master_author_map.entry(0).or_insert_with(|| Author::new(0));
+
+        // Alt accounts are exported under their main account
+        for group in &settings.alias_groups {
+            if group.iter().any(|id| affected.contains(id)) {
+                affected.insert(group[0]);
+            }
+        }
+
+        let (server_author, master_author_map, bot_author_map) =
+            match consolidate_authors(master_author_map, &settings.alias_groups, bot_mode) {
+                Ok(consolidated) => consolidated,
+                Err(error) => {
+                    reporter.warn(&error.to_string());
+                    thread::sleep(settings.interval);
+                    continue;
+                }
+            };
+
+        stage.finish();
+
+        // Every author's csv leaves out the server's top 50
+        // words, so if those change everyone has to be redone
+        let mut all_words: Vec<(&String, &u128)> = server_author.vocab_dict.iter().collect();
+        all_words.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        let mut top_words: Vec<String> = all_words.iter().take(50).map(|(word, _)| (*word).clone()).collect();
+
+        // Only which words are in the top 50 matters, not
+        // their order
+        top_words.sort();
+
+        let export_everyone = first_pass || top_words != server_top_words;
+        server_top_words = top_words;
+
+        // Clear out the files of anyone who is gone or has
+        // been exported under another name
+        for (id, name) in &exported_names {
+            let still_exported = match master_author_map.get(id) {
+                Some(author) => author.display_name() == name,
+                None => false,
+            };
+
+            if !still_exported {
+                remove_author_files(&dirs.authors, &dirs.graphs, name);
+                affected.insert(*id);
+            }
+        }
+
+        exported_names = master_author_map
+            .iter()
+            .map(|(id, author)| (*id, author.display_name().to_string()))
+            .collect();
+
+        let to_export = if export_everyone {
+            master_author_map.len()
+        } else {
+            master_author_map.keys().filter(|id| affected.contains(id)).count()
+        };
+
+        let stage = reporter.stage("Exporting stats", "authors", to_export as u64);
+
+        let author_dir_created = fs::create_dir_all(&dirs.authors);
+        let graph_dir_created = fs::create_dir_all(&dirs.graphs);
+
+        if let Err(error) = author_dir_created.and(graph_dir_created) {
+            stage.warn(&format!("Could not create the export directory! {}", error));
+        } else {
+            if let Err(error) = write_all_messages(&dirs.main, &discovery.files, &watched) {
+                stage.warn(&format!("Could not write All_Messages.txt! {}", error));
+            }
+
+            let mut channel_id_dict: HashMap<u64, Channel> = HashMap::new();
+            for file in &discovery.files {
+                channel_id_dict.insert(file.channel.id, file.channel.clone());
+            }
+
+            // Only gather up every message when a format
+            // writes them all out
+            let mut all_messages: Vec<Message> = Vec::new();
+            if settings.export_options.formats.iter().any(|format| format.needs_messages()) {
+                for file in &discovery.files {
+                    if let Some(watched_file) = watched.get(&file.path) {
+                        all_messages.extend(watched_file.messages.iter().cloned());
+                    }
+                }
+            }
+
+            let stats_exported = export_formats(
+                &settings.export_options,
+                Some(&all_messages),
+                dirs,
+                Statistics {
+                    server: server_author,
+                    authors: master_author_map,
+                    bots: bot_author_map,
+                    channels: channel_id_dict,
+                    excluded_channels: discovery.excluded.clone(),
+                    date_range: date_range,
+                },
+                if export_everyone { None } else { Some(&affected) },
+                &stage,
+            );
+
+            if let Err(error) = stats_exported {
+                stage.warn(&format!("Could not export statistics! {}", error));
+            }
+        }
+
+        stage.finish();
+
+        reporter.info(&format!(
+            "Statistics updated: {} files imported, {} removed, {} authors rewritten. Watching {} for changes...",
+            changed.len(),
+            removed.len(),
+            to_export,
+            source_path
+        ));
+
+        first_pass = false;
+
+        thread::sleep(settings.interval);
+    }
+}
+
+// Files that haven't changed, but hold messages that have
+// since fallen out of the date range
+fn expired_files(
+    watched: &HashMap<String, WatchedFile>,
+    changed: &[SourceFile],
+    date_range: DateRange,
+) -> Vec<String> {
+    let changed_paths: HashSet<&String> = changed.iter().map(|f| &f.path).collect();
+
+    return watched
+        .iter()
+        .filter(|(path, file)| {
+            !changed_paths.contains(path) && file.messages.iter().any(|m| !date_range.contains(&m.date))
+        })
+        .map(|(path, _)| path.clone())
+        .collect();
+}
+
+// The modification time and size of a file, which between
+// them change whenever the exporter writes to it
+fn file_stamp(path: &str) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+
+    return Some((metadata.modified().ok()?, metadata.len()));
+}
+
+fn write_all_messages(
+    path: &String,
+    files: &[SourceFile],
+    watched: &HashMap<String, WatchedFile>,
+) -> std::io::Result<()> {
+    let mut server_text_file = fs::File::create(format!("{}All_Messages.txt", path))?;
+
+    for file in files {
+        if let Some(watched_file) = watched.get(&file.path) {
+            for msg in &watched_file.messages {
+                server_text_file.write_all(format!("[{}] {}\n", msg.author_name, msg.content).as_bytes())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Local, NaiveDateTime, TimeDelta};
+    use std::path::Path;
+
+    fn settings(last: Option<&str>) -> WatchSettings {
+        return WatchSettings {
+            channel_filter: ChannelFilter::all(),
+            date_range: DateRange::all(),
+            last: last.map(String::from),
+            bot_filter: BotFilter::new(),
+            alias_groups: Vec::new(),
+            export_options: ExportOptions::new(),
+            interval: Duration::from_secs(1),
+        };
+    }
+
+    fn watched_file(dates: &[NaiveDateTime]) -> WatchedFile {
+        let messages = dates
+            .iter()
+            .map(|date| Message {
+                author_id: 222,
+                channel_id: 111,
+                author_name: String::from("alice"),
+                date: *date,
+                content: String::from("hello"),
+                attachments: Vec::new(),
+                reactions: HashMap::new(),
+                is_bot: false,
+            })
+            .collect();
+
+        return WatchedFile {
+            stamp: (SystemTime::UNIX_EPOCH, 0),
+            messages: messages,
+            authors: HashMap::new(),
+        };
+    }
+
+    #[test]
+    fn last_is_worked_out_again_on_every_pass() {
+        let settings = settings(Some("1h"));
+
+        let first = settings.date_range().start.unwrap();
+        thread::sleep(Duration::from_millis(20));
+        let second = settings.date_range().start.unwrap();
+
+        assert!(second > first);
+        assert!(Local::now().naive_local() - second < TimeDelta::try_minutes(61).unwrap());
+    }
+
+    #[test]
+    fn fixed_ranges_stay_put() {
+        let settings = settings(None);
+
+        assert_eq!(settings.date_range().start, None);
+        assert_eq!(settings.date_range().end, None);
+    }
+
+    #[test]
+    fn files_with_messages_older_than_the_window_are_expired() {
+        let now = Local::now().naive_local();
+        let old = now - TimeDelta::try_hours(2).unwrap();
+
+        let mut watched: HashMap<String, WatchedFile> = HashMap::new();
+        watched.insert(String::from("fresh.csv"), watched_file(&[now]));
+        watched.insert(String::from("stale.csv"), watched_file(&[old, now]));
+        watched.insert(String::from("changed.csv"), watched_file(&[old]));
+
+        let changed = vec![SourceFile {
+            path: String::from("changed.csv"),
+            channel: Channel::from_path(Path::new("g - c - changed [111111111111111111].csv")).unwrap(),
+        }];
+
+        let date_range = settings(Some("1h")).date_range();
+
+        assert_eq!(expired_files(&watched, &changed, date_range), vec![String::from("stale.csv")]);
+    }
+}
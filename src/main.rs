@@ -3,14 +3,13 @@ pub mod functions;
 use functions::*;
 
 use num_cpus;
-use regex;
 use std::collections::*;
+use functions::error::StatsError;
 use std::env;
 use std::fs;
-use std::thread;
-use std::time;
 use std::io::Write;
-use chrono::Datelike;
+use std::process;
+use std::thread;
 
 fn main() {
     /*
@@ -21,9 +20,106 @@ fn main() {
     -i                      scrape for attachments
     -p [PATH]               process polls channel when given path
     -t [WORD1, WORD2..]     Process popularity of word[s] over time
+    --start [DATE]          only include messages from this date onwards
+    --end [DATE]            only include messages up to and including this date
+    --last [SPAN]           only include messages from the last span of time (eg. 36h, 90d, 12w, 6mo, 1y)
+    --include-channels [CHANNEL1, CHANNEL2..]   only analyse these channels
+    --exclude-channels [CHANNEL1, CHANNEL2..]   never analyse these channels
+    --bots [include|exclude|separate]           how to treat bots and webhooks (default include)
+    --bot-ids [ID1, ID2..]                      author IDs to always treat as bots
+    --no-bot-heuristics                         only trust the export metadata and --bot-ids
+    --aliases [PATH]        merge alt accounts, given a file with one person per line (MAIN_ID,ALT_ID..)
+    --formats [csv,json,tidy,sqlite,parquet,html,markdown,xlsx,svg,png]  which reports to write (default csv). json writes
+                            Statistics.json, laid out as described in docs/statistics.schema.json.
+                            tidy writes one table per statistic into the Tidy folder, with a header
+                            row and one observation per row, for spreadsheets, pandas or R. sqlite
+                            writes every message into Statistics.sqlite, with views for the rankings.
+                            parquet writes messages.parquet and authors.parquet into the Parquet
+                            folder, for DuckDB or Polars. html writes everything into a single
+                            Report.html that can be viewed offline. markdown writes a summary into
+                            the Summary folder, split into messages short enough to post to Discord.
+                            xlsx writes Statistics.xlsx, with sheets for the authors, each ranking,
+                            the top words and each channel.
+                            svg and png draw the time maps, timeline and channel graph as images
+                            that need no browser, the author time maps into the Graphs folder and
+                            the rest into the main folder
+    --wrapped [YEAR]        write a year in review card for the server and every member into the
+                            "Wrapped YEAR" folder. Only messages from YEAR are counted, so every
+                            other report written on the same run covers just that year too
+    --summary [TERMS]       write the markdown summary, choosing what goes in it, eg.
+                            --summary "sections=posters,channels top=5 compare=march.snapshot"
+                            run with "--summary help" to list every term
+    --templates [html,markdown,PATH..]   write a report from each Tera template into the Reports folder,
+                            either the bundled html and markdown ones or your own, eg.
+                            --templates "markdown,weekly.md.tera"
+                            run with "--templates help" to list the variables and filters
+    -q, --quiet             only report errors and warnings
+    --log-format [human|json]   show progress bars (default), or log one JSON object per line
+    --inspect               list the guilds, channels and files found in the source path, with every
+                            row they hold before any date or bot filters, then stop
+    --repl                  load the messages once and answer queries about them interactively
+    --query [QUERY]         run a single query over the messages and print the result, eg.
+                            --query "channel=general group=month agg=count,words out=months.csv"
+    --serve [PORT]          browse the statistics on a local dashboard at http://127.0.0.1:PORT (default 8080)
+    --watch [SECONDS]       keep the reports up to date, checking the source path for new or changed
+                            exports every so often (default 60 seconds). A --last span moves along
+                            with the time, dropping messages as they fall out of it
+    --save-snapshot [PATH]  save everything counted up to a snapshot file
+    --archive-snapshots [DIR]   save a snapshot for the end of every month into DIR
+    --from-snapshot [PATH]  write the reports from a saved snapshot instead of the exports
+    --diff [OLD] [NEW]      compare two snapshots, printing and saving what changed between them. Both
+                            must have been saved with the same --bots and --aliases
+    --generate [DIR] [TERMS]    write made up exports into DIR for testing, eg.
+                            --generate fake "channels=8 authors=200 messages=50000 formats=csv,json"
+                            run with "--generate DIR help" to list every term
+
+    Dates may be given as a year (2021), a month (2021-03), a day (2021-03-15)
+    or a full datetime (2021-03-15T18:30:00). Either end of the range may be left open.
+
+    Channels may be given by ID, by name, by a glob pattern over names (*-log*)
+    or by category as category:NAME, where NAME may also be a glob pattern.
+
+    Bots are detected from the export metadata (JSON exports only), from --bot-ids,
+    and from the #0000 discriminator used by webhooks. With "separate", bots are kept
+    out of the rankings and server totals and listed on their own instead.
+
+    Exit codes: 0 success, 2 bad arguments, 3 a file or folder could not be read
+    or written, 4 an export file could not be parsed, 5 the messages could not be
+    counted up, 6 the statistics could not be exported, 7 a settings file such as
+    the aliases doesn't make sense.
 
     */
 
+    if let Err(error) = run() {
+        eprintln!("{}", error);
+        process::exit(error.exit_code());
+    }
+}
+
+// Gets the value given after a flag, such as the path
+// after -s
+fn flag_value(arguments: &[String], flag: usize) -> Result<&str, StatsError> {
+    return match arguments.get(flag + 1) {
+        Some(value) => Ok(value.as_str()),
+        None => Err(StatsError::Argument(format!("{} needs a value", arguments[flag]))),
+    };
+}
+
+// The folder a file is in, or the current folder
+fn parent_dir(path: &str) -> &str {
+    return match std::path::Path::new(path).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_str().unwrap_or("."),
+        _ => ".",
+    };
+}
+
+fn create_dir(path: &str) -> Result<(), StatsError> {
+    return fs::create_dir_all(path)
+        .map_err(|error| StatsError::Io(format!("Could not create {}! {}", path, error)));
+}
+
+fn run() -> Result<(), StatsError> {
+
     let arguments: Vec<String> = env::args().collect();
 
     let sep;
@@ -39,8 +135,23 @@ fn main() {
     let mut scrape_attachments = false;
     let mut polls_path: &str = "";
     let mut process_words: Vec<&str>;
-    let mut start_year: i32 = 0;
-    let mut end_year: i32 = 0;
+    let mut date_range = filters::DateRange::all();
+    let mut last_span: Option<String> = None;
+    let mut channel_filter = filters::ChannelFilter::all();
+    let mut bot_filter = filters::BotFilter::new();
+    let mut alias_groups: Vec<Vec<u64>> = Vec::new();
+    let mut log_mode = progress::LogMode::Human;
+    let mut inspect_only = false;
+    let mut start_repl = false;
+    let mut message_query: Option<query::Query> = None;
+    let mut serve_port: Option<u16> = None;
+    let mut watch_interval: Option<u64> = None;
+    let mut snapshot_path: Option<&str> = None;
+    let mut archive_dir: Option<&str> = None;
+    let mut snapshot_source: Option<&str> = None;
+    let mut diff_paths: Option<(&str, &str)> = None;
+    let mut generate: Option<(&str, generate::GeneratorConfig)> = None;
+    let mut export_options = export_stats::ExportOptions::new();
 
     if &arguments.len() == &1 {
         source_path = arguments[0].as_str();
@@ -56,76 +167,336 @@ fn main() {
         let t_flag = arguments.iter().position(|r| r == "-t");
         let start_flag = arguments.iter().position(|r| r == "--start");
         let end_flag = arguments.iter().position(|r| r == "--end");
+        let last_flag = arguments.iter().position(|r| r == "--last");
+        let include_channels_flag = arguments.iter().position(|r| r == "--include-channels");
+        let exclude_channels_flag = arguments.iter().position(|r| r == "--exclude-channels");
+        let bots_flag = arguments.iter().position(|r| r == "--bots");
+        let bot_ids_flag = arguments.iter().position(|r| r == "--bot-ids");
+        let no_bot_heuristics_flag = arguments.iter().position(|r| r == "--no-bot-heuristics");
+        let aliases_flag = arguments.iter().position(|r| r == "--aliases");
+        let quiet_flag = arguments.iter().position(|r| r == "-q" || r == "--quiet");
+        let log_format_flag = arguments.iter().position(|r| r == "--log-format");
+        let inspect_flag = arguments.iter().position(|r| r == "--inspect");
+        let repl_flag = arguments.iter().position(|r| r == "--repl");
+        let query_flag = arguments.iter().position(|r| r == "--query");
+        let serve_flag = arguments.iter().position(|r| r == "--serve");
+        let watch_flag = arguments.iter().position(|r| r == "--watch");
+        let save_snapshot_flag = arguments.iter().position(|r| r == "--save-snapshot");
+        let archive_snapshots_flag = arguments.iter().position(|r| r == "--archive-snapshots");
+        let from_snapshot_flag = arguments.iter().position(|r| r == "--from-snapshot");
+        let diff_flag = arguments.iter().position(|r| r == "--diff");
+        let generate_flag = arguments.iter().position(|r| r == "--generate");
+        let formats_flag = arguments.iter().position(|r| r == "--formats");
+        let summary_flag = arguments.iter().position(|r| r == "--summary");
+        let wrapped_flag = arguments.iter().position(|r| r == "--wrapped");
+        let templates_flag = arguments.iter().position(|r| r == "--templates");
 
         if let Some(value) = s_flag {
-            if value < arguments.len() {
-                source_path = arguments[value + 1].as_str();
-                export_path = source_path.clone();
-            } else {
-                println!("Error in path arguments!");
-                panic!();
-            }
+            source_path = flag_value(&arguments, value)?;
+            export_path = source_path.clone();
         }
         if let Some(value) = e_flag {
-            if value < arguments.len() {
-                export_path = arguments[value + 1].as_str();
-            } else {
-                println!("Error in path arguments!");
-                panic!();
-            }
+            export_path = flag_value(&arguments, value)?;
         }
 
         if let Some(value) = p_flag {
-            if value < arguments.len() {
-                polls_path = arguments[value + 1].as_str();
-            } else {
-                println!("Error in path arguments!");
-                panic!();
-            }
+            polls_path = flag_value(&arguments, value)?;
         }
 
         if let Some(value) = t_flag {
-            if value < arguments.len() {
-                process_words = arguments[value + 1]
-                    .clone()
-                    .split(',')
-                    .collect::<Vec<&str>>();
-            } else {
-                println!("Error in path arguments!");
-                panic!();
-            }
+            process_words = flag_value(&arguments, value)?
+                .split(',')
+                .collect::<Vec<&str>>();
         }
 
-        if let Some(value) = i_flag {
-            if value < arguments.len() {
-                scrape_attachments = true;
-            } else {
-                println!("Error in path arguments!");
-                panic!();
+        if i_flag.is_some() {
+            scrape_attachments = true;
+        }
+
+        if let Some(value) = last_flag {
+            let text = flag_value(&arguments, value)?;
+            let now = chrono::Local::now().naive_local();
+
+            match filters::DateRange::last(text, now) {
+                Ok(range) => {
+                    date_range = range;
+                    last_span = Some(String::from(text));
+                }
+                Err(error) => {
+                    return Err(StatsError::Argument(format!(
+                        "Could not parse date range '{}'! {}",
+                        text, error
+                    )))
+                }
             }
         }
 
         if let Some(value) = start_flag {
-            if value < arguments.len() {
-                start_year = arguments[value + 1]
-                    .clone()
-                    .parse::<i32>()
-                    .unwrap();
-            } else {
-                println!("Error in path arguments!");
-                panic!();
+            let text = flag_value(&arguments, value)?;
+
+            // A start date of its own replaces the one from --last
+            match filters::DateRange::parse_start(text) {
+                Some(start) => {
+                    date_range.start = Some(start);
+                    last_span = None;
+                }
+                None => return Err(StatsError::Argument(format!("Could not parse date '{}'", text))),
             }
         }
 
         if let Some(value) = end_flag {
-            if value < arguments.len() {
-                end_year = arguments[value + 1]
-                    .clone()
-                    .parse::<i32>()
-                    .unwrap();
+            let text = flag_value(&arguments, value)?;
+
+            match filters::DateRange::parse_end(text) {
+                Some(end) => date_range.end = Some(end),
+                None => return Err(StatsError::Argument(format!("Could not parse date '{}'", text))),
+            }
+        }
+
+        if let Some(value) = formats_flag {
+            let text = flag_value(&arguments, value)?;
+
+            match export_stats::OutputFormat::parse_list(text) {
+                Some(formats) => export_options.formats = formats,
+                None => return Err(StatsError::Argument(format!("Could not parse formats '{}'", text))),
+            }
+        }
+
+        if let Some(value) = wrapped_flag {
+            let text = flag_value(&arguments, value)?;
+
+            let year = match text.parse::<i32>() {
+                Ok(year) => year,
+                Err(_) => return Err(StatsError::Argument(format!("Could not parse year '{}'", text))),
+            };
+
+            // The cards are counted up from the whole year
+            // and nothing else
+            if date_range.start.is_some() || date_range.end.is_some() {
+                return Err(StatsError::Argument(String::from(
+                    "--wrapped covers the whole year, so can't be used with --start, --end or --last",
+                )));
+            }
+
+            match (filters::DateRange::parse_start(text), filters::DateRange::parse_end(text)) {
+                (Some(start), Some(end)) => {
+                    date_range.start = Some(start);
+                    date_range.end = Some(end);
+                }
+                _ => return Err(StatsError::Argument(format!("Could not parse year '{}'", text))),
+            }
+
+            export_options.wrapped = Some(year);
+        }
+
+        if let Some(value) = summary_flag {
+            // The terms are optional, as with --generate
+            let terms = match arguments.get(value + 1) {
+                Some(terms) if !terms.starts_with('-') => terms.as_str(),
+                _ => "",
+            };
+
+            if terms == "help" {
+                println!("{}", export_markdown::SUMMARY_HELP);
+                return Ok(());
+            }
+
+            match export_markdown::SummaryConfig::parse(terms) {
+                Ok(config) => export_options.summary = config,
+                Err(error) => {
+                    return Err(StatsError::Argument(format!(
+                        "Could not parse summary terms! {}\n\n{}",
+                        error,
+                        export_markdown::SUMMARY_HELP
+                    )))
+                }
+            }
+
+            if !export_options.formats.contains(&export_stats::OutputFormat::Markdown) {
+                export_options.formats.push(export_stats::OutputFormat::Markdown);
+            }
+        }
+
+        if let Some(value) = templates_flag {
+            let text = flag_value(&arguments, value)?;
+
+            if text == "help" {
+                println!("{}", export_template::TEMPLATES_HELP);
+                return Ok(());
+            }
+
+            match export_template::ReportTemplate::parse_list(text) {
+                Ok(templates) => export_options.templates = templates,
+                Err(error) => {
+                    return Err(StatsError::Argument(format!(
+                        "Could not load templates! {}\n\nRun with \"--templates help\" to see how they are written",
+                        error
+                    )))
+                }
+            }
+        }
+
+        if let Some(value) = include_channels_flag {
+            let text = flag_value(&arguments, value)?;
+
+            match filters::ChannelFilter::parse_list(text) {
+                Some(patterns) => channel_filter.include = patterns,
+                None => return Err(StatsError::Argument(format!("Could not parse channels '{}'", text))),
+            }
+        }
+
+        if let Some(value) = exclude_channels_flag {
+            let text = flag_value(&arguments, value)?;
+
+            match filters::ChannelFilter::parse_list(text) {
+                Some(patterns) => channel_filter.exclude = patterns,
+                None => return Err(StatsError::Argument(format!("Could not parse channels '{}'", text))),
+            }
+        }
+
+        if let Some(value) = bots_flag {
+            let text = flag_value(&arguments, value)?;
+
+            match filters::BotMode::parse(text) {
+                Some(mode) => bot_filter.mode = mode,
+                None => return Err(StatsError::Argument(format!("Could not parse bot mode '{}'", text))),
+            }
+        }
+
+        if let Some(value) = bot_ids_flag {
+            let text = flag_value(&arguments, value)?;
+
+            match filters::BotFilter::parse_ids(text) {
+                Some(ids) => bot_filter.ids = ids,
+                None => return Err(StatsError::Argument(format!("Could not parse bot IDs '{}'", text))),
+            }
+        }
+
+        if no_bot_heuristics_flag.is_some() {
+            bot_filter.heuristics = false;
+        }
+
+        if let Some(value) = aliases_flag {
+            alias_groups = merge_aliases::load_aliases(flag_value(&arguments, value)?)?;
+        }
+
+        if let Some(value) = log_format_flag {
+            let text = flag_value(&arguments, value)?;
+
+            match progress::LogMode::parse(text) {
+                Some(mode) => log_mode = mode,
+                None => return Err(StatsError::Argument(format!("Could not parse log format '{}'", text))),
+            }
+        }
+
+        if quiet_flag.is_some() {
+            log_mode = progress::LogMode::Quiet;
+        }
+
+        if inspect_flag.is_some() {
+            inspect_only = true;
+        }
+
+        if repl_flag.is_some() {
+            start_repl = true;
+        }
+
+        if let Some(value) = query_flag {
+            match query::Query::parse(flag_value(&arguments, value)?) {
+                Ok(parsed) => message_query = Some(parsed),
+                Err(error) => return Err(StatsError::Argument(format!("Could not parse query! {}", error))),
+            }
+        }
+
+        if let Some(value) = serve_flag {
+            // The port is optional, so only take the next
+            // argument if it isn't another flag
+            if value + 1 < arguments.len() && !arguments[value + 1].starts_with('-') {
+                match arguments[value + 1].parse::<u16>() {
+                    Ok(port) => serve_port = Some(port),
+                    Err(_) => {
+                        return Err(StatsError::Argument(format!(
+                            "\"{}\" is not a port",
+                            arguments[value + 1]
+                        )))
+                    }
+                }
+            } else {
+                serve_port = Some(8080);
+            }
+        }
+
+        if let Some(value) = watch_flag {
+            if value + 1 < arguments.len() && !arguments[value + 1].starts_with('-') {
+                match arguments[value + 1].parse::<u64>() {
+                    Ok(seconds) if seconds > 0 => watch_interval = Some(seconds),
+                    _ => {
+                        return Err(StatsError::Argument(format!(
+                            "\"{}\" is not a number of seconds",
+                            arguments[value + 1]
+                        )))
+                    }
+                }
             } else {
-                println!("Error in path arguments!");
-                panic!();
+                watch_interval = Some(60);
+            }
+        }
+
+        if let Some(value) = save_snapshot_flag {
+            snapshot_path = Some(flag_value(&arguments, value)?);
+        }
+
+        if let Some(value) = archive_snapshots_flag {
+            archive_dir = Some(flag_value(&arguments, value)?);
+        }
+
+        if let Some(value) = from_snapshot_flag {
+            snapshot_source = Some(flag_value(&arguments, value)?);
+
+            // Without an export path, write the reports next
+            // to the snapshot
+            if e_flag.is_none() && s_flag.is_none() {
+                export_path = parent_dir(snapshot_source.unwrap());
+            }
+        }
+
+        if let Some(value) = diff_flag {
+            let old_path = flag_value(&arguments, value)?;
+            let new_path = flag_value(&arguments, value + 1)?;
+
+            diff_paths = Some((old_path, new_path));
+
+            // Without an export path, write the report next to
+            // the later snapshot
+            if e_flag.is_none() && s_flag.is_none() {
+                export_path = parent_dir(new_path);
+            }
+        }
+
+        if let Some(value) = generate_flag {
+            let dir = flag_value(&arguments, value)?;
+
+            // The terms are optional, so only take the next
+            // argument if it isn't another flag
+            let terms = match arguments.get(value + 2) {
+                Some(terms) if !terms.starts_with('-') => terms.as_str(),
+                _ => "",
+            };
+
+            if terms == "help" {
+                println!("{}", generate::GENERATE_HELP);
+                return Ok(());
+            }
+
+            match generate::GeneratorConfig::parse(terms) {
+                Ok(config) => generate = Some((dir, config)),
+                Err(error) => {
+                    return Err(StatsError::Argument(format!(
+                        "Could not parse generator terms! {}\n\n{}",
+                        error,
+                        generate::GENERATE_HELP
+                    )))
+                }
             }
         }
     }
@@ -134,224 +505,349 @@ fn main() {
     // we can start to use parallel processing to import
     // and process all of the data
 
-    eprint!("\nImporting data... ");
-    let start = time::Instant::now();
+    let reporter = progress::Reporter::new(log_mode);
 
-    // First, we get all paths
-    let paths = fs::read_dir(source_path).unwrap();
+    // Work out where everything will be exported to
+    let export_main_dir = format!("{}{}{}{}", export_path, sep, "Discord Stats", sep);
+    let authors_dir = format!("{}{}{}", export_main_dir, "Authors", sep);
+    let graphs_dir = format!("{}{}{}", export_main_dir, "Graphs", sep);
+    let dirs = export_stats::ExportDirs {
+        main: export_main_dir,
+        authors: authors_dir,
+        graphs: graphs_dir,
+    };
+
+    // Generated exports are written and left for another
+    // run to pick up
+    if let Some((dir, config)) = generate {
+        let paths = generate::generate_exports(dir, &config)?;
+
+        reporter.info(&format!(
+            "{} messages from {} authors written to {} files in {}",
+            config.messages,
+            config.authors + config.bots,
+            paths.len(),
+            dir
+        ));
+        return Ok(());
+    }
 
-    // Setup a dictionary to store the ID of each channel
-    // and link it to the path and name
-    let mut channel_id_dict: HashMap<u64, String> = HashMap::new();
+    // Comparing snapshots doesn't need the exports at all
+    if let Some((old_path, new_path)) = diff_paths {
+        let old_snapshot = snapshot::Snapshot::load(old_path)?;
+        let new_snapshot = snapshot::Snapshot::load(new_path)?;
 
-    // Setup where the threads will send their data
-    let mut message_parts: Vec<structs::Message> = Vec::new();
-    let mut threads = Vec::new();
-
-    // Go over every path, making sure that it first
-    // has an extension, and then seeing if it's a
-    // csv file. If so, create a thread to scrape it
-    for path in paths {
-        let path = path.unwrap();
-
-        if let Some(value) = path.path().extension() {
-            if value == "csv" {
-                let string_path = String::from(path.path().to_str().unwrap());
-
-                // First, get the channel ID from the file name
-                let channel_id_regex = regex::Regex::new(r#"(\[[0-9]{18}\])"#).unwrap();
-
-                let channel_id = channel_id_regex.captures(&string_path);
-
-                if channel_id.is_some() {
-                    let channel_id: u64 = channel_id
-                        .unwrap()
-                        .get(0)
-                        .unwrap()
-                        .as_str()
-                        .replace("[", "")
-                        .replace("]", "")
-                        .parse::<u64>()
-                        .unwrap();
-
-                    let channel_path_words: String = string_path
-                        .replace(" ", "")
-                        .split("-")
-                        .enumerate()
-                        .filter(|&(i, _)| i >= 2)
-                        .map(|(_, e)| format!("{}-", e))
-                        .collect();
-
-                    let channel_name: String = channel_path_words
-                        .split("[")
-                        .enumerate()
-                        .filter(|&(i, _)| i < 1)
-                        .map(|(_, e)| e)
-                        .collect();
-
-                    channel_id_dict.insert(channel_id.clone(), channel_name);
-
-                    threads.push(thread::spawn(move || {
-                        scrape_file::scrape_file(string_path, channel_id)
-                    }));
-                }
+        for warning in diff::check_comparable(&old_snapshot, &new_snapshot)? {
+            reporter.warn(&warning);
+        }
+
+        let diff_dir = format!("{}{}{}", dirs.main, "Snapshot Diff", sep);
+        create_dir(&diff_dir)?;
+
+        for (title, table) in diff::diff_snapshots(&old_snapshot, &new_snapshot) {
+            println!("{}:", title);
+            table.print();
+            println!();
+
+            let path = format!("{}{}.csv", diff_dir, title);
+            if let Err(error) = table.write_csv(&path) {
+                return Err(StatsError::Export(format!("Could not write {}! {}", path, error)));
             }
         }
+
+        reporter.info(&format!("Differences written to {}", diff_dir));
+        return Ok(());
     }
 
-    // Join all the threads together, appending the vecs
-    // together into one large one that we can later
-    // process into authors
-    for thread in threads {
-        message_parts.append(&mut thread.join().unwrap());
+    // A snapshot has already been counted up, so the reports
+    // can be written straight from it
+    if let Some(path) = snapshot_source {
+        let snapshot = snapshot::Snapshot::load(path)?;
+
+        // A snapshot can't be cut down to a year afterwards, so
+        // it has to have been saved with the same --wrapped
+        if export_options.wrapped.is_some()
+            && (snapshot.date_range.start != date_range.start || snapshot.date_range.end != date_range.end)
+        {
+            return Err(StatsError::Argument(format!(
+                "{} wasn't saved for the year given to --wrapped",
+                path
+            )));
+        }
+
+        let stage = reporter.stage("Exporting stats", "authors", snapshot.authors.len() as u64);
+
+        create_dir(&dirs.authors)?;
+        create_dir(&dirs.graphs)?;
+
+        export_stats::export_formats(
+            &export_options,
+            None,
+            &dirs,
+            export_stats::Statistics {
+                server: snapshot.server,
+                authors: snapshot.authors,
+                bots: snapshot.bots,
+                channels: snapshot.channel_info,
+                excluded_channels: snapshot.excluded_channels,
+                date_range: snapshot.date_range,
+            },
+            None,
+            &stage,
+        )?;
+
+        stage.finish();
+        reporter.info(&format!("Statistics written to {} from {}", dirs.main, path));
+        reporter.finish();
+        return Ok(());
     }
 
-    eprint!("Done! in {} ms\n", start.elapsed().as_millis());
+    // First, we find all of the export files
+    let discovery = scrape_file::discover_files(source_path, &channel_filter)?;
 
-    // Now that we have imported all of the data, we can
-    // evenly distribute the load among all threads
-    eprint!("\nProcessing Authors... ");
-    let start = time::Instant::now();
+    for (file, kept) in &discovery.duplicates {
+        reporter.warn(&format!(
+            "Skipping {}, as channel {} is already read from {}",
+            file.path, file.channel.id, kept
+        ));
+    }
 
-    let num_of_threads = num_cpus::get();
+    // When only inspecting, report on what was found
+    // and stop before anything is aggregated
+    if inspect_only {
+        inspect::inspect(source_path, &discovery, &reporter);
+        return Ok(());
+    }
+
+    // Watching imports and exports over and over on its own,
+    // only redoing what changed each time
+    if let Some(seconds) = watch_interval {
+        let settings = watch::WatchSettings {
+            channel_filter: channel_filter,
+            date_range: date_range,
+            last: last_span,
+            bot_filter: bot_filter,
+            alias_groups: alias_groups,
+            export_options: export_options,
+            interval: std::time::Duration::from_secs(seconds),
+        };
+
+        watch::watch(source_path, &dirs, &settings, &reporter);
+        return Ok(());
+    }
 
-    // Setup where the threads will send their data
-    let mut thread_workloads: Vec<Vec<structs::Message>> = Vec::with_capacity(num_of_threads);
+    // Setup a dictionary to store the ID of each channel
+    // and link it to its name, category and guild
+    let mut channel_id_dict: HashMap<u64, structs::Channel> = HashMap::new();
 
-    for _ in 0..num_of_threads {
-        thread_workloads.push(Vec::new());
+    for file in &discovery.files {
+        channel_id_dict.insert(file.channel.id, file.channel.clone());
     }
 
-    let mut threads = Vec::new();
-    let mut author_parts: Vec<HashMap<u64, structs::Author>> = Vec::new();
+    // Keep track of the channels the filters dropped, so the
+    // report can show exactly what was left out
+    let excluded_channels: Vec<structs::Channel> = discovery.excluded.clone();
 
-    // Evenly distribute messages between each threadpool
-    // while making sure to not unnecessarily copy data
-    let all_messages: Vec<structs::Message>;
+    let stage = reporter.stage("Importing data", "files", discovery.files.len() as u64);
 
-    if (start_year == 0) && (end_year == 0) {
-        all_messages = message_parts.clone();
-    }  else {
-        all_messages = message_parts
-            .iter()
-            .filter(|m| {
-                let date = m.date;
+    // Scrape every file, appending the vecs together
+    // into one large one that we can later process
+    // into authors
+    let mut message_parts: Vec<structs::Message> = Vec::new();
+
+    for messages in scrape_file::scrape_files(&discovery.files, date_range, &bot_filter, &stage) {
+        message_parts.append(&mut messages?);
+    }
 
-                let year = date.year();
+    stage.finish();
 
-                year >= start_year && year <= end_year
-            }).cloned().collect();
+    // The interactive shell works straight from the
+    // messages, so nothing else needs to be processed
+    if start_repl {
+        return repl::run_repl(message_parts, channel_id_dict);
     }
 
-    let mut message_parts = all_messages.clone();
+    if let Some(message_query) = message_query {
+        let table = message_query.run(&message_parts, &channel_id_dict);
+        table.print();
 
-    let mut i = 0;
-    while message_parts.len() > 1 {
-        thread_workloads[i].push(message_parts.pop().unwrap());
-        i += 1;
-        if i == num_of_threads {
-            i = 0;
+        if let Some(path) = &message_query.output_path {
+            if let Err(error) = table.write(path) {
+                return Err(StatsError::Export(format!(
+                    "Could not write query result to {}! {}",
+                    path, error
+                )));
+            }
         }
+        return Ok(());
     }
 
-    // Go over every path, making sure that it first
-    // has an extension, and then seeing if it's a
-    // csv file. If so, create a thread to scrape it
-    for workload in thread_workloads {
-        threads.push(thread::spawn(move || {
-            create_authors::create_authors(workload)
-        }));
-    }
+    // The dashboard counts up whatever dates it is asked
+    // for, so it also works straight from the messages
+    if let Some(port) = serve_port {
+        let mut dashboard = serve::Dashboard::new(message_parts, channel_id_dict, alias_groups, bot_filter.mode);
 
-    // Join all the threads together again, and then we
-    // can wait to merge all of these together
-    for thread in threads {
-        author_parts.push(thread.join().unwrap());
+        serve::serve(&mut dashboard, port, &reporter)?;
+        return Ok(());
     }
 
-    eprint!("Done! in {} ms\n", start.elapsed().as_millis());
+    // Now that we have imported all of the data, we can
+    // evenly distribute the load among all threads
+    let stage = reporter.stage("Processing authors", "messages", message_parts.len() as u64);
+
+    let num_of_threads = num_cpus::get();
+
+    // Messages outside of the date range were already dropped
+    // while importing, so everything here is kept
+    let all_messages: Vec<structs::Message> = message_parts;
+
+    let bot_mode = bot_filter.mode;
+
+    // Evenly distribute the messages between the threads,
+    // each counting up its own share straight from the
+    // imported messages
+    let chunk_size = (all_messages.len() / num_of_threads).max(1);
+
+    let author_parts: Vec<HashMap<u64, structs::Author>> = thread::scope(|scope| {
+        let threads: Vec<_> = all_messages
+            .chunks(chunk_size)
+            .map(|workload| {
+                let stage = stage.clone();
+                scope.spawn(move || create_authors::create_authors(workload, bot_mode, stage))
+            })
+            .collect();
+
+        // Join all the threads together again, and then we
+        // can wait to merge all of these together
+        return threads
+            .into_iter()
+            .map(|thread| match thread.join() {
+                Ok(authors) => authors,
+                Err(_) => Err(StatsError::Aggregate(String::from(
+                    "A thread counting up the authors stopped unexpectedly",
+                ))),
+            })
+            .collect::<Result<Vec<HashMap<u64, structs::Author>>, StatsError>>();
+    })?;
+
+    stage.finish();
 
     // Now that we have all of the authors in parts, we
     // can consolidate them all into a single HashMap
-    eprint!("\nConsolidating Authors... ");
-    let start = time::Instant::now();
+    let stage = reporter.stage("Consolidating authors", "parts", author_parts.len() as u64);
 
     let mut master_author_map: HashMap<u64, structs::Author> = HashMap::new();
     for part in author_parts {
-        for (key, value) in part {
-            let mut temp_author: structs::Author;
-
-            if master_author_map.contains_key(&key) {
-                // If the author is already in the master map,
-                // get that value and merge it with the other
-                // part of the author
-                temp_author = master_author_map.get(&key).unwrap().clone();
-                temp_author = temp_author.merge(value);
-            } else {
-                // If the author isn't in the master map,
-                // we can simply insert it.
-                temp_author = value;
-            }
-            // Insert it into the map
-            master_author_map.insert(key, temp_author);
-        }
+        create_authors::merge_author_parts(&mut master_author_map, part);
+        stage.inc(1);
     }
 
-    // Take out the server author as we don't want to export
-    // it along with all of the real authors
-    let server_author = master_author_map.remove(&0).unwrap();
+    // Fold any alt accounts into the main account of each
+    // person before anything gets ranked, then take out the
+    // server author as we don't want to export it along with
+    // all of the real authors. When bots are listed separately,
+    // they are pulled out too so they don't show up in any of
+    // the rankings
+    let (server_author, master_author_map, bot_author_map) =
+        create_authors::consolidate_authors(master_author_map, &alias_groups, bot_filter.mode)?;
+
+    stage.finish();
+
+    // Archive a snapshot for the end of every month before
+    // anything is exported
+    if let Some(dir) = archive_dir {
+        let stage = reporter.stage("Archiving snapshots", "messages", all_messages.len() as u64);
+
+        let paths = snapshot::archive_monthly(
+            dir,
+            &all_messages,
+            &channel_id_dict,
+            &excluded_channels,
+            &snapshot::ArchiveSettings {
+                date_range: date_range,
+                bot_mode: bot_filter.mode,
+                alias_groups: alias_groups.clone(),
+            },
+            &stage,
+        )?;
+
+        stage.finish();
+        reporter.info(&format!("{} monthly snapshots written to {}", paths.len(), dir));
+    }
 
-    eprint!("Done! in {} ms\n", start.elapsed().as_millis());
+    // Save everything that was counted up, so the reports
+    // can be made again later without the exports
+    let (server_author, master_author_map, bot_author_map) = match snapshot_path {
+        Some(path) => {
+            let snapshot = snapshot::Snapshot {
+                version: snapshot::SNAPSHOT_VERSION,
+                created: chrono::Local::now().naive_local(),
+                date_range: date_range,
+                bot_mode: bot_filter.mode,
+                alias_groups: Some(alias_groups.clone()),
+                server: server_author,
+                authors: master_author_map,
+                bots: bot_author_map,
+                channels: create_authors::create_channels(&all_messages, bot_filter.mode),
+                channel_info: channel_id_dict.clone(),
+                excluded_channels: excluded_channels.clone(),
+            };
+
+            snapshot.save(path)?;
+            reporter.info(&format!("Snapshot saved to {}", path));
+
+            (snapshot.server, snapshot.authors, snapshot.bots)
+        }
+        None => (server_author, master_author_map, bot_author_map),
+    };
     // We are basically done now! Just need to
     // export everything as csv documents,
     // and then generate cool graphs!
-    eprint!("\nExporting Stats...\n");
-    let start = time::Instant::now();
+    let stage = reporter.stage("Exporting stats", "authors", master_author_map.len() as u64);
 
     // First, create the export directory
-    let export_main_dir = format!("{}{}{}{}", export_path, sep, "Discord Stats", sep);
-    let authors_dir = format!("{}{}{}", export_main_dir, "Authors", sep);
-    let graphs_dir = format!("{}{}{}", export_main_dir, "Graphs", sep);
-
     // One function will create the base directory and the inner Author dir
-    let author_dir_created = fs::create_dir_all(&authors_dir);
+    create_dir(&dirs.authors)?;
 
     // Next, we can create the graph dir also
-    let graph_dir_created = fs::create_dir_all(&graphs_dir);
+    create_dir(&dirs.graphs)?;
 
     // Create the text file of all message for use as a simple dataset
-    let mut server_text_file = fs::File::create(format!("{}All_Messages.txt", &export_main_dir)).unwrap();
-    
-    for msg in all_messages {
-        server_text_file.write_all(format!("[{}] {}\n", msg.author_name, msg.content).as_bytes());
-    }
-
-    if author_dir_created.is_ok() && graph_dir_created.is_ok() {
-        // Export all of the csv and graph files
-        let stats_exported = export_stats::export_all(
-            &export_main_dir,
-            &authors_dir,
-            &graphs_dir,
-            server_author,
-            master_author_map,
-            channel_id_dict,
-        );
-
-        if stats_exported.is_err() {
-            panic!(
-                "ERROR: Could not export csv files! {}",
-                stats_exported.unwrap_err()
-            );
+    let all_messages_path = format!("{}All_Messages.txt", &dirs.main);
+    let mut server_text_file = match fs::File::create(&all_messages_path) {
+        Ok(file) => file,
+        Err(error) => return Err(StatsError::Io(format!("Could not create {}! {}", all_messages_path, error))),
+    };
+
+    for msg in &all_messages {
+        if let Err(error) = server_text_file.write_all(format!("[{}] {}\n", msg.author_name, msg.content).as_bytes()) {
+            return Err(StatsError::Io(format!("Could not write {}! {}", all_messages_path, error)));
         }
-    } else {
-        panic!(
-            "ERROR: Could not create the export directory! \n{}\n{}",
-            author_dir_created.unwrap_err(),
-            graph_dir_created.unwrap_err()
-        );
     }
 
-    eprint!("Done! in {} ms\n", start.elapsed().as_millis());
+    // Export all of the csv and graph files,
+    // and whichever other formats were asked for
+    export_stats::export_formats(
+        &export_options,
+        Some(&all_messages),
+        &dirs,
+        export_stats::Statistics {
+            server: server_author,
+            authors: master_author_map,
+            bots: bot_author_map,
+            channels: channel_id_dict,
+            excluded_channels: excluded_channels,
+            date_range: date_range,
+        },
+        None,
+        &stage,
+    )?;
+
+    stage.finish();
+    reporter.info(&format!("Statistics written to {}", dirs.main));
+    reporter.finish();
 
     // Now onto the conditionals
+
+    Ok(())
 }